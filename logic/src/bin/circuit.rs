@@ -0,0 +1,366 @@
+//! Interactive shell to query and edit logic circuits.
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use logic::{CachedCircuit, Circuit, Connection, LogicGate};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+#[derive(Debug, Parser)]
+#[clap(author, version)]
+struct Args {
+    /// Circuit files to load, later files override the connections of earlier ones
+    files: Vec<PathBuf>,
+}
+
+const HELP: &str = "\
+get <cable>           print the signal of a cable
+set <cable> <expr>    drive a cable with a value or a gate, eg. `set b 3` or `set b x AND y`
+trace <cable>         print the evaluation tree of a cable with intermediate signals
+deps <cable>          print the cables a cable reads from and the cables reading from it
+watch <cable>         print the signal of a cable whenever a command changes it
+unwatch <cable>       stop watching a cable
+load <file>           load connections from a file, overriding existing ones
+save <file>           write the circuit to a file
+help                  print this message
+quit                  exit (or Ctrl-D)";
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let mut repl = Repl::default();
+    for file in &args.files {
+        repl.load(file)?;
+    }
+    let mut editor = DefaultEditor::new()?;
+    println!("Type `help` for the list of commands");
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        let result = Command::parse(line).and_then(|command| repl.run(command, &mut io::stdout()));
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => eprintln!("error: {error:#}"),
+        }
+    }
+    Ok(())
+}
+
+/// A line typed in the shell.
+#[derive(Debug)]
+enum Command<'a> {
+    Quit,
+    Help,
+    Get(&'a str),
+    Set(&'a str, LogicGate),
+    Trace(&'a str),
+    Deps(&'a str),
+    Watch(&'a str),
+    Unwatch(&'a str),
+    Load(&'a Path),
+    Save(&'a Path),
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Result<Self> {
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        Ok(match (command, argument) {
+            ("quit" | "exit", _) => Command::Quit,
+            ("help", _) => Command::Help,
+            ("get", cable) if !cable.is_empty() => Command::Get(cable),
+            ("set", argument) if argument.contains(char::is_whitespace) => {
+                let (cable, expression) = argument.split_once(char::is_whitespace).unwrap();
+                let gate = expression
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("parsing {expression:?}"))?;
+                Command::Set(cable, gate)
+            }
+            ("trace", cable) if !cable.is_empty() => Command::Trace(cable),
+            ("deps", cable) if !cable.is_empty() => Command::Deps(cable),
+            ("watch", cable) if !cable.is_empty() => Command::Watch(cable),
+            ("unwatch", cable) if !cable.is_empty() => Command::Unwatch(cable),
+            ("load", file) if !file.is_empty() => Command::Load(file.as_ref()),
+            ("save", file) if !file.is_empty() => Command::Save(file.as_ref()),
+            ("get" | "set" | "trace" | "deps" | "watch" | "unwatch" | "load" | "save", _) => {
+                bail!("missing argument, type `help` for usage")
+            }
+            (command, _) => bail!("unknown command {command:?}, type `help` for the list"),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Repl {
+    circuit: CachedCircuit,
+    /// Watched cables with the signal they had after the last command.
+    watched: Vec<(String, Option<u16>)>,
+}
+
+impl Repl {
+    /// Runs a single command, returns `false` when the shell should exit.
+    fn run(&mut self, command: Command, out: &mut impl Write) -> Result<bool> {
+        match command {
+            Command::Quit => return Ok(false),
+            Command::Help => writeln!(out, "{HELP}")?,
+            Command::Get(cable) => self.get(cable, out)?,
+            Command::Set(cable, gate) => {
+                self.apply(vec![Connection::new(cable, gate)])?;
+                self.report_watched(out)?;
+            }
+            Command::Trace(cable) => self.trace(cable, "", "", &mut HashSet::new(), out)?,
+            Command::Deps(cable) => self.deps(cable, out)?,
+            Command::Watch(cable) => {
+                let signal = self.circuit.signal(cable);
+                if !self.watched.iter().any(|(watched, _)| watched == cable) {
+                    self.watched.push((cable.to_owned(), signal));
+                }
+                writeln!(out, "{cable} = {}", display(signal))?;
+            }
+            Command::Unwatch(cable) => {
+                let before = self.watched.len();
+                self.watched.retain(|(watched, _)| watched != cable);
+                if self.watched.len() == before {
+                    bail!("{cable:?} isn't watched");
+                }
+            }
+            Command::Load(file) => {
+                self.load(file)?;
+                self.report_watched(out)?;
+            }
+            Command::Save(file) => {
+                std::fs::write(file, self.circuit.circuit().to_string())
+                    .with_context(|| format!("writing {}", file.display()))?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn load(&mut self, file: &Path) -> Result<()> {
+        let loaded =
+            Circuit::from_file(file).with_context(|| format!("loading {}", file.display()))?;
+        self.apply(loaded.connections().cloned().collect())
+            .with_context(|| format!("loading {}", file.display()))
+    }
+
+    /// Adds `connections` to the circuit, unless it would create a loop.
+    fn apply(&mut self, connections: Vec<Connection>) -> Result<()> {
+        let mut previous = vec![];
+        for connection in connections {
+            let output = connection.output().to_owned();
+            previous.push((output, self.circuit.replace(connection)));
+        }
+        let Some(cycle) = self.circuit.circuit().find_loop() else {
+            return Ok(());
+        };
+        let cycle = cycle.join(" <- ");
+        for (output, connection) in previous.into_iter().rev() {
+            match connection {
+                Some(connection) => self.circuit.replace(connection),
+                None => self.circuit.remove(&output),
+            };
+        }
+        bail!("refusing to create the loop {cycle}")
+    }
+
+    /// Prints the watched cables whose signal changed since the last command.
+    fn report_watched(&mut self, out: &mut impl Write) -> Result<()> {
+        for (cable, last) in &mut self.watched {
+            let signal = self.circuit.signal(cable);
+            if signal != *last {
+                writeln!(out, "{cable}: {} -> {}", display(*last), display(signal))?;
+                *last = signal;
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, cable: &str, out: &mut impl Write) -> Result<()> {
+        if let Some(signal) = self.circuit.signal(cable) {
+            writeln!(out, "{cable} = {signal}")?;
+            return Ok(());
+        }
+        let circuit = self.circuit.circuit();
+        if circuit.get(cable).is_none() {
+            bail!("no connection drives {cable:?}");
+        }
+        let mut undriven = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = vec![cable];
+        while let Some(cable) = pending.pop() {
+            if !seen.insert(cable) {
+                continue;
+            }
+            match circuit.get(cable) {
+                Some(_) => pending.extend(circuit.inputs_of(cable)),
+                None => undriven.push(cable),
+            }
+        }
+        undriven.sort_unstable();
+        bail!(
+            "{cable:?} depends on undriven cables: {}",
+            undriven.join(", ")
+        )
+    }
+
+    fn trace<'a>(
+        &'a self,
+        cable: &'a str,
+        prefix: &str,
+        branch: &str,
+        seen: &mut HashSet<&'a str>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let signal = display(self.circuit.signal(cable));
+        let Some(connection) = self.circuit.circuit().get(cable) else {
+            return writeln!(out, "{prefix}{branch}{cable} = {signal} (undriven)");
+        };
+        if !seen.insert(cable) {
+            return writeln!(out, "{prefix}{branch}{cable} = {signal} (see above)");
+        }
+        writeln!(
+            out,
+            "{prefix}{branch}{cable} = {signal} <- {}",
+            connection.gate()
+        )?;
+        let prefix = match branch {
+            "├─ " => format!("{prefix}│  "),
+            "└─ " => format!("{prefix}   "),
+            _ => prefix.to_owned(),
+        };
+        let inputs = self.circuit.circuit().inputs_of(cable);
+        for (i, input) in inputs.iter().enumerate() {
+            let branch = if i + 1 == inputs.len() {
+                "└─ "
+            } else {
+                "├─ "
+            };
+            self.trace(input, &prefix, branch, seen, out)?;
+        }
+        Ok(())
+    }
+
+    fn deps(&self, cable: &str, out: &mut impl Write) -> io::Result<()> {
+        let inputs = self.circuit.circuit().inputs_of(cable);
+        let dependents = self.circuit.dependents_of(cable);
+        writeln!(out, "inputs: {}", inputs.join(" "))?;
+        writeln!(out, "dependents: {}", dependents.join(" "))
+    }
+}
+
+/// A signal, or `?` when it can't be computed.
+fn display(signal: Option<u16>) -> String {
+    signal.map_or_else(|| "?".to_owned(), |signal| signal.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `lines` in a fresh shell, returning the output of each.
+    fn session(lines: &[&str]) -> Vec<Result<String>> {
+        let mut repl = Repl::default();
+        lines
+            .iter()
+            .map(|line| {
+                let mut out = vec![];
+                Command::parse(line).and_then(|command| repl.run(command, &mut out))?;
+                Ok(String::from_utf8(out).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_commands_and_their_arguments() {
+        assert!(matches!(Command::parse("get  a "), Ok(Command::Get("a"))));
+        assert!(matches!(
+            Command::parse("set b x AND y"),
+            Ok(Command::Set("b", LogicGate::And(..)))
+        ));
+        assert!(matches!(Command::parse("watch a"), Ok(Command::Watch("a"))));
+        assert!(matches!(Command::parse("exit"), Ok(Command::Quit)));
+        let error = |line| Command::parse(line).unwrap_err().to_string();
+        assert_eq!(error("get"), "missing argument, type `help` for usage");
+        assert_eq!(error("set b"), "missing argument, type `help` for usage");
+        assert_eq!(error("set b x FOO y"), "parsing \"x FOO y\"");
+        assert_eq!(
+            error("frobnicate a"),
+            "unknown command \"frobnicate\", type `help` for the list"
+        );
+    }
+
+    #[test]
+    fn sets_gets_and_traces_cables() {
+        let out = session(&[
+            "set x 3",
+            "set y 5",
+            "set d x AND y",
+            "get d",
+            "trace d",
+            "deps x",
+        ]);
+        let out: Vec<_> = out.into_iter().map(Result::unwrap).collect();
+        assert_eq!(out[3], "d = 1\n");
+        assert_eq!(out[4], "d = 1 <- x AND y\n├─ x = 3 <- 3\n└─ y = 5 <- 5\n");
+        assert_eq!(out[5], "inputs: \ndependents: d\n");
+    }
+
+    #[test]
+    fn reports_errors_without_changing_the_circuit() {
+        let out = session(&["set a NOT b", "get a", "set b a", "get c", "get a"]);
+        assert_eq!(
+            out[1].as_ref().unwrap_err().to_string(),
+            "\"a\" depends on undriven cables: b"
+        );
+        assert_eq!(
+            out[2].as_ref().unwrap_err().to_string(),
+            "refusing to create the loop a <- b"
+        );
+        assert_eq!(
+            out[3].as_ref().unwrap_err().to_string(),
+            "no connection drives \"c\""
+        );
+        assert!(out[4].is_err());
+    }
+
+    #[test]
+    fn watched_cables_are_printed_when_they_change() {
+        let out = session(&[
+            "set x 1",
+            "set d NOT x",
+            "watch d",
+            "set y 2",
+            "set x 0",
+            "unwatch d",
+            "set x 1",
+            "unwatch d",
+        ]);
+        assert_eq!(out[2].as_ref().unwrap(), "d = 65534\n");
+        assert_eq!(out[3].as_ref().unwrap(), "");
+        assert_eq!(out[4].as_ref().unwrap(), "d: 65534 -> 65535\n");
+        assert_eq!(out[6].as_ref().unwrap(), "");
+        assert_eq!(
+            out[7].as_ref().unwrap_err().to_string(),
+            "\"d\" isn't watched"
+        );
+    }
+
+    #[test]
+    fn quit_stops_the_shell() {
+        let mut repl = Repl::default();
+        assert!(!repl.run(Command::Quit, &mut io::sink()).unwrap());
+        assert!(repl.run(Command::Help, &mut io::sink()).unwrap());
+    }
+}
@@ -1,6 +1,4 @@
-use crate::And;
-
-use super::{Assignment, Connection, LogicGate};
+use super::{Assignment, Connection, LogicGate, Operand};
 use std::error;
 use std::fmt;
 use std::num::ParseIntError;
@@ -33,32 +31,52 @@ impl fmt::Display for ConnectionParseError {
 
 impl error::Error for ConnectionParseError {}
 
+impl FromStr for Operand {
+    type Err = ConnectionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(ConnectionParseError::InvalidInput);
+        }
+        Ok(match s.parse::<u16>() {
+            Ok(value) => Operand::Value(value),
+            _ => Operand::Cable(s.to_owned()),
+        })
+    }
+}
+
 impl FromStr for LogicGate {
     type Err = ConnectionParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut s = s.split_whitespace();
-        let gate = match (s.next(), s.next(), s.next()) {
-            (Some(part0), None, None) => match part0.parse::<u16>() {
+        let gate = match (s.next(), s.next(), s.next(), s.next(), s.next()) {
+            (Some(part0), None, None, None, None) => match part0.parse::<u16>() {
                 Ok(value) => LogicGate::Assignment(Assignment::Direct(value)),
                 _ => LogicGate::Assignment(Assignment::Gate(part0.to_string())),
             },
-            (Some(part0), Some(part1), None) if part0 == "NOT" => LogicGate::Not(part1.to_owned()),
-            (Some(part0), Some(part1), Some(part2)) => {
+            (Some("NOT"), Some(part1), None, None, None) => LogicGate::Not(part1.parse()?),
+            (Some("DFF"), Some(part1), None, None, None) => LogicGate::Dff(part1.parse()?),
+            (Some("MUX"), Some(select), Some(a), Some(b), None) => {
+                LogicGate::Mux(select.parse()?, a.parse()?, b.parse()?)
+            }
+            (Some(part0), Some(part1), Some(part2), None, None) => {
                 let (origin, gate, dest) = (part0, part1, part2);
-                let origin = origin.to_owned();
+                let origin = origin.parse()?;
                 match gate {
-                    "AND" => {
-                        if origin == "1" {
-                            LogicGate::And(And::True(dest.to_owned()))
-                        } else {
-                            LogicGate::And(And::And(origin, dest.to_owned()))
-                        }
-                    }
-                    "OR" => LogicGate::Or(origin, dest.to_owned()),
+                    "AND" => LogicGate::And(origin, dest.parse()?),
+                    "OR" => LogicGate::Or(origin, dest.parse()?),
+                    "XOR" => LogicGate::Xor(origin, dest.parse()?),
+                    "NAND" => LogicGate::Nand(origin, dest.parse()?),
+                    "NOR" => LogicGate::Nor(origin, dest.parse()?),
+                    "XNOR" => LogicGate::Xnor(origin, dest.parse()?),
+                    "ADD" => LogicGate::Add(origin, dest.parse()?),
+                    "SUB" => LogicGate::Sub(origin, dest.parse()?),
                     "LSHIFT" => LogicGate::Lshift(origin, dest.parse()?),
                     "RSHIFT" => LogicGate::Rshift(origin, dest.parse()?),
-                    _ => return Err(ConnectionParseError::InvalidFormat),
+                    "LROTATE" => LogicGate::Lrotate(origin, dest.parse()?),
+                    "RROTATE" => LogicGate::Rrotate(origin, dest.parse()?),
+                    _ => return Err(ConnectionParseError::InvalidGate),
                 }
             }
             _ => return Err(ConnectionParseError::InvalidFormat),
@@ -82,3 +100,42 @@ impl FromStr for Connection {
         }
     }
 }
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Value(value) => fmt::Display::fmt(value, f),
+            Operand::Cable(cable) => f.write_str(cable),
+        }
+    }
+}
+
+impl fmt::Display for LogicGate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogicGate::Assignment(Assignment::Direct(value)) => write!(f, "{value}"),
+            LogicGate::Assignment(Assignment::Gate(cable)) => f.write_str(cable),
+            LogicGate::And(left, right) => write!(f, "{left} AND {right}"),
+            LogicGate::Or(left, right) => write!(f, "{left} OR {right}"),
+            LogicGate::Xor(left, right) => write!(f, "{left} XOR {right}"),
+            LogicGate::Nand(left, right) => write!(f, "{left} NAND {right}"),
+            LogicGate::Nor(left, right) => write!(f, "{left} NOR {right}"),
+            LogicGate::Xnor(left, right) => write!(f, "{left} XNOR {right}"),
+            LogicGate::Add(left, right) => write!(f, "{left} ADD {right}"),
+            LogicGate::Sub(left, right) => write!(f, "{left} SUB {right}"),
+            LogicGate::Not(origin) => write!(f, "NOT {origin}"),
+            LogicGate::Lshift(origin, shift) => write!(f, "{origin} LSHIFT {shift}"),
+            LogicGate::Rshift(origin, shift) => write!(f, "{origin} RSHIFT {shift}"),
+            LogicGate::Lrotate(origin, shift) => write!(f, "{origin} LROTATE {shift}"),
+            LogicGate::Rrotate(origin, shift) => write!(f, "{origin} RROTATE {shift}"),
+            LogicGate::Mux(select, a, b) => write!(f, "MUX {select} {a} {b}"),
+            LogicGate::Dff(input) => write!(f, "DFF {input}"),
+        }
+    }
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.gate, self.output)
+    }
+}
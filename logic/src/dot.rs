@@ -0,0 +1,71 @@
+//! Graphviz export of a [`Circuit`].
+use std::fmt;
+
+use crate::signal::{Memoized, Signal};
+use crate::Circuit;
+
+/// Displays a [`Circuit`] as a Graphviz DOT digraph.
+///
+/// Each cable is a node labelled with the gate driving it, with an edge
+/// from every cable it reads from.
+///
+/// ```
+/// # use logic::Circuit;
+/// let circuit = Circuit::from_string("3 -> x\nNOT x -> y").unwrap();
+/// let dot = circuit.dot().with_signals().to_string();
+/// assert!(dot.contains(r#""x" -> "y";"#));
+/// assert!(dot.contains(r#""y" [label="y = 65532\nNOT x"];"#));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Dot<'a> {
+    circuit: &'a Circuit,
+    signals: bool,
+}
+
+impl<'a> Dot<'a> {
+    pub(crate) fn new(circuit: &'a Circuit) -> Self {
+        Self {
+            circuit,
+            signals: false,
+        }
+    }
+
+    /// Annotate each node with the signal evaluated on its cable.
+    pub fn with_signals(self) -> Self {
+        Self {
+            signals: true,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Dot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let memoized = Memoized::new(self.circuit);
+        writeln!(f, "digraph circuit {{")?;
+        for connection in self.circuit.sorted_connections() {
+            let output = escape(&connection.output);
+            let gate = escape(&connection.gate.to_string());
+            let signal = if self.signals {
+                memoized.signal(&connection.output)
+            } else {
+                None
+            };
+            match signal {
+                Some(signal) => writeln!(
+                    f,
+                    "    \"{output}\" [label=\"{output} = {signal}\\n{gate}\"];"
+                )?,
+                None => writeln!(f, "    \"{output}\" [label=\"{output}\\n{gate}\"];")?,
+            }
+            for input in connection.gate.inputs() {
+                writeln!(f, "    \"{}\" -> \"{output}\";", escape(input))?;
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
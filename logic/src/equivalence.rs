@@ -0,0 +1,309 @@
+//! Exhaustive or sampled comparison of circuits over their free inputs.
+use std::fmt;
+
+use crate::signal::{Memoized, Signal};
+use crate::{Circuit, Error};
+
+/// Checks that two circuits agree on some outputs, whatever the signals of
+/// some free input cables.
+///
+/// The free inputs are forced to every combination of values when there are
+/// at most [`EquivalenceChecker::exhaustive_limit`] of them, and to randomly
+/// sampled ones otherwise. Their values can be restricted to their lowest
+/// bits with [`EquivalenceChecker::width`].
+///
+/// ```
+/// # use logic::{Circuit, EquivalenceChecker};
+/// let xor = Circuit::from_string("a XOR b -> out").unwrap();
+/// let gates = Circuit::from_string("a OR b -> o\na NAND b -> n\no AND n -> out").unwrap();
+/// let checker = EquivalenceChecker::new(["a", "b"], ["out"]).width(4);
+/// assert!(checker.is_exhaustive());
+/// assert!(checker.check(&xor, &gates).unwrap().is_none());
+///
+/// let or = Circuit::from_string("a OR b -> out").unwrap();
+/// let counterexample = checker.check(&xor, &or).unwrap().unwrap();
+/// assert_eq!(counterexample.to_string(), "a=1 b=1: out is 0 on the left and 1 on the right");
+/// ```
+#[derive(Debug, Clone)]
+pub struct EquivalenceChecker {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    width: u32,
+    exhaustive_limit: u64,
+    samples: u64,
+    seed: u64,
+}
+
+/// Assignment of the free inputs for which two circuits disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub inputs: Vec<(String, u16)>,
+    /// First output on which the circuits disagree.
+    pub output: String,
+    pub left: Option<u16>,
+    pub right: Option<u16>,
+}
+
+impl EquivalenceChecker {
+    pub fn new<'a, I, O>(inputs: I, outputs: O) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+        O: IntoIterator<Item = &'a str>,
+    {
+        Self {
+            inputs: inputs.into_iter().map(String::from).collect(),
+            outputs: outputs.into_iter().map(String::from).collect(),
+            width: 16,
+            exhaustive_limit: 1 << 24,
+            samples: 1 << 16,
+            seed: 0x5eed,
+        }
+    }
+
+    /// Only the lowest `bits` bits of the free inputs vary, 16 by default.
+    pub fn width(self, bits: u32) -> Self {
+        Self {
+            width: bits.min(16),
+            ..self
+        }
+    }
+
+    /// Maximum number of combinations enumerated before resorting to
+    /// random sampling, 2^24 by default.
+    pub fn exhaustive_limit(self, combinations: u64) -> Self {
+        Self {
+            exhaustive_limit: combinations,
+            ..self
+        }
+    }
+
+    /// Number of random combinations tried when there are too many to
+    /// enumerate, 2^16 by default.
+    pub fn samples(self, samples: u64) -> Self {
+        Self { samples, ..self }
+    }
+
+    /// Seed of the random sampling, for reproducible checks.
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Whether [`EquivalenceChecker::check`] tries every combination of
+    /// inputs, making a `None` result a proof of equivalence.
+    pub fn is_exhaustive(&self) -> bool {
+        combinations(self.inputs.len(), self.width).is_some_and(|c| c <= self.exhaustive_limit)
+    }
+
+    /// First counterexample found, `None` if the circuits agree on every
+    /// combination tried. Fails if either circuit contains a combinational
+    /// loop.
+    pub fn check(&self, left: &Circuit, right: &Circuit) -> Result<Option<Counterexample>, Error> {
+        for circuit in [left, right] {
+            if let Some(cables) = circuit.find_loop() {
+                return Err(Error::Loop(cables.into_iter().map(String::from).collect()));
+            }
+        }
+        let mut values = vec![0; self.inputs.len()];
+        let mask = mask(self.width);
+        let compare = |values: &[u16]| {
+            let inputs = || {
+                self.inputs
+                    .iter()
+                    .map(String::as_str)
+                    .zip(values.iter().copied())
+            };
+            let (left, right) = (
+                Memoized::with_inputs(left, inputs()),
+                Memoized::with_inputs(right, inputs()),
+            );
+            self.outputs.iter().find_map(|output| {
+                let (left, right) = (left.signal(output), right.signal(output));
+                (left != right).then(|| Counterexample {
+                    inputs: inputs()
+                        .map(|(cable, value)| (cable.into(), value))
+                        .collect(),
+                    output: output.clone(),
+                    left,
+                    right,
+                })
+            })
+        };
+        if self.is_exhaustive() {
+            let combinations = combinations(self.inputs.len(), self.width).unwrap_or(1);
+            for combination in 0..combinations {
+                assignment(combination, self.width, &mut values);
+                if let Some(counterexample) = compare(&values) {
+                    return Ok(Some(counterexample));
+                }
+            }
+        } else {
+            let mut state = self.seed;
+            for _ in 0..self.samples {
+                for value in &mut values {
+                    *value = splitmix64(&mut state) as u16 & mask;
+                }
+                if let Some(counterexample) = compare(&values) {
+                    return Ok(Some(counterexample));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (cable, value)) in self.inputs.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{separator}{cable}={value}")?;
+        }
+        let signal =
+            |signal: Option<u16>| signal.map_or_else(|| "no signal".into(), |s| s.to_string());
+        write!(
+            f,
+            ": {} is {} on the left and {} on the right",
+            self.output,
+            signal(self.left),
+            signal(self.right)
+        )
+    }
+}
+
+/// Displays the outputs of a [`Circuit`] for every combination of values
+/// of some free input cables, see [`Circuit::truth_table`].
+///
+/// ```
+/// # use logic::Circuit;
+/// let half_adder = Circuit::from_string("a XOR b -> sum\na AND b -> carry").unwrap();
+/// let table = half_adder.truth_table(["a", "b"], ["sum", "carry"]).width(1);
+/// assert_eq!(
+///     table.to_string(),
+///     "a b | sum carry
+/// 0 0 |   0     0
+/// 0 1 |   1     0
+/// 1 0 |   1     0
+/// 1 1 |   0     1
+/// "
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct TruthTable<'a> {
+    circuit: &'a Circuit,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    width: u32,
+    max_rows: u64,
+}
+
+impl Circuit {
+    /// Truth table of `outputs` over every value of `inputs`, meant for
+    /// small input sets, see [`TruthTable`].
+    pub fn truth_table<'a, I, O>(&self, inputs: I, outputs: O) -> TruthTable<'_>
+    where
+        I: IntoIterator<Item = &'a str>,
+        O: IntoIterator<Item = &'a str>,
+    {
+        TruthTable {
+            circuit: self,
+            inputs: inputs.into_iter().map(String::from).collect(),
+            outputs: outputs.into_iter().map(String::from).collect(),
+            width: 16,
+            max_rows: 1 << 16,
+        }
+    }
+}
+
+impl TruthTable<'_> {
+    /// Only the lowest `bits` bits of the inputs vary, 16 by default.
+    pub fn width(self, bits: u32) -> Self {
+        Self {
+            width: bits.min(16),
+            ..self
+        }
+    }
+
+    /// Maximum number of rows displayed, 2^16 by default. The rows past it
+    /// are left out and counted on a last `...` line.
+    pub fn max_rows(self, rows: u64) -> Self {
+        Self {
+            max_rows: rows,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for TruthTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = mask(self.width).to_string().len();
+        let widths: Vec<_> = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .map(|cable| cable.len().max(digits))
+            .collect();
+        let mut row = |cells: Vec<String>| {
+            for (i, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+                let separator = match i {
+                    0 => "",
+                    i if i == self.inputs.len() => " | ",
+                    _ => " ",
+                };
+                write!(f, "{separator}{cell:>width$}")?;
+            }
+            writeln!(f)
+        };
+        row(self.inputs.iter().chain(&self.outputs).cloned().collect())?;
+        let combinations = combinations(self.inputs.len(), self.width);
+        let mut values = vec![0; self.inputs.len()];
+        for combination in 0..combinations.unwrap_or(u64::MAX).min(self.max_rows) {
+            assignment(combination, self.width, &mut values);
+            let inputs = self
+                .inputs
+                .iter()
+                .map(String::as_str)
+                .zip(values.iter().copied());
+            let state = Memoized::with_inputs(self.circuit, inputs);
+            let outputs = self.outputs.iter().map(|output| {
+                state
+                    .signal(output)
+                    .map_or_else(|| "-".into(), |signal| signal.to_string())
+            });
+            row(values.iter().map(u16::to_string).chain(outputs).collect())?;
+        }
+        match combinations.map(|combinations| combinations.saturating_sub(self.max_rows)) {
+            Some(0) => Ok(()),
+            Some(left_out) => writeln!(f, "... {left_out} more rows"),
+            None => writeln!(f, "... more than {} more rows", u64::MAX - self.max_rows),
+        }
+    }
+}
+
+/// Number of combinations of `inputs` values of `width` bits, `None` on
+/// overflow.
+fn combinations(inputs: usize, width: u32) -> Option<u64> {
+    let bits = u32::try_from(inputs).ok()?.checked_mul(width)?;
+    1u64.checked_shl(bits)
+}
+
+fn mask(width: u32) -> u16 {
+    u16::MAX.checked_shr(16 - width).unwrap_or(0)
+}
+
+/// Decodes `combination` into `values`, the first one being the most
+/// significant.
+fn assignment(combination: u64, width: u32, values: &mut [u16]) {
+    let mut combination = combination;
+    for value in values.iter_mut().rev() {
+        *value = (combination & u64::from(mask(width))) as u16;
+        combination = combination.checked_shr(width).unwrap_or(0);
+    }
+}
+
+/// Tiny PRNG good enough to sample inputs, see <https://prng.di.unimi.it/splitmix64.c>.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -33,24 +33,76 @@
 //! circuit.add_connection(Connection::new("b", LogicGate::Lshift("a".into(), 2))).unwrap();
 //! assert_eq!(circuit.signal("b"), Some(64));
 //! ```
+//!
+//! Besides one connection per line, the text format supports blank lines,
+//! reusable module definitions instantiated with a prefix on each of their
+//! cables, and including other files:
+//!
+//! ```text
+//! include "gates.txt"
+//!
+//! module half_adder {
+//! a XOR b -> sum
+//! a AND b -> carry
+//! }
+//!
+//! instance half_adder ha
+//! x -> ha.a
+//! y -> ha.b
+//! ha.sum -> s
+//! ```
+//!
+//! A [`Circuit`] is displayed in the same line format it is parsed from,
+//! sorted by output cable, and can be exported to Graphviz with [`Circuit::dot`]:
+//!
+//! ```
+//! # use logic::Circuit;
+//! let circuit = Circuit::from_string("x AND y -> d\n123 -> x\n456 -> y").unwrap();
+//! assert_eq!(circuit.to_string(), "x AND y -> d\n123 -> x\n456 -> y\n");
+//! let dot = circuit.dot().with_signals().to_string();
+//! assert!(dot.starts_with("digraph circuit {"));
+//! ```
 mod connection;
+mod dot;
+mod equivalence;
+mod optimize;
+mod parser;
 mod signal;
+mod simulator;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, prelude::*, BufReader};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub use connection::ConnectionParseError;
+pub use dot::Dot;
+pub use equivalence::{Counterexample, EquivalenceChecker, TruthTable};
+pub use simulator::{Simulator, Vcd};
 
 #[derive(Debug, Clone)]
 pub enum LogicGate {
     Assignment(Assignment),
-    And(And),
-    Or(String, String),
-    Not(String),
-    Lshift(String, u16),
-    Rshift(String, u16),
+    And(Operand, Operand),
+    Or(Operand, Operand),
+    Xor(Operand, Operand),
+    Nand(Operand, Operand),
+    Nor(Operand, Operand),
+    Xnor(Operand, Operand),
+    /// Wrapping addition.
+    Add(Operand, Operand),
+    /// Wrapping subtraction.
+    Sub(Operand, Operand),
+    Not(Operand),
+    Lshift(Operand, u16),
+    Rshift(Operand, u16),
+    Lrotate(Operand, u16),
+    Rrotate(Operand, u16),
+    /// 2-input multiplexer: `Mux(select, a, b)` outputs `a` when `select`
+    /// is 0, `b` otherwise.
+    Mux(Operand, Operand, Operand),
+    /// D flip-flop, outputs the value its input had on the previous clock
+    /// tick of a [`Simulator`], and 0 outside of one.
+    Dff(Operand),
 }
 
 #[derive(Debug, Clone)]
@@ -59,10 +111,11 @@ pub enum Assignment {
     Gate(String),
 }
 
-#[derive(Debug, Clone)]
-pub enum And {
-    True(String),
-    And(String, String),
+/// Input of a gate, either a literal signal or the signal of another cable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Value(u16),
+    Cable(String),
 }
 
 #[derive(Debug, Clone)]
@@ -80,6 +133,9 @@ pub struct Circuit {
 /// Cached version of the logic circuit.
 ///
 /// Use if you want better time performance at the cost of memory.
+/// Changing a connection only invalidates the cached signals of the cables
+/// depending on it. The cache is behind a lock so the circuit can be shared
+/// between threads.
 ///
 /// Can be built from a [`Circuit`]:
 /// ```
@@ -89,17 +145,40 @@ pub struct Circuit {
 #[derive(Debug, Default)]
 pub struct CachedCircuit {
     circuit: Circuit,
-    cache: RefCell<HashMap<String, u16>>,
+    cache: RwLock<HashMap<String, u16>>,
+    /// Cables reading from each cable.
+    dependents: HashMap<String, HashSet<String>>,
 }
 
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
     Parse(ConnectionParseError),
+    /// Combinational loop, see [`Circuit::find_loop`].
+    Loop(Vec<String>),
+    /// Instantiation of a module which isn't defined.
+    UnknownModule(String),
+    /// Module definition missing its closing `}`.
+    UnclosedModule(String),
+    /// Closing `}` outside of a module definition.
+    UnexpectedModuleEnd,
+    /// Module definition inside another one.
+    NestedModule(String),
+    /// File including itself, directly or not.
+    IncludeLoop(std::path::PathBuf),
+    /// Error on a given line of a circuit description.
+    At {
+        /// `None` when not parsing from [`Circuit::from_file`].
+        file: Option<std::path::PathBuf>,
+        line: usize,
+        error: Box<Error>,
+    },
 }
 
 impl Circuit {
     /// Attempts to build a `Circuit` from any kind of well-structured `Reader`.
+    ///
+    /// Included files are resolved relative to the current directory.
     pub fn from_read<R>(reader: R) -> Result<Self, Error>
     where
         R: Read,
@@ -108,24 +187,26 @@ impl Circuit {
     }
 
     /// Attempts to build a `Circuit` from a string.
+    ///
+    /// Included files are resolved relative to the current directory.
     pub fn from_string(s: &str) -> Result<Self, Error> {
         Self::from_bufread(s.as_bytes())
     }
 
     /// Convenience method to build a [`Circuit`] from a file.
+    ///
+    /// Included files are resolved relative to the including file.
     pub fn from_file(file_path: &std::path::Path) -> Result<Self, Error> {
-        Self::from_read(File::open(file_path)?)
+        let connections = parser::Parser::default().include(file_path)?;
+        Ok(Circuit::from_iter(connections))
     }
 
     fn from_bufread<R>(reader: R) -> Result<Self, Error>
     where
         R: BufRead,
     {
-        let mut circuit = Circuit::default();
-        for line in reader.lines() {
-            circuit.add_connection(&line?)?;
-        }
-        Ok(circuit)
+        let connections = parser::Parser::default().parse(reader, None)?;
+        Ok(Circuit::from_iter(connections))
     }
 
     /// Adds a new connection to the existing circuit.
@@ -133,12 +214,114 @@ impl Circuit {
     where
         T: TryInto<Connection>,
     {
-        let connection = connection.try_into()?;
-        self.connections
-            .insert(connection.output.clone(), connection);
+        self.insert(connection.try_into()?);
         Ok(())
     }
 
+    /// Inserts `connection`, returning the one previously driving its output.
+    pub fn replace(&mut self, connection: Connection) -> Option<Connection> {
+        self.insert(connection)
+    }
+
+    /// Removes the connection driving `cable`.
+    pub fn remove(&mut self, cable: &str) -> Option<Connection> {
+        self.connections.remove(cable)
+    }
+
+    /// Connection driving `cable`.
+    pub fn get(&self, cable: &str) -> Option<&Connection> {
+        self.connections.get(cable)
+    }
+
+    /// All the connections, in arbitrary order.
+    pub fn connections(&self) -> impl Iterator<Item = &Connection> {
+        self.connections.values()
+    }
+
+    /// Cables read by the connection driving `cable`, empty if there is none.
+    pub fn inputs_of(&self, cable: &str) -> Vec<&str> {
+        self.get(cable)
+            .map(|connection| connection.gate.inputs())
+            .unwrap_or_default()
+    }
+
+    /// Cables reading from `cable`, sorted.
+    pub fn dependents_of(&self, cable: &str) -> Vec<&str> {
+        let mut dependents: Vec<_> = self
+            .connections()
+            .filter(|connection| connection.gate.inputs().contains(&cable))
+            .map(Connection::output)
+            .collect();
+        dependents.sort_unstable();
+        dependents
+    }
+
+    /// Cables forming a combinational loop, each one reading from the next
+    /// and the last reading from the first, if the circuit contains any.
+    ///
+    /// Evaluating a cable on such a loop never terminates. Loops going
+    /// through a [`LogicGate::Dff`] are fine since it doesn't read its input
+    /// until the next clock tick.
+    ///
+    /// ```
+    /// # use logic::Circuit;
+    /// let circuit = Circuit::from_string("NOT b -> a\na AND 1 -> b\n1 -> c").unwrap();
+    /// let mut cycle = circuit.find_loop().unwrap();
+    /// cycle.sort();
+    /// assert_eq!(cycle, ["a", "b"]);
+    /// ```
+    pub fn find_loop(&self) -> Option<Vec<&str>> {
+        // cables fully explored without finding a loop
+        let mut done = HashSet::new();
+        for start in self.sorted_connections() {
+            let cable = start.output();
+            if done.contains(cable) {
+                continue;
+            }
+            let mut path = vec![(cable, self.combinational_inputs(cable))];
+            while let Some((_, inputs)) = path.last_mut() {
+                let Some(input) = inputs.pop() else {
+                    let (cable, _) = path.pop().unwrap();
+                    done.insert(cable);
+                    continue;
+                };
+                if done.contains(input) {
+                    continue;
+                }
+                if let Some(position) = path.iter().position(|(cable, _)| *cable == input) {
+                    return Some(path[position..].iter().map(|(cable, _)| *cable).collect());
+                }
+                path.push((input, self.combinational_inputs(input)));
+            }
+        }
+        None
+    }
+
+    /// Same as [`Circuit::inputs_of`], minus the inputs only read on clock ticks.
+    fn combinational_inputs(&self, cable: &str) -> Vec<&str> {
+        match self.get(cable).map(Connection::gate) {
+            Some(LogicGate::Dff(_)) => vec![],
+            _ => self.inputs_of(cable),
+        }
+    }
+
+    fn insert(&mut self, connection: Connection) -> Option<Connection> {
+        self.connections
+            .insert(connection.output.clone(), connection)
+    }
+
+    /// Graphviz DOT representation of the circuit, see [`Dot`].
+    pub fn dot(&self) -> Dot<'_> {
+        Dot::new(self)
+    }
+
+    /// Connections sorted by output cable.
+    fn sorted_connections(&self) -> Vec<&Connection> {
+        let mut connections: Vec<_> = self.connections.values().collect();
+        connections.sort_unstable_by(|a, b| a.output.cmp(&b.output));
+        connections
+    }
+
     /// Get the signal out of the specified cable. `None` if no matching cable.
     pub fn signal(&self, cable: &str) -> Option<u16> {
         let connection = self.connections.get(cable)?;
@@ -149,33 +332,128 @@ impl Circuit {
 
 impl CachedCircuit {
     /// Adds a new connection to the existing circuit.
-    /// Same as [`Circuit::add_connection`], but resets the interval value cache.
+    /// Same as [`Circuit::add_connection`], but invalidates the cached signals
+    /// of the cables depending on the new connection's output.
     pub fn add_connection<T>(&mut self, connection: T) -> Result<(), T::Error>
     where
         T: TryInto<Connection>,
     {
-        self.circuit.add_connection(connection)?;
-        self.cache.borrow_mut().clear();
+        self.insert(connection.try_into()?);
         Ok(())
     }
 
     /// Get the signal out of the specified cable. `None` if no matching cable.
     pub fn signal(&self, cable: &str) -> Option<u16> {
-        if let Some(i) = self.cache.borrow().get(cable) {
+        if let Some(i) = self.cache().get(cable) {
             return Some(*i);
         }
-        let connection = self.circuit.connections.get(cable)?.clone();
-        let signal = signal::explore_gates(self, &connection)?;
-        self.cache.borrow_mut().insert(cable.into(), signal);
+        let connection = self.circuit.connections.get(cable)?;
+        let signal = signal::explore_gates(self, connection)?;
+        self.cache_mut().insert(cable.into(), signal);
         Some(signal)
     }
+
+    /// Same as [`Circuit::replace`], but invalidates the cached signals of
+    /// the cables depending on the new connection's output.
+    pub fn replace(&mut self, connection: Connection) -> Option<Connection> {
+        self.insert(connection)
+    }
+
+    /// Same as [`Circuit::remove`], but invalidates the cached signals of
+    /// the cables depending on the removed connection's output.
+    pub fn remove(&mut self, cable: &str) -> Option<Connection> {
+        let previous = self.circuit.remove(cable)?;
+        unlink(&mut self.dependents, &previous);
+        self.invalidate(cable);
+        Some(previous)
+    }
+
+    /// Cables reading from `cable`, sorted.
+    pub fn dependents_of(&self, cable: &str) -> Vec<&str> {
+        let mut dependents: Vec<_> = self
+            .dependents
+            .get(cable)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        dependents.sort_unstable();
+        dependents
+    }
+
+    /// The underlying, uncached, circuit.
+    pub fn circuit(&self) -> &Circuit {
+        &self.circuit
+    }
+
+    fn insert(&mut self, connection: Connection) -> Option<Connection> {
+        let output = connection.output.clone();
+        link(&mut self.dependents, &connection);
+        let previous = self.circuit.insert(connection);
+        if let Some(previous) = &previous {
+            unlink(&mut self.dependents, previous);
+            link(&mut self.dependents, &self.circuit.connections[&output]);
+        }
+        self.invalidate(&output);
+        previous
+    }
+
+    /// Removes the cached signals of `cable` and everything downstream of it.
+    fn invalidate(&mut self, cable: &str) {
+        let cache = self.cache.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let mut pending = vec![cable];
+        while let Some(cable) = pending.pop() {
+            // a signal is only cached after the inputs it read were, so an
+            // uncached cable wasn't read by anything cached. A MUX doesn't
+            // read its unselected input, nor a DFF its input, and doesn't
+            // depend on it until its select changes or the clock ticks
+            if cache.remove(cable).is_none() {
+                continue;
+            }
+            if let Some(dependents) = self.dependents.get(cable) {
+                pending.extend(dependents.iter().map(String::as_str));
+            }
+        }
+    }
+
+    fn cache(&self) -> RwLockReadGuard<'_, HashMap<String, u16>> {
+        self.cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn cache_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, u16>> {
+        self.cache.write().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 impl From<Circuit> for CachedCircuit {
     fn from(circuit: Circuit) -> Self {
+        let mut dependents = HashMap::new();
+        for connection in circuit.connections.values() {
+            link(&mut dependents, connection);
+        }
         Self {
             circuit,
             cache: Default::default(),
+            dependents,
+        }
+    }
+}
+
+/// Records `connection`'s output as a dependent of each of its inputs.
+fn link(dependents: &mut HashMap<String, HashSet<String>>, connection: &Connection) {
+    for input in connection.gate.inputs() {
+        dependents
+            .entry(input.into())
+            .or_default()
+            .insert(connection.output.clone());
+    }
+}
+
+/// Reverts [`link`].
+fn unlink(dependents: &mut HashMap<String, HashSet<String>>, connection: &Connection) {
+    for input in connection.gate.inputs() {
+        if let Some(set) = dependents.get_mut(input) {
+            set.remove(&connection.output);
         }
     }
 }
@@ -187,6 +465,91 @@ impl Connection {
             output: output.into(),
         }
     }
+
+    /// Gate driving the output cable.
+    pub fn gate(&self) -> &LogicGate {
+        &self.gate
+    }
+
+    /// Cable this connection drives.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+}
+
+impl LogicGate {
+    /// Same gate with every operand replaced by `f(operand)`.
+    pub(crate) fn map_operands<F>(&self, mut f: F) -> LogicGate
+    where
+        F: FnMut(&Operand) -> Operand,
+    {
+        match self {
+            LogicGate::Assignment(assignment) => LogicGate::Assignment(assignment.clone()),
+            LogicGate::And(left, right) => LogicGate::And(f(left), f(right)),
+            LogicGate::Or(left, right) => LogicGate::Or(f(left), f(right)),
+            LogicGate::Xor(left, right) => LogicGate::Xor(f(left), f(right)),
+            LogicGate::Nand(left, right) => LogicGate::Nand(f(left), f(right)),
+            LogicGate::Nor(left, right) => LogicGate::Nor(f(left), f(right)),
+            LogicGate::Xnor(left, right) => LogicGate::Xnor(f(left), f(right)),
+            LogicGate::Add(left, right) => LogicGate::Add(f(left), f(right)),
+            LogicGate::Sub(left, right) => LogicGate::Sub(f(left), f(right)),
+            LogicGate::Not(origin) => LogicGate::Not(f(origin)),
+            LogicGate::Lshift(origin, shift) => LogicGate::Lshift(f(origin), *shift),
+            LogicGate::Rshift(origin, shift) => LogicGate::Rshift(f(origin), *shift),
+            LogicGate::Lrotate(origin, shift) => LogicGate::Lrotate(f(origin), *shift),
+            LogicGate::Rrotate(origin, shift) => LogicGate::Rrotate(f(origin), *shift),
+            LogicGate::Mux(select, a, b) => LogicGate::Mux(f(select), f(a), f(b)),
+            LogicGate::Dff(input) => LogicGate::Dff(f(input)),
+        }
+    }
+
+    /// Cables this gate reads from, literal operands excluded.
+    pub fn inputs(&self) -> Vec<&str> {
+        let operands = match self {
+            LogicGate::Assignment(Assignment::Direct(_)) => vec![],
+            LogicGate::Assignment(Assignment::Gate(cable)) => return vec![cable],
+            LogicGate::And(left, right)
+            | LogicGate::Or(left, right)
+            | LogicGate::Xor(left, right)
+            | LogicGate::Nand(left, right)
+            | LogicGate::Nor(left, right)
+            | LogicGate::Xnor(left, right)
+            | LogicGate::Add(left, right)
+            | LogicGate::Sub(left, right) => vec![left, right],
+            LogicGate::Not(origin)
+            | LogicGate::Lshift(origin, _)
+            | LogicGate::Rshift(origin, _)
+            | LogicGate::Lrotate(origin, _)
+            | LogicGate::Rrotate(origin, _)
+            | LogicGate::Dff(origin) => vec![origin],
+            LogicGate::Mux(select, a, b) => vec![select, a, b],
+        };
+        operands
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Value(_) => None,
+                Operand::Cable(cable) => Some(cable.as_str()),
+            })
+            .collect()
+    }
+}
+
+impl From<u16> for Operand {
+    fn from(value: u16) -> Self {
+        Operand::Value(value)
+    }
+}
+
+impl From<&str> for Operand {
+    fn from(cable: &str) -> Self {
+        Operand::Cable(cable.into())
+    }
+}
+
+impl From<String> for Operand {
+    fn from(cable: String) -> Self {
+        Operand::Cable(cable)
+    }
 }
 
 impl TryFrom<&str> for Connection {
@@ -220,12 +583,37 @@ impl std::fmt::Display for Error {
         match self {
             Error::Io(error) => std::fmt::Display::fmt(error, f),
             Error::Parse(error) => std::fmt::Display::fmt(error, f),
+            Error::Loop(cables) => write!(f, "Combinational loop {}", cables.join(" <- ")),
+            Error::UnknownModule(name) => write!(f, "Unknown module {name:?}"),
+            Error::UnclosedModule(name) => write!(f, "Module {name:?} is never closed"),
+            Error::UnexpectedModuleEnd => f.write_str("Unexpected `}` outside of a module"),
+            Error::NestedModule(name) => write!(f, "Module {name:?} is defined inside another"),
+            Error::IncludeLoop(path) => write!(f, "{} includes itself", path.display()),
+            Error::At {
+                file: Some(file),
+                line,
+                error,
+            } => write!(f, "{}:{line}: {error}", file.display()),
+            Error::At {
+                file: None,
+                line,
+                error,
+            } => write!(f, "line {line}: {error}"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl std::fmt::Display for Circuit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for connection in self.sorted_connections() {
+            writeln!(f, "{connection}")?;
+        }
+        Ok(())
+    }
+}
+
 impl std::iter::FromIterator<Connection> for Circuit {
     fn from_iter<T: IntoIterator<Item = Connection>>(iter: T) -> Self {
         let mut circuit = Circuit::default();
@@ -237,8 +625,7 @@ impl std::iter::FromIterator<Connection> for Circuit {
 impl std::iter::Extend<Connection> for Circuit {
     fn extend<T: IntoIterator<Item = Connection>>(&mut self, iter: T) {
         for connection in iter {
-            self.connections
-                .insert(connection.output.clone(), connection);
+            self.insert(connection);
         }
     }
 }
@@ -254,10 +641,7 @@ impl std::iter::FromIterator<Connection> for CachedCircuit {
 impl std::iter::Extend<Connection> for CachedCircuit {
     fn extend<T: IntoIterator<Item = Connection>>(&mut self, iter: T) {
         for connection in iter {
-            self.circuit
-                .connections
-                .insert(connection.output.clone(), connection);
+            self.insert(connection);
         }
-        self.cache.borrow_mut().clear();
     }
 }
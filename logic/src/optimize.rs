@@ -0,0 +1,119 @@
+//! Simplification pass producing a smaller equivalent [`Circuit`].
+use std::collections::{HashMap, HashSet};
+
+use crate::signal::explore_gates;
+use crate::{Assignment, Circuit, Connection, LogicGate, Operand};
+
+impl Circuit {
+    /// Returns an equivalent circuit restricted to what `outputs` depend on.
+    ///
+    /// Gates whose inputs are all known are folded into constants, aliases
+    /// (`x -> y`) are collapsed into their source, and every cable none of
+    /// the `outputs` depend on is removed. Each remaining cable evaluates to
+    /// the same signal as in `self`.
+    ///
+    /// ```
+    /// # use logic::Circuit;
+    /// let circuit = Circuit::from_string("3 -> x\nx -> y\ny LSHIFT 1 -> z\nz AND w -> out\n1 -> unused").unwrap();
+    /// let optimized = circuit.optimize(["out"]);
+    /// assert_eq!(optimized.to_string(), "6 AND w -> out\n");
+    /// ```
+    pub fn optimize<'a, I>(&self, outputs: I) -> Circuit
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut optimizer = Optimizer {
+            circuit: self,
+            canonical: HashMap::new(),
+            visiting: HashSet::new(),
+        };
+        let mut optimized = Circuit::default();
+        let mut pending: Vec<String> = Vec::new();
+        for output in outputs {
+            if !self.connections.contains_key(output) || optimized.connections.contains_key(output)
+            {
+                continue;
+            }
+            let gate = match optimizer.canonical(output) {
+                Operand::Value(value) => LogicGate::Assignment(Assignment::Direct(value)),
+                Operand::Cable(cable) if cable != output => {
+                    LogicGate::Assignment(Assignment::Gate(cable))
+                }
+                Operand::Cable(_) => optimizer.simplify(output),
+            };
+            pending.extend(gate.inputs().into_iter().map(String::from));
+            optimized.insert(Connection::new(output, gate));
+        }
+        while let Some(cable) = pending.pop() {
+            if !self.connections.contains_key(&cable) || optimized.connections.contains_key(&cable)
+            {
+                continue;
+            }
+            let gate = optimizer.simplify(&cable);
+            pending.extend(gate.inputs().into_iter().map(String::from));
+            optimized.insert(Connection::new(&cable, gate));
+        }
+        optimized
+    }
+}
+
+struct Optimizer<'a> {
+    circuit: &'a Circuit,
+    /// Constant or cable each cable can be replaced with.
+    canonical: HashMap<String, Operand>,
+    /// Guards against looping forever on cyclic circuits.
+    visiting: HashSet<String>,
+}
+
+impl Optimizer<'_> {
+    fn canonical(&mut self, cable: &str) -> Operand {
+        if let Some(operand) = self.canonical.get(cable) {
+            return operand.clone();
+        }
+        let unchanged = Operand::Cable(cable.into());
+        let Some(connection) = self.circuit.connections.get(cable) else {
+            return unchanged;
+        };
+        if !self.visiting.insert(cable.into()) {
+            return unchanged;
+        }
+        let operand = match &connection.gate {
+            LogicGate::Assignment(Assignment::Direct(value)) => Operand::Value(*value),
+            LogicGate::Assignment(Assignment::Gate(source)) => self.canonical(source),
+            LogicGate::Dff(_) => unchanged,
+            gate => {
+                let gate = gate.map_operands(|operand| self.operand(operand));
+                if gate.inputs().is_empty() {
+                    explore_gates(&Circuit::default(), &Connection::new(cable, gate))
+                        .map_or(unchanged, Operand::Value)
+                } else {
+                    unchanged
+                }
+            }
+        };
+        self.visiting.remove(cable);
+        self.canonical.insert(cable.into(), operand.clone());
+        operand
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Operand {
+        match operand {
+            Operand::Value(value) => Operand::Value(*value),
+            Operand::Cable(cable) => self.canonical(cable),
+        }
+    }
+
+    /// Gate driving `cable`, with its operands replaced by their canonical form.
+    fn simplify(&mut self, cable: &str) -> LogicGate {
+        if let Operand::Value(value) = self.canonical(cable) {
+            return LogicGate::Assignment(Assignment::Direct(value));
+        }
+        match &self.circuit.connections[cable].gate {
+            LogicGate::Assignment(Assignment::Gate(source)) => match self.canonical(source) {
+                Operand::Value(value) => LogicGate::Assignment(Assignment::Direct(value)),
+                Operand::Cable(source) => LogicGate::Assignment(Assignment::Gate(source)),
+            },
+            gate => gate.map_operands(|operand| self.operand(operand)),
+        }
+    }
+}
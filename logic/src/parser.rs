@@ -0,0 +1,115 @@
+//! Parsing of whole circuit descriptions, on top of the line-by-line
+//! parsing of [`Connection`]s.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::{Assignment, Connection, ConnectionParseError, Error, LogicGate, Operand};
+
+#[derive(Debug, Default)]
+pub struct Parser {
+    modules: HashMap<String, Vec<Connection>>,
+    /// Files being parsed, to detect include loops.
+    including: Vec<PathBuf>,
+}
+
+impl Parser {
+    /// Connections described by `reader`, with modules instantiated and
+    /// included files parsed in place.
+    pub fn parse<R>(&mut self, reader: R, file: Option<&Path>) -> Result<Vec<Connection>, Error>
+    where
+        R: BufRead,
+    {
+        let mut connections = vec![];
+        // name, first line and connections of the module being defined
+        let mut module: Option<(String, usize, Vec<Connection>)> = None;
+        for (i, line) in reader.lines().enumerate() {
+            let at = |error: Error| match error {
+                Error::At { .. } => error,
+                error => Error::At {
+                    file: file.map(Path::to_owned),
+                    line: i + 1,
+                    error: Box::new(error),
+                },
+            };
+            let line = line.map_err(|e| at(e.into()))?;
+            let target = match &mut module {
+                Some((_, _, connections)) => connections,
+                None => &mut connections,
+            };
+            let include = line
+                .trim()
+                .strip_prefix("include ")
+                .and_then(|path| path.trim().strip_prefix('"')?.strip_suffix('"'));
+            if let Some(path) = include {
+                let path = match file.and_then(Path::parent) {
+                    Some(directory) => directory.join(path),
+                    None => PathBuf::from(path),
+                };
+                target.extend(self.include(&path).map_err(at)?);
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => {}
+                ["module", name, "{"] => match module {
+                    Some(_) => return Err(at(Error::NestedModule(name.into()))),
+                    None => module = Some((name.into(), i + 1, vec![])),
+                },
+                ["}"] => match module.take() {
+                    Some((name, _, connections)) => {
+                        self.modules.insert(name, connections);
+                    }
+                    None => return Err(at(Error::UnexpectedModuleEnd)),
+                },
+                ["instance", name, prefix] if name != "->" => {
+                    let Some(definition) = self.modules.get(name) else {
+                        return Err(at(Error::UnknownModule(name.into())));
+                    };
+                    let prefix = format!("{prefix}.");
+                    target.extend(definition.iter().map(|c| prefixed(c, &prefix)));
+                }
+                _ => target.push(
+                    line.parse()
+                        .map_err(|e: ConnectionParseError| at(e.into()))?,
+                ),
+            }
+        }
+        match module {
+            Some((name, line, _)) => Err(Error::At {
+                file: file.map(Path::to_owned),
+                line,
+                error: Box::new(Error::UnclosedModule(name)),
+            }),
+            None => Ok(connections),
+        }
+    }
+
+    /// Connections described by the file at `path`.
+    pub fn include(&mut self, path: &Path) -> Result<Vec<Connection>, Error> {
+        let canonical = path.canonicalize()?;
+        if self.including.contains(&canonical) {
+            return Err(Error::IncludeLoop(path.to_owned()));
+        }
+        let reader = BufReader::new(File::open(path)?);
+        self.including.push(canonical);
+        let connections = self.parse(reader, Some(path));
+        self.including.pop();
+        connections
+    }
+}
+
+/// `connection` with `prefix` added to each of its cables.
+fn prefixed(connection: &Connection, prefix: &str) -> Connection {
+    let rename = |cable: &str| format!("{prefix}{cable}");
+    let gate = match &connection.gate {
+        LogicGate::Assignment(Assignment::Gate(cable)) => {
+            LogicGate::Assignment(Assignment::Gate(rename(cable)))
+        }
+        gate => gate.map_operands(|operand| match operand {
+            Operand::Value(value) => Operand::Value(*value),
+            Operand::Cable(cable) => Operand::Cable(rename(cable)),
+        }),
+    };
+    Connection::new(&rename(&connection.output), gate)
+}
@@ -1,52 +1,136 @@
 //! Internal implemention detail to share some logic between the cached and
 //! non-cached version of the logic circuit.
-use crate::{And, Assignment, CachedCircuit, Circuit, Connection, LogicGate};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::{Assignment, CachedCircuit, Circuit, Connection, LogicGate, Operand};
 
 pub fn explore_gates<T>(circuit: &T, connection: &Connection) -> Option<u16>
 where
     T: Signal,
 {
+    let operand = |operand: &Operand| match operand {
+        Operand::Value(value) => Some(*value),
+        Operand::Cable(cable) => circuit.signal(cable),
+    };
     Some(match &connection.gate {
         LogicGate::Assignment(Assignment::Direct(value)) => *value,
         LogicGate::Assignment(Assignment::Gate(cable)) => circuit.signal(cable)?,
-        LogicGate::And(And::True(right)) => circuit.signal(right)?,
-        LogicGate::And(And::And(left, right)) => {
-            let left = circuit.signal(left)?;
-            let right = circuit.signal(right)?;
-            left & right
-        }
-        LogicGate::Or(left, right) => {
-            let left = circuit.signal(left)?;
-            let right = circuit.signal(right)?;
-            left | right
-        }
+        LogicGate::And(left, right) => operand(left)? & operand(right)?,
+        LogicGate::Or(left, right) => operand(left)? | operand(right)?,
+        LogicGate::Xor(left, right) => operand(left)? ^ operand(right)?,
+        LogicGate::Nand(left, right) => !(operand(left)? & operand(right)?),
+        LogicGate::Nor(left, right) => !(operand(left)? | operand(right)?),
+        LogicGate::Xnor(left, right) => !(operand(left)? ^ operand(right)?),
+        LogicGate::Add(left, right) => operand(left)?.wrapping_add(operand(right)?),
+        LogicGate::Sub(left, right) => operand(left)?.wrapping_sub(operand(right)?),
         LogicGate::Lshift(origin, shift) => {
-            let value = circuit.signal(origin)?;
-            value << shift
+            operand(origin)?.checked_shl(*shift as u32).unwrap_or(0)
         }
         LogicGate::Rshift(origin, shift) => {
-            let value = circuit.signal(origin)?;
-            value >> shift
+            operand(origin)?.checked_shr(*shift as u32).unwrap_or(0)
         }
-        LogicGate::Not(origin) => {
-            let value = circuit.signal(origin)?;
-            !value
+        LogicGate::Lrotate(origin, shift) => operand(origin)?.rotate_left(*shift as u32),
+        LogicGate::Rrotate(origin, shift) => operand(origin)?.rotate_right(*shift as u32),
+        LogicGate::Not(origin) => !operand(origin)?,
+        LogicGate::Dff(_) => circuit.register(&connection.output)?,
+        LogicGate::Mux(select, a, b) => {
+            if operand(select)? == 0 {
+                operand(a)?
+            } else {
+                operand(b)?
+            }
         }
     })
 }
 
 pub trait Signal {
     fn signal(&self, cable: &str) -> Option<u16>;
+
+    /// Current value of the register driving `cable`.
+    fn register(&self, _cable: &str) -> Option<u16> {
+        Some(0)
+    }
 }
 
 impl Signal for Circuit {
     fn signal(&self, cable: &str) -> Option<u16> {
-        Circuit::signal(&self, cable)
+        Circuit::signal(self, cable)
     }
 }
 
 impl Signal for CachedCircuit {
     fn signal(&self, cable: &str) -> Option<u16> {
-        CachedCircuit::signal(&self, cable)
+        CachedCircuit::signal(self, cable)
+    }
+}
+
+/// Memoizing evaluator borrowing a [`Circuit`], for one-off passes over
+/// many cables.
+pub struct Memoized<'a> {
+    circuit: &'a Circuit,
+    /// Register values, missing ones hold their initial value of 0.
+    registers: Option<&'a HashMap<String, Option<u16>>>,
+    cache: RefCell<HashMap<String, u16>>,
+}
+
+impl<'a> Memoized<'a> {
+    pub fn new(circuit: &'a Circuit) -> Self {
+        Self {
+            circuit,
+            registers: None,
+            cache: Default::default(),
+        }
+    }
+
+    pub fn with_registers(
+        circuit: &'a Circuit,
+        registers: &'a HashMap<String, Option<u16>>,
+    ) -> Self {
+        Self {
+            registers: Some(registers),
+            ..Self::new(circuit)
+        }
+    }
+
+    /// Evaluates `circuit` with the given cables forced to the given signals.
+    pub fn with_inputs<'b, I>(circuit: &'a Circuit, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = (&'b str, u16)>,
+    {
+        let cache = inputs
+            .into_iter()
+            .map(|(cable, signal)| (cable.to_owned(), signal))
+            .collect();
+        Self {
+            cache: RefCell::new(cache),
+            ..Self::new(circuit)
+        }
+    }
+
+    pub fn operand(&self, operand: &Operand) -> Option<u16> {
+        match operand {
+            Operand::Value(value) => Some(*value),
+            Operand::Cable(cable) => self.signal(cable),
+        }
+    }
+}
+
+impl Signal for Memoized<'_> {
+    fn signal(&self, cable: &str) -> Option<u16> {
+        if let Some(i) = self.cache.borrow().get(cable) {
+            return Some(*i);
+        }
+        let connection = self.circuit.connections.get(cable)?;
+        let signal = explore_gates(self, connection)?;
+        self.cache.borrow_mut().insert(cable.into(), signal);
+        Some(signal)
+    }
+
+    fn register(&self, cable: &str) -> Option<u16> {
+        match self.registers.and_then(|registers| registers.get(cable)) {
+            Some(value) => *value,
+            None => Some(0),
+        }
     }
 }
@@ -0,0 +1,173 @@
+//! Clocked simulation of circuits containing registers.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::signal::{Memoized, Signal};
+use crate::{Circuit, Error, LogicGate};
+
+/// Clocked simulation of a [`Circuit`].
+///
+/// Each [`LogicGate::Dff`] starts at 0 and, on every [`Simulator::tick`],
+/// latches the value its input had during the previous clock cycle. The
+/// signals of the watched cables are recorded at every cycle and can be
+/// exported with [`Simulator::vcd`].
+///
+/// ```
+/// # use logic::{Circuit, Simulator};
+/// let counter = Circuit::from_string("DFF next -> count\ncount ADD 1 -> next").unwrap();
+/// let mut simulator = Simulator::new(counter).unwrap();
+/// simulator.watch("count");
+/// simulator.run(3);
+/// assert_eq!(simulator.signal("count"), Some(3));
+/// assert_eq!(simulator.waveform("count"), Some(&[Some(0), Some(1), Some(2)][..]));
+/// ```
+#[derive(Debug)]
+pub struct Simulator {
+    circuit: Circuit,
+    /// Register values, `None` when their input had no signal.
+    registers: HashMap<String, Option<u16>>,
+    /// Number of clock ticks since the start.
+    time: usize,
+    /// Signals recorded for each watched cable, one per clock cycle.
+    waveforms: Vec<(String, Vec<Option<u16>>)>,
+}
+
+impl Simulator {
+    /// Fails if the circuit contains a combinational loop.
+    pub fn new(circuit: Circuit) -> Result<Self, Error> {
+        if let Some(cables) = circuit.find_loop() {
+            return Err(Error::Loop(cables.into_iter().map(String::from).collect()));
+        }
+        Ok(Self {
+            circuit,
+            registers: HashMap::new(),
+            time: 0,
+            waveforms: vec![],
+        })
+    }
+
+    /// Records the signal of `cable` on every following clock cycle.
+    pub fn watch(&mut self, cable: &str) {
+        if self.waveforms.iter().any(|(watched, _)| watched == cable) {
+            return;
+        }
+        self.waveforms.push((cable.into(), vec![None; self.time]));
+    }
+
+    /// Signal of `cable` during the current clock cycle.
+    pub fn signal(&self, cable: &str) -> Option<u16> {
+        Memoized::with_registers(&self.circuit, &self.registers).signal(cable)
+    }
+
+    /// Number of clock ticks since the start.
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
+    /// Records the watched cables and moves on to the next clock cycle.
+    pub fn tick(&mut self) {
+        let state = Memoized::with_registers(&self.circuit, &self.registers);
+        for (cable, waveform) in &mut self.waveforms {
+            waveform.push(state.signal(cable));
+        }
+        let registers = self
+            .circuit
+            .connections()
+            .filter_map(|connection| match connection.gate() {
+                LogicGate::Dff(input) => Some((connection.output().into(), state.operand(input))),
+                _ => None,
+            })
+            .collect();
+        self.registers = registers;
+        self.time += 1;
+    }
+
+    /// Ticks the clock `ticks` times.
+    pub fn run(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Signals recorded for `cable`, one per elapsed clock cycle. `None` if
+    /// the cable isn't watched.
+    pub fn waveform(&self, cable: &str) -> Option<&[Option<u16>]> {
+        self.waveforms
+            .iter()
+            .find(|(watched, _)| watched == cable)
+            .map(|(_, waveform)| waveform.as_slice())
+    }
+
+    /// Recorded waveforms in Value Change Dump format, see [`Vcd`].
+    pub fn vcd(&self) -> Vcd<'_> {
+        Vcd { simulator: self }
+    }
+}
+
+/// Displays the waveforms recorded by a [`Simulator`] as a Value Change
+/// Dump, readable by GTKWave and most waveform viewers.
+///
+/// Each clock cycle lasts one time unit, cycles where a cable had no signal
+/// are dumped as `x`.
+///
+/// ```
+/// # use logic::{Circuit, Simulator};
+/// let toggle = Circuit::from_string("DFF next -> q\nNOT q -> next").unwrap();
+/// let mut simulator = Simulator::new(toggle).unwrap();
+/// simulator.watch("q");
+/// simulator.run(2);
+/// let vcd = simulator.vcd().to_string();
+/// assert!(vcd.contains("$var wire 16 ! q $end"));
+/// assert!(vcd.ends_with("#0\nb0 !\n#1\nb1111111111111111 !\n#2\n"));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Vcd<'a> {
+    simulator: &'a Simulator,
+}
+
+impl fmt::Display for Vcd<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let waveforms = &self.simulator.waveforms;
+        writeln!(
+            f,
+            "$version {} {} $end",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(f, "$timescale 1ns $end")?;
+        writeln!(f, "$scope module circuit $end")?;
+        for (i, (cable, _)) in waveforms.iter().enumerate() {
+            writeln!(f, "$var wire 16 {} {cable} $end", identifier(i))?;
+        }
+        writeln!(f, "$upscope $end")?;
+        writeln!(f, "$enddefinitions $end")?;
+        for time in 0..self.simulator.time {
+            writeln!(f, "#{time}")?;
+            for (i, (_, waveform)) in waveforms.iter().enumerate() {
+                if time > 0 && waveform[time] == waveform[time - 1] {
+                    continue;
+                }
+                match waveform[time] {
+                    Some(signal) => writeln!(f, "b{signal:b} {}", identifier(i))?,
+                    None => writeln!(f, "bx {}", identifier(i))?,
+                }
+            }
+        }
+        writeln!(f, "#{}", self.simulator.time)
+    }
+}
+
+/// Short VCD identifier made of printable ASCII characters.
+fn identifier(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - FIRST + 1) as usize;
+    let mut identifier = String::new();
+    loop {
+        identifier.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
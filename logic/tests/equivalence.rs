@@ -0,0 +1,57 @@
+use logic::{Circuit, EquivalenceChecker};
+
+#[test]
+fn sampled_equivalence() {
+    let left = Circuit::from_string("a ADD b -> s\nc XOR d -> x\ns AND x -> out").unwrap();
+    let right = Circuit::from_string("b ADD a -> s\nd XOR c -> x\nx AND s -> out").unwrap();
+    let checker = EquivalenceChecker::new(["a", "b", "c", "d"], ["out"]).samples(1000);
+    assert!(!checker.is_exhaustive());
+    assert_eq!(checker.check(&left, &right).unwrap(), None);
+
+    let right = Circuit::from_string("b SUB a -> s\nd XOR c -> x\nx AND s -> out").unwrap();
+    let counterexample = checker.check(&left, &right).unwrap().unwrap();
+    assert_eq!(counterexample.output, "out");
+    assert_ne!(counterexample.left, counterexample.right);
+}
+
+#[test]
+fn undriven_outputs_differ_from_driven_ones() {
+    let left = Circuit::from_string("a AND 1 -> out").unwrap();
+    let right = Circuit::from_string("a AND missing -> out").unwrap();
+    let checker = EquivalenceChecker::new(["a"], ["out"]).width(1);
+    let counterexample = checker.check(&left, &right).unwrap().unwrap();
+    assert_eq!(counterexample.right, None);
+    assert_eq!(
+        counterexample.to_string(),
+        "a=0: out is 0 on the left and no signal on the right"
+    );
+}
+
+#[test]
+fn loops_are_errors() {
+    let left = Circuit::from_string("NOT out -> out").unwrap();
+    let checker = EquivalenceChecker::new([], ["out"]);
+    assert!(checker.check(&left, &Circuit::default()).is_err());
+}
+
+#[test]
+fn truth_tables_are_capped() {
+    let circuit = Circuit::from_string("a AND b -> out").unwrap();
+    let table = circuit
+        .truth_table(["a", "b"], ["out"])
+        .width(2)
+        .max_rows(3);
+    assert_eq!(
+        table.to_string(),
+        "a b | out\n0 0 |   0\n0 1 |   0\n0 2 |   0\n... 13 more rows\n"
+    );
+    let inputs = ["a", "b", "c", "d", "e"];
+    let table = circuit.truth_table(inputs, ["out"]).max_rows(1);
+    assert_eq!(
+        table.to_string().lines().last(),
+        Some(format!("... more than {} more rows", u64::MAX - 1).as_str())
+    );
+    let table = circuit.truth_table(["a", "b"], ["out"]).to_string();
+    assert_eq!(table.lines().count(), 1 + (1 << 16) + 1);
+    assert!(table.ends_with("\n... 4294901760 more rows\n"));
+}
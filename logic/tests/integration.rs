@@ -1,4 +1,4 @@
-use logic::Circuit;
+use logic::{CachedCircuit, Circuit};
 
 #[test]
 fn example_1() {
@@ -31,3 +31,147 @@ NOT y -> i";
     circuit.add_connection("NOT g -> error").unwrap();
     assert_eq!(circuit.signal("error"), Some(65421));
 }
+
+#[test]
+fn extended_gates() {
+    let circuit = "123 -> x
+456 -> y
+1 -> s
+x XOR y -> xor
+x NAND y -> nand
+x NOR y -> nor
+x XNOR y -> xnor
+x ADD y -> add
+x SUB y -> sub
+x LROTATE 4 -> lrot
+x RROTATE 4 -> rrot
+MUX s x y -> mux
+MUX 0 x y -> mux0
+1 AND x -> and1
+x AND 1 -> and2
+65535 ADD 2 -> wrap
+NOT 0 -> full";
+    let circuit = Circuit::from_string(circuit).unwrap();
+    let (x, y) = (123u16, 456u16);
+    let expected_results = [
+        ("xor", x ^ y),
+        ("nand", !(x & y)),
+        ("nor", !(x | y)),
+        ("xnor", !(x ^ y)),
+        ("add", x + y),
+        ("sub", x.wrapping_sub(y)),
+        ("lrot", x.rotate_left(4)),
+        ("rrot", x.rotate_right(4)),
+        ("mux", y),
+        ("mux0", x),
+        ("and1", 1),
+        ("and2", 1),
+        ("wrap", 1),
+        ("full", u16::MAX),
+    ];
+    for (cable, expected_signal) in expected_results {
+        assert_eq!(circuit.signal(cable), Some(expected_signal), "{cable}");
+    }
+}
+
+#[test]
+fn invalid_gates() {
+    let mut circuit = Circuit::default();
+    assert!(circuit.add_connection("x FOO y -> z").is_err());
+    assert!(circuit.add_connection("MUX x y -> z").is_err());
+    assert!(circuit.add_connection("x LSHIFT y -> z").is_err());
+}
+
+#[test]
+fn display_round_trip() {
+    let original = Circuit::from_file("input.txt".as_ref()).unwrap();
+    let text = original.to_string();
+    let parsed = Circuit::from_string(&text).unwrap();
+    assert_eq!(text, parsed.to_string());
+    let (original, parsed) = (CachedCircuit::from(original), CachedCircuit::from(parsed));
+    assert_eq!(original.signal("a"), parsed.signal("a"));
+}
+
+#[test]
+fn cached_override_invalidates_dependents() {
+    let mut circuit = CachedCircuit::from(Circuit::from_file("input.txt".as_ref()).unwrap());
+    let a = circuit.signal("a").unwrap();
+    assert_eq!(circuit.signal("a"), Some(a));
+    circuit
+        .add_connection(format!("{a} -> b").as_str())
+        .unwrap();
+
+    let mut expected = Circuit::from_file("input.txt".as_ref()).unwrap();
+    expected
+        .add_connection(format!("{a} -> b").as_str())
+        .unwrap();
+    let expected = CachedCircuit::from(expected);
+    assert_eq!(circuit.signal("a"), expected.signal("a"));
+    assert_ne!(circuit.signal("a"), Some(a));
+}
+
+#[test]
+fn cached_circuit_is_shareable_between_threads() {
+    let circuit = CachedCircuit::from(Circuit::from_file("input.txt".as_ref()).unwrap());
+    let signals: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = ["a", "b", "lx", "a"]
+            .into_iter()
+            .map(|cable| scope.spawn(|| circuit.signal(cable)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert_eq!(signals[0], signals[3]);
+    assert!(signals.iter().all(Option::is_some));
+}
+
+#[test]
+fn inspect_and_edit_connections() {
+    let mut circuit = Circuit::from_file("tests/small.txt".as_ref()).unwrap();
+    assert_eq!(circuit.connections().count(), 8);
+    assert_eq!(circuit.get("d").unwrap().to_string(), "x AND y -> d");
+    assert_eq!(circuit.get("d").unwrap().output(), "d");
+    assert_eq!(circuit.inputs_of("d"), ["x", "y"]);
+    assert_eq!(circuit.inputs_of("x"), Vec::<&str>::new());
+    assert_eq!(circuit.dependents_of("x"), ["d", "e", "f", "h"]);
+
+    let previous = circuit.replace("x XOR y -> d".parse().unwrap()).unwrap();
+    assert_eq!(previous.to_string(), "x AND y -> d");
+    assert_eq!(circuit.signal("d"), Some(123 ^ 456));
+    assert!(circuit.replace("1 -> new".parse().unwrap()).is_none());
+
+    let removed = circuit.remove("x").unwrap();
+    assert_eq!(removed.to_string(), "123 -> x");
+    assert_eq!(circuit.signal("d"), None);
+    assert!(circuit.remove("x").is_none());
+}
+
+#[test]
+fn cached_edit_connections() {
+    let circuit = Circuit::from_file("tests/small.txt".as_ref()).unwrap();
+    let mut circuit = CachedCircuit::from(circuit);
+    assert_eq!(circuit.signal("d"), Some(72));
+    assert_eq!(circuit.dependents_of("y"), ["d", "e", "g", "i"]);
+    circuit.replace("x -> y".parse().unwrap()).unwrap();
+    assert_eq!(circuit.dependents_of("x"), ["d", "e", "f", "h", "y"]);
+    assert_eq!(circuit.signal("d"), Some(123));
+    circuit.remove("y").unwrap();
+    assert_eq!(circuit.signal("d"), None);
+    assert_eq!(circuit.signal("x"), Some(123));
+    assert_eq!(circuit.dependents_of("x"), ["d", "e", "f", "h"]);
+}
+
+#[test]
+fn cached_mux_sees_changes_to_its_unselected_input() {
+    let circuit = Circuit::from_string(
+        "0 -> s\n1 -> a\nNOT c -> b\n2 -> c\nMUX s a b -> m\nDFF c -> r\nr OR 4 -> q",
+    )
+    .unwrap();
+    let mut circuit = CachedCircuit::from(circuit);
+    assert_eq!(circuit.signal("m"), Some(1));
+    assert_eq!(circuit.signal("q"), Some(4));
+    circuit.replace("5 -> c".parse().unwrap());
+    assert_eq!(circuit.signal("m"), Some(1));
+    assert_eq!(circuit.signal("q"), Some(4));
+    circuit.replace("1 -> s".parse().unwrap());
+    assert_eq!(circuit.signal("m"), Some(!5));
+}
@@ -0,0 +1,54 @@
+use logic::{Circuit, Error};
+
+#[test]
+fn nested_modules_and_includes() {
+    let circuit = Circuit::from_file("tests/modules/circuit.txt".as_ref()).unwrap();
+    assert_eq!(circuit.signal("fa.sum"), Some(1));
+    assert_eq!(circuit.signal("fa.carry"), Some(1));
+    assert_eq!(circuit.signal("fa.h1.carry"), Some(1));
+    assert_eq!(
+        circuit.get("fa.h2.sum").unwrap().to_string(),
+        "fa.h2.a XOR fa.h2.b -> fa.h2.sum"
+    );
+    assert!(circuit.get("sum").is_none());
+}
+
+#[test]
+fn errors_are_located() {
+    let error = Circuit::from_file("tests/modules/invalid.txt".as_ref()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "tests/modules/invalid.txt:4: Invalid gate"
+    );
+    let error = Circuit::from_string("1 -> a\n\ninstance adder x").unwrap_err();
+    assert_eq!(error.to_string(), "line 3: Unknown module \"adder\"");
+    let error = Circuit::from_string("module m {\n1 -> a\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: Module \"m\" is never closed");
+    let error = Circuit::from_string("module m {\nmodule n {\n}\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2: Module \"n\" is defined inside another"
+    );
+    let error = Circuit::from_string("1 -> a\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2: Unexpected `}` outside of a module"
+    );
+    let error = Circuit::from_string("include \"missing.txt\"").unwrap_err();
+    assert!(matches!(error, Error::At { line: 1, .. }));
+}
+
+#[test]
+fn wires_may_be_named_like_directives() {
+    let circuit = Circuit::from_string("1 -> instance\ninstance -> x").unwrap();
+    assert_eq!(circuit.signal("x"), Some(1));
+}
+
+#[test]
+fn include_loops_are_errors() {
+    let error = Circuit::from_file("tests/modules/self.txt".as_ref()).unwrap_err();
+    let Error::At { line: 2, error, .. } = error else {
+        panic!("{error:?}");
+    };
+    assert!(matches!(*error, Error::IncludeLoop(_)));
+}
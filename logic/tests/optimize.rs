@@ -0,0 +1,73 @@
+use logic::{CachedCircuit, Circuit};
+use proptest::prelude::*;
+
+const GATES: [&str; 12] = [
+    "AND", "OR", "XOR", "NAND", "NOR", "XNOR", "ADD", "SUB", "LSHIFT", "RSHIFT", "LROTATE",
+    "RROTATE",
+];
+
+/// Operand referring either to a literal, an earlier cable or an undefined one.
+fn operand(index: usize) -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<u16>().prop_map(|value| value.to_string()),
+        (0..index.max(1)).prop_map(move |i| match index {
+            0 => "undefined".to_owned(),
+            _ => format!("c{i}"),
+        }),
+        Just("undefined".to_owned()),
+    ]
+}
+
+fn line(index: usize) -> impl Strategy<Value = String> {
+    prop_oneof![
+        operand(index).prop_map(move |a| format!("{a} -> c{index}")),
+        operand(index).prop_map(move |a| format!("NOT {a} -> c{index}")),
+        (operand(index), operand(index), operand(index))
+            .prop_map(move |(s, a, b)| format!("MUX {s} {a} {b} -> c{index}")),
+        (operand(index), 0..12usize, operand(index), 0..20u16).prop_map(
+            move |(a, gate, b, shift)| match GATES[gate] {
+                gate @ ("LSHIFT" | "RSHIFT" | "LROTATE" | "RROTATE") => {
+                    format!("{a} {gate} {shift} -> c{index}")
+                }
+                gate => format!("{a} {gate} {b} -> c{index}"),
+            }
+        ),
+    ]
+}
+
+fn circuit() -> impl Strategy<Value = (String, Vec<usize>)> {
+    (1..40usize).prop_flat_map(|size| {
+        let lines: Vec<_> = (0..size).map(line).collect();
+        (lines, prop::collection::vec(0..size, 1..4))
+            .prop_map(|(lines, outputs)| (lines.join("\n"), outputs))
+    })
+}
+
+proptest! {
+    #[test]
+    fn optimize_preserves_signals((text, outputs) in circuit()) {
+        let original = Circuit::from_string(&text).unwrap();
+        let outputs: Vec<_> = outputs.iter().map(|i| format!("c{i}")).collect();
+        let optimized = original.optimize(outputs.iter().map(String::as_str));
+        let remaining: Vec<String> = optimized
+            .connections()
+            .map(|connection| connection.output().to_owned())
+            .collect();
+        for output in &outputs {
+            prop_assert!(remaining.contains(output));
+        }
+        let (original, optimized) = (CachedCircuit::from(original), CachedCircuit::from(optimized));
+        for cable in &remaining {
+            prop_assert_eq!(original.signal(cable), optimized.signal(cable), "{}", cable);
+        }
+    }
+}
+
+#[test]
+fn optimize_removes_dead_wires() {
+    let circuit = Circuit::from_file("input.txt".as_ref()).unwrap();
+    let optimized = circuit.optimize(["a"]);
+    assert_eq!(optimized.connections().count(), 1);
+    let circuit = CachedCircuit::from(circuit);
+    assert_eq!(optimized.signal("a"), circuit.signal("a"));
+}
@@ -0,0 +1,52 @@
+use logic::{Circuit, Error, Simulator};
+
+#[test]
+fn combinational_loops_are_rejected() {
+    let circuit = Circuit::from_string("NOT b -> a\na AND 1 -> b").unwrap();
+    assert!(matches!(Simulator::new(circuit), Err(Error::Loop(_))));
+    let circuit = Circuit::from_string("NOT b -> a\nDFF a -> b").unwrap();
+    assert!(Simulator::new(circuit).is_ok());
+}
+
+#[test]
+fn shift_register() {
+    let circuit = Circuit::from_string(
+        "DFF in -> q0
+DFF q0 -> q1
+DFF q1 -> q2
+count AND 1 -> in
+DFF next -> count
+count ADD 1 -> next",
+    )
+    .unwrap();
+    let mut simulator = Simulator::new(circuit).unwrap();
+    simulator.watch("q2");
+    simulator.run(5);
+    simulator.watch("in");
+    simulator.run(2);
+    assert_eq!(simulator.time(), 7);
+    let q2: Vec<_> = simulator
+        .waveform("q2")
+        .unwrap()
+        .iter()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(q2, [0, 0, 0, 0, 1, 0, 1]);
+    assert_eq!(
+        simulator.waveform("in").unwrap(),
+        [None, None, None, None, None, Some(1), Some(0)]
+    );
+    assert_eq!(simulator.waveform("q0"), None);
+    let vcd = simulator.vcd().to_string();
+    assert!(vcd.contains("$var wire 16 \" in $end"));
+    assert!(vcd.contains("#0\nb0 !\nbx \"\n"));
+}
+
+#[test]
+fn undriven_register_inputs() {
+    let circuit = Circuit::from_string("DFF missing -> q").unwrap();
+    let mut simulator = Simulator::new(circuit).unwrap();
+    assert_eq!(simulator.signal("q"), Some(0));
+    simulator.tick();
+    assert_eq!(simulator.signal("q"), None);
+}
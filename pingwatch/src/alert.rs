@@ -0,0 +1,409 @@
+//! Threshold alerts on host state changes.
+//!
+//! `HostAlerts` is the functional core: it folds ping outcomes into a host
+//! state (up, slow, down) and decides, with debouncing, when a state change is
+//! worth an alert. `run_alerts` is the shell: it sits between the workers and
+//! the printer, forwards every event unchanged, and rings the bell or spawns
+//! the configured commands when an alert fires. Duplicate and late replies
+//! say nothing new about a host and are skipped. `CommandBudget` caps the
+//! commands spawned across all hosts, so many hosts going down at once can't
+//! fork a process storm either.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::Write as _,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{event::PingEvent, types};
+
+/// When to alert and what to do about it.
+#[derive(Clone, Debug)]
+pub struct AlertConfig {
+    /// Consecutive failures after which a host is down.
+    pub down_after: u32,
+    /// RTT in milliseconds above which a reply counts as slow; `None`
+    /// disables slow alerts.
+    pub slow_ms: Option<f64>,
+    /// Consecutive slow replies after which a host is slow, and consecutive
+    /// fast replies after which it is no longer slow.
+    pub slow_after: u32,
+    /// Minimum time between two alerts for the same host.
+    pub cooldown: Duration,
+    /// Ring the terminal bell.
+    pub bell: bool,
+    /// Desktop-notification command, split on whitespace and run without a
+    /// shell; the alert message is appended as its last argument.
+    pub notify: Option<String>,
+    /// Shell hook run with `sh -c`.
+    pub exec: Option<String>,
+}
+
+/// What a host currently looks like from its recent pings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostState {
+    Up,
+    Slow,
+    Down,
+}
+
+impl fmt::Display for HostState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Up => "up",
+            Self::Slow => "slow",
+            Self::Down => "down",
+        })
+    }
+}
+
+/// An alert to deliver for one host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub state: HostState,
+    /// RTT of the ping that triggered the alert, if it got a reply.
+    pub rtt_ms: Option<f64>,
+}
+
+/// Commands spawned per [`COMMAND_WINDOW`] across all hosts, at most.
+pub const MAX_COMMANDS: usize = 10;
+/// Window over which [`MAX_COMMANDS`] applies.
+pub const COMMAND_WINDOW: Duration = Duration::from_secs(60);
+
+/// Rate limit on the commands spawned for alerts, whatever the host.
+#[derive(Debug, Default)]
+pub struct CommandBudget {
+    /// When the commands of the current window were spawned.
+    spawned: VecDeque<Instant>,
+}
+
+impl CommandBudget {
+    /// Whether a command may be spawned at `now`, counting it if so.
+    pub fn take(&mut self, now: Instant) -> bool {
+        while self
+            .spawned
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= COMMAND_WINDOW)
+        {
+            self.spawned.pop_front();
+        }
+        if self.spawned.len() >= MAX_COMMANDS {
+            return false;
+        }
+        self.spawned.push_back(now);
+        true
+    }
+}
+
+/// Per-host state machine plus debouncing.
+#[derive(Debug)]
+pub struct HostAlerts {
+    /// `None` until the first transition out of "no pings yet".
+    state: Option<HostState>,
+    failures: u32,
+    slow: u32,
+    fast: u32,
+    /// Last state an alert was delivered for, and when.
+    alerted: Option<(HostState, Instant)>,
+}
+
+impl HostAlerts {
+    pub fn new() -> Self {
+        Self {
+            state: None,
+            failures: 0,
+            slow: 0,
+            fast: 0,
+            alerted: None,
+        }
+    }
+
+    /// Fold one ping outcome (`None` for a failure) observed at `now` into the
+    /// host state and return the alert to deliver, if any.
+    ///
+    /// A host that comes up for the first time is not worth an alert. Within
+    /// `config.cooldown` of the previous alert nothing fires; once it has
+    /// passed, the current state fires if it differs from the last alerted
+    /// one, so a flapping link yields at most one alert per cooldown and the
+    /// final state is never lost.
+    pub fn observe(
+        &mut self,
+        config: &AlertConfig,
+        rtt_ms: Option<f64>,
+        now: Instant,
+    ) -> Option<Alert> {
+        self.state = self.next_state(config, rtt_ms);
+        let state = self.state?;
+        let fire = match self.alerted {
+            None => state != HostState::Up,
+            Some((alerted, at)) => state != alerted && now.duration_since(at) >= config.cooldown,
+        };
+        if !fire {
+            return None;
+        }
+        self.alerted = Some((state, now));
+        Some(Alert { state, rtt_ms })
+    }
+
+    fn next_state(&mut self, config: &AlertConfig, rtt_ms: Option<f64>) -> Option<HostState> {
+        let Some(rtt_ms) = rtt_ms else {
+            self.failures += 1;
+            return if self.failures >= config.down_after {
+                Some(HostState::Down)
+            } else {
+                self.state
+            };
+        };
+        self.failures = 0;
+        let Some(slow_ms) = config.slow_ms else {
+            return Some(HostState::Up);
+        };
+        if rtt_ms > slow_ms {
+            self.slow += 1;
+            self.fast = 0;
+        } else {
+            self.fast += 1;
+            self.slow = 0;
+        }
+        match self.state {
+            _ if self.slow >= config.slow_after => Some(HostState::Slow),
+            Some(HostState::Slow) if self.fast < config.slow_after => Some(HostState::Slow),
+            _ => Some(HostState::Up),
+        }
+    }
+}
+
+/// Forward every event from `rx` to `tx` unchanged, delivering alerts along
+/// the way. Returns when `rx` is exhausted or `tx` is closed.
+pub async fn run_alerts(
+    hosts: Arc<[types::Hostname]>,
+    config: AlertConfig,
+    mut rx: mpsc::Receiver<PingEvent>,
+    tx: mpsc::Sender<PingEvent>,
+) {
+    let mut hosts = hosts.to_vec();
+    let mut alerts: Vec<_> = hosts.iter().map(|_| HostAlerts::new()).collect();
+    let mut addrs: Vec<Option<IpAddr>> = vec![None; hosts.len()];
+    let mut budget = CommandBudget::default();
+    while let Some(ev) = rx.recv().await {
+        let i = ev.idx().as_usize();
+        let outcome = match &ev {
+            PingEvent::HostAdded { host, .. } if i == hosts.len() => {
+                hosts.push(host.clone());
+                alerts.push(HostAlerts::new());
+                addrs.push(None);
+                None
+            }
+            PingEvent::Resolved { addr, .. } | PingEvent::AddressChanged { to: addr, .. } => {
+                if let Some(slot) = addrs.get_mut(i) {
+                    *slot = Some(*addr);
+                }
+                None
+            }
+            PingEvent::Success { rtt, .. } => Some(Some(rtt.as_secs_f64() * 1000.0)),
+            PingEvent::Failure { .. } => Some(None),
+            PingEvent::LateReply { .. }
+            | PingEvent::Duplicate { .. }
+            | PingEvent::ResolutionFailed { .. }
+            | PingEvent::PathMtu { .. }
+            | PingEvent::HostAdded { .. }
+            | PingEvent::HostRemoved { .. } => None,
+        };
+        if let (Some(rtt_ms), Some(host_alerts)) = (outcome, alerts.get_mut(i))
+            && let Some(alert) = host_alerts.observe(&config, rtt_ms, Instant::now())
+        {
+            deliver(&config, &mut budget, &hosts[i], addrs[i], &alert);
+        }
+        if tx.send(ev).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Human-readable one-liner for notifications.
+pub fn message(host: &types::Hostname, alert: &Alert) -> String {
+    match alert.rtt_ms {
+        Some(rtt_ms) => format!("{host} is {} (rtt {rtt_ms:.1}ms)", alert.state),
+        None => format!("{host} is {}", alert.state),
+    }
+}
+
+/// Ring the bell and spawn the configured commands, within `budget`, without
+/// waiting for them. Commands see the alert as `PINGWATCH_HOST`,
+/// `PINGWATCH_ADDR`, `PINGWATCH_STATE`, `PINGWATCH_RTT_MS` and
+/// `PINGWATCH_MESSAGE`; the host name never goes through a shell.
+fn deliver(
+    config: &AlertConfig,
+    budget: &mut CommandBudget,
+    host: &types::Hostname,
+    addr: Option<IpAddr>,
+    alert: &Alert,
+) {
+    if config.bell {
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(b"\x07").and_then(|()| stderr.flush());
+    }
+    let message = message(host, alert);
+    let commands = [
+        config.notify.as_deref().and_then(|notify| {
+            let mut words = notify.split_whitespace();
+            let mut command = tokio::process::Command::new(words.next()?);
+            command.args(words).arg(&message);
+            Some(command)
+        }),
+        config.exec.as_ref().map(|exec| {
+            let mut command = tokio::process::Command::new("sh");
+            command.arg("-c").arg(exec);
+            command
+        }),
+    ];
+    for mut command in commands.into_iter().flatten() {
+        if !budget.take(Instant::now()) {
+            continue;
+        }
+        let child = command
+            .env("PINGWATCH_HOST", host.as_str())
+            .env(
+                "PINGWATCH_ADDR",
+                addr.map(|a| a.to_string()).unwrap_or_default(),
+            )
+            .env("PINGWATCH_STATE", alert.state.to_string())
+            .env(
+                "PINGWATCH_RTT_MS",
+                alert
+                    .rtt_ms
+                    .map(|ms| format!("{ms:.3}"))
+                    .unwrap_or_default(),
+            )
+            .env("PINGWATCH_MESSAGE", &message)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .spawn();
+        // Reap the child in the background so it doesn't linger as a zombie.
+        if let Ok(mut child) = child {
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AlertConfig {
+        AlertConfig {
+            down_after: 3,
+            slow_ms: Some(100.0),
+            slow_after: 2,
+            cooldown: Duration::from_secs(10),
+            bell: false,
+            notify: None,
+            exec: None,
+        }
+    }
+
+    /// Feed outcomes one second apart and collect the alerted states.
+    fn run(config: &AlertConfig, outcomes: &[Option<f64>]) -> Vec<HostState> {
+        let start = Instant::now();
+        let mut alerts = HostAlerts::new();
+        outcomes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &rtt_ms)| {
+                let now = start + Duration::from_secs(i as u64);
+                alerts.observe(config, rtt_ms, now).map(|alert| alert.state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_reply_is_not_an_alert() {
+        assert_eq!(run(&config(), &[Some(1.0), Some(1.0)]), []);
+    }
+
+    #[test]
+    fn down_after_consecutive_failures_then_up() {
+        let config = AlertConfig {
+            cooldown: Duration::ZERO,
+            ..config()
+        };
+        let outcomes = [
+            Some(1.0),
+            None,
+            None,
+            Some(1.0),
+            None,
+            None,
+            None,
+            Some(1.0),
+        ];
+        assert_eq!(run(&config, &outcomes), [HostState::Down, HostState::Up]);
+    }
+
+    #[test]
+    fn slow_after_consecutive_slow_replies() {
+        let config = AlertConfig {
+            cooldown: Duration::ZERO,
+            ..config()
+        };
+        let outcomes = [
+            Some(1.0),
+            Some(150.0),
+            Some(1.0),
+            Some(150.0),
+            Some(150.0),
+            Some(1.0),
+            Some(1.0),
+        ];
+        assert_eq!(run(&config, &outcomes), [HostState::Slow, HostState::Up]);
+    }
+
+    #[test]
+    fn flapping_is_debounced_but_the_final_state_fires() {
+        let config = AlertConfig {
+            down_after: 1,
+            cooldown: Duration::from_secs(5),
+            ..config()
+        };
+        // Down at t=0, flapping until t=6, then steadily up.
+        let outcomes = [
+            None,
+            Some(1.0),
+            None,
+            Some(1.0),
+            None,
+            Some(1.0),
+            None,
+            Some(1.0),
+            Some(1.0),
+        ];
+        assert_eq!(run(&config, &outcomes), [HostState::Down, HostState::Up]);
+    }
+
+    #[test]
+    fn commands_are_capped_across_hosts() {
+        let start = Instant::now();
+        let mut budget = CommandBudget::default();
+        let taken = (0..MAX_COMMANDS + 5).filter(|_| budget.take(start)).count();
+        assert_eq!(taken, MAX_COMMANDS);
+        assert!(!budget.take(start + COMMAND_WINDOW / 2));
+        assert!(budget.take(start + COMMAND_WINDOW));
+    }
+
+    #[test]
+    fn message_mentions_host_state_and_rtt() {
+        let host = "db".parse().unwrap();
+        let alert = Alert {
+            state: HostState::Slow,
+            rtt_ms: Some(250.0),
+        };
+        assert_eq!(message(&host, &alert), "db is slow (rtt 250.0ms)");
+    }
+}
@@ -1,9 +1,14 @@
 use crate::{
     spinner_style::{DEFAULT_SPINNER_STYLE_NAME, SpinnerStyle},
-    types::Hostname,
+    types::{Family, Hostname, OutputMode, SocketOptions, SortOrder, SparklineScale},
 };
 use anyhow::Context as _;
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 /// Config file schema — all fields optional; CLI takes precedence.
 ///
@@ -21,6 +26,107 @@ struct Config {
     /// Spinner style preset name. Animated builds expose the full catalog;
     /// static builds accept only `staticDot`.
     spinner_style: Option<SpinnerStyle>,
+    /// Output mode: `auto`, `tui`, `plain`, `jsonl` or `csv`.
+    output: Option<OutputMode>,
+    /// Number of pings per host before stopping.
+    count: Option<u64>,
+    /// Total run time in milliseconds before stopping.
+    deadline: Option<u64>,
+    /// Number of recent RTTs drawn as a sparkline.
+    sparkline: Option<usize>,
+    /// Sparkline scaling: `host` or `global`.
+    sparkline_scale: Option<SparklineScale>,
+    /// Display order: `none`, `name`, `loss` or `latency`.
+    sort: Option<SortOrder>,
+    /// Pack several hosts per display row.
+    compact: Option<bool>,
+    /// Ping every resolved address of each host instead of the first one.
+    all_addresses: Option<bool>,
+    /// Milliseconds after which a resolved address is resolved again.
+    resolve_interval: Option<u64>,
+    /// Consecutive failures after which a host is resolved again early.
+    resolve_after_failures: Option<u32>,
+    /// Consecutive failures after which a host is down.
+    alert_down_after: Option<u32>,
+    /// RTT in milliseconds above which a reply is slow.
+    alert_slow_ms: Option<u64>,
+    /// Consecutive slow replies after which a host is slow.
+    alert_slow_after: Option<u32>,
+    /// Minimum milliseconds between two alerts for the same host.
+    alert_cooldown: Option<u64>,
+    /// Ring the terminal bell on alerts.
+    alert_bell: Option<bool>,
+    /// Desktop-notification command, given the alert message as last argument.
+    alert_notify: Option<String>,
+    /// Shell hook run on alerts.
+    alert_exec: Option<String>,
+    /// Address to serve OpenMetrics on, e.g. `127.0.0.1:9898`.
+    metrics_listen: Option<SocketAddr>,
+    /// Bytes of payload in ICMP echo requests and UDP datagrams.
+    size: Option<u16>,
+    /// IPv4 TTL or IPv6 hop limit of probes.
+    ttl: Option<u8>,
+    /// IPv4 TOS or IPv6 traffic class of probes.
+    tos: Option<u8>,
+    /// DSCP of probes, the upper six bits of `tos`.
+    dscp: Option<u8>,
+    /// Set don't-fragment on probes.
+    dont_fragment: Option<bool>,
+    /// Discover the path MTU of each host before pinging it.
+    mtu_discover: Option<bool>,
+    /// Named sets of the keys above, selected with `--profile`.
+    profiles: Option<BTreeMap<String, Config>>,
+}
+
+impl Config {
+    /// Apply the profile called `name`: its keys replace the top-level ones.
+    fn with_profile(mut self, name: &str) -> anyhow::Result<Self> {
+        let mut profiles = self.profiles.take().unwrap_or_default();
+        let Some(profile) = profiles.remove(name) else {
+            let known: Vec<_> = profiles.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "config: unknown profile `{name}` (known profiles: {})",
+                if known.is_empty() {
+                    "none".to_owned()
+                } else {
+                    known.join(", ")
+                }
+            );
+        };
+        Ok(Self {
+            hosts: profile.hosts.or(self.hosts),
+            interval: profile.interval.or(self.interval),
+            timeout: profile.timeout.or(self.timeout),
+            spinner_style: profile.spinner_style.or(self.spinner_style),
+            output: profile.output.or(self.output),
+            count: profile.count.or(self.count),
+            deadline: profile.deadline.or(self.deadline),
+            sparkline: profile.sparkline.or(self.sparkline),
+            sparkline_scale: profile.sparkline_scale.or(self.sparkline_scale),
+            sort: profile.sort.or(self.sort),
+            compact: profile.compact.or(self.compact),
+            all_addresses: profile.all_addresses.or(self.all_addresses),
+            resolve_interval: profile.resolve_interval.or(self.resolve_interval),
+            resolve_after_failures: profile
+                .resolve_after_failures
+                .or(self.resolve_after_failures),
+            alert_down_after: profile.alert_down_after.or(self.alert_down_after),
+            alert_slow_ms: profile.alert_slow_ms.or(self.alert_slow_ms),
+            alert_slow_after: profile.alert_slow_after.or(self.alert_slow_after),
+            alert_cooldown: profile.alert_cooldown.or(self.alert_cooldown),
+            alert_bell: profile.alert_bell.or(self.alert_bell),
+            alert_notify: profile.alert_notify.or(self.alert_notify),
+            alert_exec: profile.alert_exec.or(self.alert_exec),
+            metrics_listen: profile.metrics_listen.or(self.metrics_listen),
+            size: profile.size.or(self.size),
+            ttl: profile.ttl.or(self.ttl),
+            tos: profile.tos.or(self.tos),
+            dscp: profile.dscp.or(self.dscp),
+            dont_fragment: profile.dont_fragment.or(self.dont_fragment),
+            mtu_discover: profile.mtu_discover.or(self.mtu_discover),
+            profiles: None,
+        })
+    }
 }
 
 /// Ping one or more hosts simultaneously, showing live status in a TUI.
@@ -34,22 +140,64 @@ struct Config {
 ///   $XDG_CONFIG_HOME/pingwatch/config.toml
 ///   ~/.config/pingwatch/config.toml
 ///
+/// When the hosts come from the config file, changes to them apply while
+/// pingwatch runs; other keys take effect on the next start.
+///
 /// Supported keys:
 ///
-///   hosts        = ["example.com", "8.8.8.8"]   # list of hostnames or IPs
+///   hosts        = ["example.com", "tcp://db:5432"] # hostnames or IPs, optionally with a probe
+///   hosts        = [{ name = "db.example.com", label = "db", group = "backend",
+///                     address = "10.0.0.5", probe = "tcp:5432", family = "ipv4",
+///                     interval = 5000, timeout = 1000, payload_size = 56,
+///                     ttl = 64, dscp = 46, dont_fragment = true }]
+///                                               # or tables; only `name` is required
 ///   interval     = 1000                         # milliseconds between pings
 ///   timeout      = 2000                         # per-ping timeout in milliseconds
 ///   spinner_style = "staticDot"                 # or any animated preset when the feature is enabled
+///   output       = "auto"                       # tui, plain, jsonl or csv
+///   count        = 10                           # stop after this many pings per host
+///   deadline     = 60000                        # stop after this many milliseconds
+///   sparkline    = 30                           # draw the last 30 RTTs next to each host
+///   sparkline_scale = "host"                    # or "global" to share one scale
+///   sort         = "none"                       # or "name", "loss", "latency"
+///   compact      = false                        # several hosts per row
+///   all_addresses = false                       # one bar per resolved address
+///   resolve_interval = 300000                   # milliseconds before resolving hosts again
+///   resolve_after_failures = 3                  # also resolve again after this many failures
+///   alert_down_after = 3                        # failures in a row before a host is down
+///   alert_slow_ms    = 200                      # RTT above which a reply is slow
+///   alert_slow_after = 5                        # slow replies in a row before a host is slow
+///   alert_cooldown   = 30000                    # milliseconds between alerts per host
+///   alert_bell       = true                     # ring the terminal bell on alerts
+///   alert_notify     = "notify-send pingwatch"  # message is appended as last argument
+///   alert_exec       = "~/bin/on-alert"         # run with PINGWATCH_HOST, _ADDR, _STATE,
+///                                               # _RTT_MS and _MESSAGE in the environment
+///   metrics_listen   = "127.0.0.1:9898"         # serve OpenMetrics for scraping
+///   size         = 56                           # payload bytes per ICMP or UDP probe
+///   ttl          = 64                           # TTL / hop limit of probes
+///   tos          = 0                            # TOS / traffic class, or instead:
+///   dscp         = 46                           # DSCP, the upper six bits of the TOS
+///   dont_fragment = false                       # set don't-fragment on probes
+///   mtu_discover = false                        # find each host's path MTU first
+///
+///   [profiles.work]                             # selected with --profile work; any key
+///   hosts = ["vpn.example.com"]                 # above, replacing the top-level value
+///
+/// Sessions saved with --record FILE can be watched again with
+/// `pingwatch replay FILE`, using the display settings above.
 // The derive keeps the clap API intact (including `try_parse_from` used in
 // tests); the inherent `parse(bin_name)` method shadows it for production
 // use and adds config-file resolution.
 #[derive(clap::Parser)]
 #[command(version, verbatim_doc_comment)]
 pub struct Args {
-    /// Hosts to ping (1-10 hostnames or IP addresses)
+    /// Hosts to ping (hostnames or IP addresses), optionally prefixed with a
+    /// probe: icmp://HOST, icmp-dgram://HOST, tcp://HOST:PORT or udp://HOST:PORT;
+    /// `-` reads more hosts from stdin, one per line. Duplicate and
+    /// out-of-order replies are counted for ICMP and UDP probes only: a TCP
+    /// handshake completes once, so tcp:// hosts never report them
     // `required` is omitted here so the config file can supply hosts;
     // the constraint is re-enforced in `parse()` after merging.
-    #[arg(num_args = 0..=10)]
     pub hosts: Vec<Hostname>,
     /// Interval between pings in milliseconds
     #[arg(short, long, default_value = "1000", value_parser = parse_millis)]
@@ -60,9 +208,171 @@ pub struct Args {
     /// Spinner style preset from cli-spinners
     #[arg(long, value_enum, default_value = DEFAULT_SPINNER_STYLE_NAME)]
     pub spinner_style: SpinnerStyle,
+    /// Output mode; `auto` picks the TUI when stdout is a terminal and plain
+    /// log lines otherwise
+    #[arg(short, long, value_enum, default_value = "auto")]
+    pub output: OutputMode,
+    /// Stop each host after this many pings
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: Option<u64>,
+    /// Stop after this many milliseconds, whatever the number of pings sent
+    #[arg(short = 'w', long, value_parser = parse_millis)]
+    pub deadline: Option<Duration>,
+    /// Draw a sparkline of the last N RTTs next to each host
+    #[arg(long, value_name = "N", value_parser = parse_sparkline_width)]
+    pub sparkline: Option<usize>,
+    /// Scale sparklines per host or across all hosts
+    #[arg(long, value_enum, default_value = "host")]
+    pub sparkline_scale: SparklineScale,
+    /// Order of hosts in the live display; failing hosts are pinned to the
+    /// top unless the order is `none` (press `s` to cycle)
+    #[arg(long, value_enum, default_value = "none")]
+    pub sort: SortOrder,
+    /// Pack several hosts per row in the live display (press `c` to toggle)
+    #[arg(long)]
+    pub compact: bool,
+    /// Start in the path view of the first host, like mtr (press `p` to
+    /// toggle, Tab to pick another host)
+    #[arg(long)]
+    pub path: bool,
+    /// Only use IPv4 addresses
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    pub ipv4: bool,
+    /// Only use IPv6 addresses
+    #[arg(short = '6', long)]
+    pub ipv6: bool,
+    /// Ping every resolved address of each host, one bar per address
+    #[arg(long)]
+    pub all_addresses: bool,
+    /// Resolve hosts again after this many milliseconds
+    #[arg(long, value_name = "MS", default_value = "300000", value_parser = parse_millis)]
+    pub resolve_interval: Duration,
+    /// Resolve a host again after this many failed pings in a row
+    #[arg(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub resolve_after_failures: u32,
+    /// Alert when a host fails this many pings in a row
+    #[arg(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u32).range(1..), help_heading = "Alerts")]
+    pub alert_down_after: u32,
+    /// Alert when replies take longer than this many milliseconds
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = "Alerts")]
+    pub alert_slow_ms: Option<Duration>,
+    /// Number of slow replies in a row before alerting
+    #[arg(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u32).range(1..), help_heading = "Alerts")]
+    pub alert_slow_after: u32,
+    /// Minimum milliseconds between two alerts for the same host
+    #[arg(long, value_name = "MS", default_value = "30000", value_parser = parse_millis, help_heading = "Alerts")]
+    pub alert_cooldown: Duration,
+    /// Ring the terminal bell on alerts
+    #[arg(long, overrides_with = "no_alert_bell", help_heading = "Alerts")]
+    pub alert_bell: bool,
+    /// Don't ring the bell, even if the config file asks to
+    #[arg(long, overrides_with = "alert_bell", help_heading = "Alerts")]
+    pub no_alert_bell: bool,
+    /// Desktop-notification command, run without a shell; the alert message
+    /// is appended as its last argument
+    #[arg(long, value_name = "COMMAND", help_heading = "Alerts")]
+    pub alert_notify: Option<String>,
+    /// Shell command run on alerts with PINGWATCH_HOST, PINGWATCH_ADDR,
+    /// PINGWATCH_STATE, PINGWATCH_RTT_MS and PINGWATCH_MESSAGE set
+    #[arg(long, value_name = "COMMAND", help_heading = "Alerts")]
+    pub alert_exec: Option<String>,
+    /// Serve per-host metrics in OpenMetrics text format on this address
+    #[arg(long, value_name = "ADDR:PORT")]
+    pub metrics_listen: Option<SocketAddr>,
+    /// Bytes of payload in ICMP echo requests and UDP datagrams
+    #[arg(short, long, value_name = "BYTES")]
+    pub size: Option<u16>,
+    /// IPv4 TTL or IPv6 hop limit of probes
+    #[arg(long, value_name = "HOPS", value_parser = clap::value_parser!(u8).range(1..))]
+    pub ttl: Option<u8>,
+    /// IPv4 type of service or IPv6 traffic class of probes
+    #[arg(long, conflicts_with = "dscp")]
+    pub tos: Option<u8>,
+    /// DSCP of probes, the upper six bits of the type of service
+    #[arg(long, value_parser = clap::value_parser!(u8).range(..64))]
+    pub dscp: Option<u8>,
+    /// Set don't-fragment on probes
+    #[arg(long)]
+    pub dont_fragment: bool,
+    /// Find the largest packet that reaches each host with don't-fragment set
+    /// and report its path MTU before pinging
+    #[arg(long)]
+    pub mtu_discover: bool,
+    /// Use this profile from the config file on top of its top-level keys
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Write every event with its time to FILE, for `replay`
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// `-` was among the hosts: more hosts arrive on stdin.
+    #[arg(skip)]
+    pub hosts_from_stdin: bool,
+    /// Set when the hosts come from the config file, to pick up changes to
+    /// them while running.
+    #[arg(skip)]
+    pub watch: Option<ConfigWatch>,
+}
+
+/// How to read the hosts of the config file again, the way `Args::parse`
+/// read them at the start.
+#[derive(Clone, Debug)]
+pub struct ConfigWatch {
+    path: PathBuf,
+    profile: Option<String>,
+    family: Option<Family>,
+}
+
+impl ConfigWatch {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The hosts the config file names now; none once it's gone.
+    pub fn hosts(&self) -> anyhow::Result<Vec<Hostname>> {
+        let config = load_config(&self.path)?;
+        let config = match &self.profile {
+            Some(name) => config.with_profile(name)?,
+            None => config,
+        };
+        Ok(with_family(
+            group_hosts(config.hosts.unwrap_or_default()),
+            self.family,
+        ))
+    }
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Show a session saved with --record again
+    Replay(Replay),
+}
+
+/// Options of `pingwatch replay`.
+#[derive(clap::Args)]
+pub struct Replay {
+    /// Recording written by --record
+    pub file: PathBuf,
+    /// Play this many times faster than recorded
+    #[arg(long, value_name = "FACTOR", default_value = "1", value_parser = parse_speed)]
+    pub speed: f64,
+    /// Print the statistics right away instead of replaying the display;
+    /// implied when stdout is not a terminal
+    #[arg(long)]
+    pub summary: bool,
 }
 
 impl Args {
+    /// Socket options of probes for hosts that don't set their own.
+    pub fn socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            ttl: self.ttl,
+            tos: self.tos.or(self.dscp.map(|dscp| dscp << 2)),
+            dont_fragment: self.dont_fragment,
+        }
+    }
+
     /// Parse and resolve configuration from `std::env::args_os()` and the XDG
     /// config file. Delegates to [`Args::parse_from`].
     pub fn parse(bin_name: &str) -> anyhow::Result<Self> {
@@ -71,7 +381,8 @@ impl Args {
 
     /// Parse and resolve configuration from the given argv and the XDG config file.
     ///
-    /// Resolution order: CLI > config file > built-in defaults.
+    /// Resolution order: CLI > selected config profile > config file >
+    /// built-in defaults.
     /// Accepts any iterator of `OsString`-convertible values so tests can supply
     /// a controlled argv without touching `std::env::args`.
     pub fn parse_from<I, T>(bin_name: &str, argv: I) -> anyhow::Result<Self>
@@ -79,7 +390,8 @@ impl Args {
         I: IntoIterator<Item = T>,
         T: Into<std::ffi::OsString> + Clone,
     {
-        let config = load_config(bin_name)?;
+        let config_path = config_path(bin_name);
+        let config = load_config(&config_path)?;
 
         let matches = <Self as clap::CommandFactory>::command()
             .try_get_matches_from(argv)
@@ -92,48 +404,238 @@ impl Args {
                 anyhow::Error::from(e)
             })?;
 
+        let profile = matches.get_one::<String>("profile").cloned();
+        let config = match &profile {
+            Some(name) => config.with_profile(name)?,
+            None => config,
+        };
+
         // Hosts: CLI wins if any were provided, otherwise fall back to config.
-        let cli_hosts: Vec<Hostname> = matches
+        // `-` stands for the hosts on stdin, which may still be coming.
+        let mut cli_hosts: Vec<Hostname> = matches
             .get_many::<Hostname>("hosts")
             .into_iter()
             .flatten()
             .cloned()
             .collect();
+        let hosts_from_stdin = cli_hosts.iter().any(|h| h.as_str() == "-");
+        cli_hosts.retain(|h| h.as_str() != "-");
+        let hosts_from_config = cli_hosts.is_empty() && !hosts_from_stdin;
 
-        let hosts = if !cli_hosts.is_empty() {
-            cli_hosts
+        let hosts = if hosts_from_config {
+            group_hosts(config.hosts.unwrap_or_default())
         } else {
-            config.hosts.unwrap_or_default()
+            cli_hosts
         };
 
+        // -4/-6 override the per-host `family` from the config file.
+        let ipv4 = matches.get_flag("ipv4");
+        let ipv6 = matches.get_flag("ipv6");
+        let family = match (ipv4, ipv6) {
+            (true, _) => Some(Family::Ipv4),
+            (_, true) => Some(Family::Ipv6),
+            _ => None,
+        };
+        let hosts = with_family(hosts, family);
+
+        // A replay takes its hosts from the recording.
+        let command = match matches.subcommand_name() {
+            Some(_) => Some(<Command as clap::FromArgMatches>::from_arg_matches(
+                &matches,
+            )?),
+            None => None,
+        };
         anyhow::ensure!(
-            !hosts.is_empty(),
-            "at least one host is required (provide on the CLI or in the config file)"
-        );
-        anyhow::ensure!(
-            hosts.len() <= 10,
-            "at most 10 hosts allowed, got {}",
-            hosts.len()
+            !hosts.is_empty() || hosts_from_stdin || command.is_some(),
+            "at least one host is required (provide on the CLI, in the config file or on stdin with `-`)"
         );
+        let watch = (hosts_from_config && command.is_none()).then(|| ConfigWatch {
+            path: config_path,
+            profile: profile.clone(),
+            family,
+        });
 
         let interval = resolve_duration(&matches, "interval", config.interval, 1000)?;
         let timeout = resolve_duration(&matches, "timeout", config.timeout, 2000)?;
-        let spinner_style = resolve_spinner_style(
+        let spinner_style = resolve_value(
             &matches,
             "spinner_style",
             config.spinner_style,
             SpinnerStyle::default(),
         );
+        let output = resolve_value(&matches, "output", config.output, OutputMode::default());
+        // The CLI parsers already reject zero, so a zero here came from the config.
+        let count = resolve_optional(&matches, "count", config.count);
+        anyhow::ensure!(count != Some(0), "config: `count` must be at least 1");
+        let deadline = resolve_optional(
+            &matches,
+            "deadline",
+            config.deadline.map(Duration::from_millis),
+        );
+        anyhow::ensure!(
+            deadline != Some(Duration::ZERO),
+            "config: `deadline` must be at least 1 ms"
+        );
+        let sparkline = resolve_optional(&matches, "sparkline", config.sparkline);
+        anyhow::ensure!(
+            sparkline != Some(0),
+            "config: `sparkline` must be at least 1"
+        );
+        let sparkline_scale = resolve_value(
+            &matches,
+            "sparkline_scale",
+            config.sparkline_scale,
+            SparklineScale::default(),
+        );
+        let sort = resolve_value(&matches, "sort", config.sort, SortOrder::default());
+        let compact = resolve_value(&matches, "compact", config.compact, false);
+
+        let all_addresses = resolve_value(&matches, "all_addresses", config.all_addresses, false);
+        let resolve_interval = resolve_duration(
+            &matches,
+            "resolve_interval",
+            config.resolve_interval,
+            300_000,
+        )?;
+        let resolve_after_failures = resolve_value(
+            &matches,
+            "resolve_after_failures",
+            config.resolve_after_failures,
+            3,
+        );
+        anyhow::ensure!(
+            resolve_after_failures > 0,
+            "config: `resolve_after_failures` must be at least 1"
+        );
+        let alert_down_after =
+            resolve_value(&matches, "alert_down_after", config.alert_down_after, 3);
+        anyhow::ensure!(
+            alert_down_after > 0,
+            "config: `alert_down_after` must be at least 1"
+        );
+        let alert_slow_ms = resolve_optional(
+            &matches,
+            "alert_slow_ms",
+            config.alert_slow_ms.map(Duration::from_millis),
+        );
+        anyhow::ensure!(
+            alert_slow_ms != Some(Duration::ZERO),
+            "config: `alert_slow_ms` must be at least 1 ms"
+        );
+        let alert_slow_after =
+            resolve_value(&matches, "alert_slow_after", config.alert_slow_after, 3);
+        anyhow::ensure!(
+            alert_slow_after > 0,
+            "config: `alert_slow_after` must be at least 1"
+        );
+        let alert_cooldown =
+            resolve_duration(&matches, "alert_cooldown", config.alert_cooldown, 30000)?;
+        let no_alert_bell = matches.get_flag("no_alert_bell");
+        let alert_bell =
+            !no_alert_bell && resolve_value(&matches, "alert_bell", config.alert_bell, false);
+        let alert_notify = resolve_optional(&matches, "alert_notify", config.alert_notify);
+        let alert_exec = resolve_optional(&matches, "alert_exec", config.alert_exec);
+        let metrics_listen = resolve_optional(&matches, "metrics_listen", config.metrics_listen);
+        let size = resolve_optional(&matches, "size", config.size);
+        let ttl = resolve_optional(&matches, "ttl", config.ttl);
+        anyhow::ensure!(ttl != Some(0), "config: `ttl` must be at least 1");
+        // --tos and --dscp both set the same bits, so either on the CLI
+        // replaces both from the config.
+        let (tos, dscp) =
+            if matches.value_source("tos").is_some() || matches.value_source("dscp").is_some() {
+                (
+                    matches.get_one::<u8>("tos").copied(),
+                    matches.get_one::<u8>("dscp").copied(),
+                )
+            } else {
+                anyhow::ensure!(
+                    config.tos.is_none() || config.dscp.is_none(),
+                    "config: `tos` conflicts with `dscp`"
+                );
+                (config.tos, config.dscp)
+            };
+        anyhow::ensure!(
+            dscp.is_none_or(|dscp| dscp < 64),
+            "config: `dscp` must be at most 63"
+        );
+        let dont_fragment = resolve_value(&matches, "dont_fragment", config.dont_fragment, false);
+        let mtu_discover = resolve_value(&matches, "mtu_discover", config.mtu_discover, false);
 
         Ok(Args {
             hosts,
             interval,
             timeout,
             spinner_style,
+            output,
+            count,
+            deadline,
+            sparkline,
+            sparkline_scale,
+            sort,
+            compact,
+            path: matches.get_flag("path"),
+            ipv4,
+            ipv6,
+            all_addresses,
+            resolve_interval,
+            resolve_after_failures,
+            alert_down_after,
+            alert_slow_ms,
+            alert_slow_after,
+            alert_cooldown,
+            alert_bell,
+            no_alert_bell,
+            alert_notify,
+            alert_exec,
+            metrics_listen,
+            size,
+            ttl,
+            tos,
+            dscp,
+            dont_fragment,
+            mtu_discover,
+            profile,
+            record: matches.get_one::<PathBuf>("record").cloned(),
+            command,
+            hosts_from_stdin,
+            watch,
         })
     }
 }
 
+/// Restrict every host to `family`, as -4/-6 ask.
+fn with_family(hosts: Vec<Hostname>, family: Option<Family>) -> Vec<Hostname> {
+    match family {
+        Some(family) => hosts.into_iter().map(|h| h.with_family(family)).collect(),
+        None => hosts,
+    }
+}
+
+/// Move hosts of the same group next to each other, groups in order of first
+/// appearance, so the display can head each group once. Ungrouped hosts come
+/// first, where no heading would be mistaken for theirs.
+fn group_hosts(hosts: Vec<Hostname>) -> Vec<Hostname> {
+    let mut groups: Vec<String> = Vec::new();
+    let mut keyed: Vec<(usize, Hostname)> = hosts
+        .into_iter()
+        .map(|host| {
+            let key = match host.group() {
+                None => 0,
+                Some(group) => match groups.iter().position(|g| g == group) {
+                    Some(i) => i + 1,
+                    None => {
+                        groups.push(group.to_owned());
+                        groups.len()
+                    }
+                },
+            };
+            (key, host)
+        })
+        .collect();
+    keyed.sort_by_key(|&(key, _)| key);
+    keyed.into_iter().map(|(_, host)| host).collect()
+}
+
 /// Resolve a timing argument: CLI (explicit) > config file value > built-in default.
 ///
 /// Checks `ValueSource` to distinguish an explicit `--flag VALUE` from a value
@@ -163,34 +665,47 @@ fn resolve_duration(
     Ok(Duration::from_millis(default_ms))
 }
 
-/// Resolve an enum argument: CLI (explicit) > config file value > built-in default.
-fn resolve_spinner_style(
+/// Resolve any argument: CLI (explicit) > config file value > built-in default.
+fn resolve_value<T>(
     matches: &clap::ArgMatches,
     name: &str,
-    config_style: Option<SpinnerStyle>,
-    default_style: SpinnerStyle,
-) -> SpinnerStyle {
+    config_value: Option<T>,
+    default_value: T,
+) -> T
+where
+    T: Clone + Send + Sync + 'static,
+{
+    resolve_optional(matches, name, config_value).unwrap_or(default_value)
+}
+
+/// Resolve an argument without a default: CLI (explicit) > config file value.
+fn resolve_optional<T>(matches: &clap::ArgMatches, name: &str, config_value: Option<T>) -> Option<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
     use clap::parser::ValueSource;
 
     if matches.value_source(name) == Some(ValueSource::CommandLine) {
-        return *matches
-            .get_one::<SpinnerStyle>(name)
-            .expect("CommandLine source guarantees a value");
+        return matches.get_one::<T>(name).cloned();
     }
 
-    config_style.unwrap_or(default_style)
+    config_value
+}
+
+/// Where the config file of `bin_name` lives.
+fn config_path(bin_name: &str) -> PathBuf {
+    xdg_config_dir().join(bin_name).join("config.toml")
 }
 
 /// Load and deserialize the TOML config file.
 ///
 /// Returns `Ok(Config::default())` when the file does not exist so callers
 /// can treat absence as "no overrides" without special-casing.
-fn load_config(bin_name: &str) -> anyhow::Result<Config> {
-    let path = xdg_config_dir().join(bin_name).join("config.toml");
+fn load_config(path: &Path) -> anyhow::Result<Config> {
     if !path.exists() {
         return Ok(Config::default());
     }
-    let content = std::fs::read_to_string(&path)
+    let content = std::fs::read_to_string(path)
         .with_context(|| format!("reading config file {}", path.display()))?;
     toml_edit::de::from_str(&content)
         .with_context(|| format!("parsing config file {}", path.display()))
@@ -219,3 +734,23 @@ fn parse_millis(s: &str) -> Result<Duration, String> {
     }
     Ok(Duration::from_millis(ms))
 }
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err("value must be a positive number".to_string());
+    }
+    Ok(speed)
+}
+
+fn parse_sparkline_width(s: &str) -> Result<usize, String> {
+    let width: usize = s
+        .parse()
+        .map_err(|e: std::num::ParseIntError| e.to_string())?;
+    if width == 0 {
+        return Err("value must be at least 1".to_string());
+    }
+    Ok(width)
+}
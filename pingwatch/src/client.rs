@@ -1,43 +1,442 @@
-use std::net::IpAddr;
+use std::{
+    collections::{HashMap, hash_map},
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
 
-/// Shared ICMP clients, one per protocol version.
+use surge_ping::{IcmpPacket, PingIdentifier, PingSequence, SurgeError};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    probe::Stray,
+    types::{self, SocketOptions},
+};
+
+/// Shared ICMP clients, one per protocol version, socket kind and set of
+/// socket options.
 ///
-/// Created once in [`crate::run`] and cloned into each worker task.
-/// `surge_ping::Client` is `Arc`-backed internally, so cloning is cheap
-/// and all workers share the same underlying socket per protocol.
+/// Created once in [`crate::run`], extended for hosts joining later, and
+/// cloned into each worker task.
+/// `IcmpClient` is `Arc`-backed internally, so cloning is cheap and all
+/// workers share the same underlying socket per protocol.
+/// Only the socket kinds some host actually probes with are opened, so TCP
+/// and UDP probes work without any ICMP privileges.
 #[derive(Clone)]
 pub struct PingClients {
-    inner: std::sync::Arc<Inner>,
+    inner: std::sync::Arc<[Entry]>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    probe: types::Probe,
+    options: SocketOptions,
+    pair: Pair,
 }
 
 #[derive(Clone)]
-struct Inner {
-    v4: surge_ping::Client,
-    v6: surge_ping::Client,
+struct Pair {
+    v4: IcmpClient,
+    v6: IcmpClient,
+}
+
+impl Pair {
+    /// Open both protocol versions with the given socket kind and options.
+    /// surge-ping falls back to the other kind when the kernel refuses this
+    /// one.
+    fn new(sock_type: socket2::Type, options: SocketOptions) -> io::Result<Self> {
+        let client = |kind, v6| {
+            let client = IcmpClient::new(
+                &surge_ping::Config::builder()
+                    .kind(kind)
+                    .sock_type_hint(sock_type)
+                    .build(),
+            )?;
+            apply(&client, options, v6)?;
+            Ok::<_, io::Error>(client)
+        };
+        Ok(Self {
+            v4: client(surge_ping::ICMP::V4, false)?,
+            v6: client(surge_ping::ICMP::V6, true)?,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn apply(client: &IcmpClient, options: SocketOptions, v6: bool) -> io::Result<()> {
+    if options == SocketOptions::default() {
+        return Ok(());
+    }
+    let fd = client.socket.get_native_sock();
+    // SAFETY: the client owns the socket and outlives this borrow.
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    options.apply(socket2::SockRef::from(&fd), v6)
+}
+
+#[cfg(not(unix))]
+fn apply(_: &IcmpClient, options: SocketOptions, _: bool) -> io::Result<()> {
+    if options == SocketOptions::default() {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::Unsupported.into())
+    }
 }
 
 impl PingClients {
-    pub fn new() -> std::io::Result<Self> {
+    /// Open the ICMP sockets needed for `uses`, the probe kind and socket
+    /// options of every host.
+    pub fn new(uses: impl IntoIterator<Item = (types::Probe, SocketOptions)>) -> io::Result<Self> {
+        Self {
+            inner: std::sync::Arc::from([]),
+        }
+        .with(uses)
+    }
+
+    /// These clients plus the ICMP sockets `uses` needs on top of them, for
+    /// hosts that join while running. Sockets already open are shared.
+    pub fn with(
+        &self,
+        uses: impl IntoIterator<Item = (types::Probe, SocketOptions)>,
+    ) -> io::Result<Self> {
+        let mut entries: Vec<Entry> = self.inner.to_vec();
+        for (probe, options) in uses {
+            let sock_type = match probe {
+                types::Probe::Icmp => socket2::Type::RAW,
+                types::Probe::IcmpDgram => socket2::Type::DGRAM,
+                types::Probe::Tcp(_) | types::Probe::Udp(_) => continue,
+            };
+            if !entries
+                .iter()
+                .any(|e| e.probe == probe && e.options == options)
+            {
+                entries.push(Entry {
+                    probe,
+                    options,
+                    pair: Pair::new(sock_type, options)?,
+                });
+            }
+        }
         Ok(Self {
-            inner: std::sync::Arc::new(Inner {
-                v4: surge_ping::Client::new(
-                    &surge_ping::Config::builder()
-                        .kind(surge_ping::ICMP::V4)
-                        .build(),
-                )?,
-                v6: surge_ping::Client::new(
-                    &surge_ping::Config::builder()
-                        .kind(surge_ping::ICMP::V6)
-                        .build(),
-                )?,
+            inner: entries.into(),
+        })
+    }
+
+    /// The client for `addr` with the socket kind `probe` asks for and these
+    /// `options`, or `None` for non-ICMP probes and for combinations no host
+    /// was created with.
+    pub(crate) fn for_addr(
+        &self,
+        probe: types::Probe,
+        options: SocketOptions,
+        addr: IpAddr,
+    ) -> Option<&IcmpClient> {
+        let entry = self
+            .inner
+            .iter()
+            .find(|e| e.probe == probe && e.options == options)?;
+        Some(match addr {
+            IpAddr::V4(_) => &entry.pair.v4,
+            IpAddr::V6(_) => &entry.pair.v6,
+        })
+    }
+}
+
+/// An ICMP reply and when it arrived.
+type Reply = (Instant, IcmpPacket);
+
+/// Host, identifier where the socket keeps it, and sequence number.
+type ReplyKey = (IpAddr, Option<u16>, u16);
+
+/// One ICMP socket and the task reading it.
+///
+/// Like `surge_ping::Client`, it hands each reply to the round trip waiting
+/// for its host, identifier and sequence number. Unlike it, a reply nobody
+/// waits for any more -- a duplicate, or one after the round trip gave up --
+/// goes to the stray listener for its host and identifier, if there is one,
+/// rather than being dropped.
+#[derive(Clone)]
+pub struct IcmpClient {
+    socket: surge_ping::AsyncSocket,
+    routes: Arc<Mutex<Routes>>,
+    _recv: Arc<RecvTask>,
+}
+
+#[derive(Default)]
+struct Routes {
+    waiters: HashMap<ReplyKey, oneshot::Sender<Reply>>,
+    strays: HashMap<(IpAddr, Option<u16>), mpsc::Sender<Stray>>,
+}
+
+/// Stops reading the socket once the last client is gone.
+struct RecvTask(tokio::task::JoinHandle<()>);
+
+impl Drop for RecvTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl IcmpClient {
+    fn new(config: &surge_ping::Config) -> io::Result<Self> {
+        let socket = surge_ping::AsyncSocket::new(config)?;
+        let routes = Arc::default();
+        let recv = tokio::spawn(receive(socket.clone(), Arc::clone(&routes)));
+        Ok(Self {
+            socket,
+            routes,
+            _recv: Arc::new(RecvTask(recv)),
+        })
+    }
+
+    /// Send an echo request with `id` and `seq` to `addr` and wait up to
+    /// `timeout` for what the host answers: an echo reply, or an ICMP error
+    /// about the request.
+    pub async fn ping(
+        &self,
+        addr: IpAddr,
+        id: PingIdentifier,
+        seq: u16,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<(IcmpPacket, Duration), SurgeError> {
+        let kernel_ident = kernel_sets_ident(self.socket.get_type());
+        let key = (addr, (!kernel_ident).then_some(id.0), seq);
+        let (tx, rx) = oneshot::channel();
+        match lock(&self.routes).waiters.entry(key) {
+            hash_map::Entry::Occupied(_) => {
+                return Err(SurgeError::IdenticalRequests {
+                    host: addr,
+                    ident: key.1.map(PingIdentifier),
+                    seq: PingSequence(seq),
+                });
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(tx);
+            }
+        }
+        let mut waiter = Waiter {
+            routes: Arc::clone(&self.routes),
+            key,
+            rx,
+        };
+        let mut packet = echo_request(addr.is_ipv6(), kernel_ident, id.0, seq, payload);
+        let sent = Instant::now();
+        self.socket
+            .send_to(&mut packet, &SocketAddr::new(addr, 0))
+            .await?;
+        match tokio::time::timeout(timeout, &mut waiter.rx).await {
+            Ok(Ok((at, packet))) => Ok((packet, at.saturating_duration_since(sent))),
+            Ok(Err(_)) => Err(SurgeError::NetworkError),
+            Err(_) => Err(SurgeError::Timeout {
+                seq: PingSequence(seq),
             }),
+        }
+    }
+
+    /// Pass the replies from `addr` to probes with `id` that no round trip
+    /// waits for to `strays`, until the returned listener is dropped.
+    pub fn listen(
+        &self,
+        addr: IpAddr,
+        id: PingIdentifier,
+        strays: mpsc::Sender<Stray>,
+    ) -> Listener {
+        let key = (
+            addr,
+            (!kernel_sets_ident(self.socket.get_type())).then_some(id.0),
+        );
+        lock(&self.routes).strays.insert(key, strays.clone());
+        Listener {
+            routes: Arc::clone(&self.routes),
+            key,
+            strays,
+        }
+    }
+}
+
+/// A round trip waiting for its reply; stops waiting when dropped.
+struct Waiter {
+    routes: Arc<Mutex<Routes>>,
+    key: ReplyKey,
+    rx: oneshot::Receiver<Reply>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.rx.close();
+        // The receiving task removed this waiter already if a reply came;
+        // another round trip may have taken its place since.
+        if let hash_map::Entry::Occupied(entry) = lock(&self.routes).waiters.entry(self.key)
+            && entry.get().is_closed()
+        {
+            entry.remove();
+        }
+    }
+}
+
+/// Where stray replies for one host and identifier go, until dropped.
+pub struct Listener {
+    routes: Arc<Mutex<Routes>>,
+    key: (IpAddr, Option<u16>),
+    strays: mpsc::Sender<Stray>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let hash_map::Entry::Occupied(entry) = lock(&self.routes).strays.entry(self.key)
+            && entry.get().same_channel(&self.strays)
+        {
+            entry.remove();
+        }
+    }
+}
+
+fn lock(routes: &Mutex<Routes>) -> MutexGuard<'_, Routes> {
+    // The maps stay consistent whatever panicked while holding the lock.
+    routes
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether the kernel picks the identifier of echo requests, as it does for
+/// Linux ICMP datagram sockets; replies then carry that identifier instead.
+fn kernel_sets_ident(sock_type: socket2::Type) -> bool {
+    sock_type == socket2::Type::DGRAM && cfg!(any(target_os = "linux", target_os = "android"))
+}
+
+/// Read replies off `socket` and route them, until the last client is gone.
+async fn receive(socket: surge_ping::AsyncSocket, routes: Arc<Mutex<Routes>>) {
+    let kernel_ident = kernel_sets_ident(socket.get_type());
+    let mut buf = [0; 2048];
+    loop {
+        let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        let at = Instant::now();
+        let Some(packet) = decode(&socket, &buf[..len], from.ip()) else {
+            continue;
+        };
+        let ident = (!kernel_ident).then_some(packet.get_identifier().0);
+        let seq = packet.get_sequence().0;
+        let mut routes = lock(&routes);
+        if let Some(waiter) = routes.waiters.remove(&(from.ip(), ident, seq)) {
+            // The round trip may have given up meanwhile.
+            let _ = waiter.send((at, packet));
+        } else if is_echo_reply(&packet)
+            && let Some(strays) = routes.strays.get(&(from.ip(), ident))
+        {
+            // A full channel costs a count, never a reply that counts.
+            let _ = strays.try_send(Stray { seq, at });
+        }
+    }
+}
+
+fn decode(socket: &surge_ping::AsyncSocket, message: &[u8], from: IpAddr) -> Option<IcmpPacket> {
+    match from {
+        IpAddr::V4(from) => {
+            let IpAddr::V4(local) = socket.local_addr().ok()?.ip() else {
+                return None;
+            };
+            surge_ping::Icmpv4Packet::decode(message, socket.get_type(), from, local)
+                .ok()
+                .map(IcmpPacket::V4)
+        }
+        IpAddr::V6(from) => surge_ping::Icmpv6Packet::decode(message, from)
+            .ok()
+            .map(IcmpPacket::V6),
+    }
+}
+
+fn is_echo_reply(packet: &IcmpPacket) -> bool {
+    match packet {
+        IcmpPacket::V4(packet) => packet.get_icmp_type().0 == 0,
+        IcmpPacket::V6(packet) => packet.get_icmpv6_type().0 == 129,
+    }
+}
+
+/// An echo request as the socket takes it: the kernel fills in the
+/// identifier and checksum for Linux ICMP datagram sockets, and the checksum
+/// of every ICMPv6 message.
+fn echo_request(v6: bool, kernel_ident: bool, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let id = if kernel_ident && !v6 { 0 } else { id };
+    let mut packet = vec![if v6 { 128 } else { 8 }, 0, 0, 0];
+    packet.extend(id.to_be_bytes());
+    packet.extend(seq.to_be_bytes());
+    packet.extend(payload);
+    if !v6 && !kernel_ident {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// The Internet checksum of RFC 1071.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| {
+            u32::from(u16::from_be_bytes([
+                pair[0],
+                pair.get(1).copied().unwrap_or(0),
+            ]))
         })
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
     }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub(crate) fn for_addr(&self, addr: IpAddr) -> &surge_ping::Client {
-        match addr {
-            IpAddr::V4(_) => &self.inner.v4,
-            IpAddr::V6(_) => &self.inner.v6,
+    #[test]
+    fn echo_requests_checksum_to_zero() {
+        let packet = echo_request(false, false, 0x1234, 7, &[1, 2, 3]);
+        assert_eq!(&packet[..2], [8, 0]);
+        assert_eq!(&packet[4..8], [0x12, 0x34, 0, 7]);
+        assert_eq!(checksum(&packet), 0);
+        // The kernel fills both in for datagram sockets.
+        assert_eq!(&echo_request(false, true, 0x1234, 7, &[])[2..6], [0; 4]);
+    }
+
+    // A second reply to an answered request reaches the listener. Needs a
+    // raw ICMP socket, so it checks nothing where the kernel refuses one.
+    #[tokio::test]
+    async fn replies_nobody_waits_for_are_strays() {
+        let Ok(clients) = PingClients::new([(types::Probe::Icmp, SocketOptions::default())]) else {
+            return;
+        };
+        let localhost = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+        let client = clients
+            .for_addr(types::Probe::Icmp, SocketOptions::default(), localhost)
+            .unwrap();
+        if client.socket.get_type() != socket2::Type::RAW {
+            return;
         }
+        let id = PingIdentifier(0x4242);
+        let (tx, mut strays) = mpsc::channel(4);
+        let _listener = client.listen(localhost, id, tx);
+        let timeout = Duration::from_secs(1);
+        assert!(client.ping(localhost, id, 5, &[], timeout).await.is_ok());
+
+        let mut reply = echo_request(false, false, id.0, 5, &[]);
+        reply[0] = 0;
+        reply[2..4].fill(0);
+        let checksum = checksum(&reply);
+        reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+        let sender = socket2::Socket::new(
+            socket2::Domain::IPV4,
+            socket2::Type::RAW,
+            Some(socket2::Protocol::ICMPV4),
+        )
+        .unwrap();
+        sender
+            .send_to(&reply, &SocketAddr::new(localhost, 0).into())
+            .unwrap();
+        let stray = tokio::time::timeout(timeout, strays.recv()).await.unwrap();
+        assert_eq!(stray.map(|stray| stray.seq), Some(5));
     }
 }
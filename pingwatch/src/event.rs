@@ -2,7 +2,11 @@ use std::{io, net::IpAddr, time::Duration};
 
 use crate::types;
 
-/// Compact ping failure carried from workers to the printer without allocating.
+/// Compact probe failure carried from workers to the printer without allocating.
+///
+/// Most variants mirror `surge_ping::SurgeError`; TCP and UDP probes only ever
+/// produce `Io`, `Timeout` and the ICMP errors `FragmentationNeeded` and
+/// `TtlExceeded`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PingFailure {
     IncorrectBufferSize,
@@ -19,6 +23,34 @@ pub enum PingFailure {
         seq: u16,
     },
     ClientDestroyed,
+    /// The probe didn't fit the path MTU with don't-fragment set; `mtu` is
+    /// the limit when the kernel or the reporting router told it.
+    FragmentationNeeded {
+        mtu: Option<u32>,
+    },
+    /// The TTL (IPv6 hop limit) ran out at router `from`.
+    TtlExceeded {
+        from: IpAddr,
+    },
+}
+
+impl PingFailure {
+    /// Classify an ICMP error message from `from`, or `None` for other types.
+    /// `info` is the MTU carried by "fragmentation needed" messages.
+    pub fn from_icmp(
+        v6: bool,
+        icmp_type: u8,
+        icmp_code: u8,
+        info: u32,
+        from: IpAddr,
+    ) -> Option<Self> {
+        let mtu = (info > 0).then_some(info);
+        match (v6, icmp_type, icmp_code) {
+            (false, 3, 4) | (true, 2, _) => Some(Self::FragmentationNeeded { mtu }),
+            (false, 11, _) | (true, 3, _) => Some(Self::TtlExceeded { from }),
+            _ => None,
+        }
+    }
 }
 
 impl From<surge_ping::SurgeError> for PingFailure {
@@ -44,13 +76,23 @@ impl From<surge_ping::SurgeError> for PingFailure {
     }
 }
 
+/// True for `EMSGSIZE`. With don't-fragment set it means the datagram is
+/// larger than the MTU the kernel knows for the path; without, only that it
+/// is larger than any datagram may be.
+pub fn message_too_long(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::EMSGSIZE);
+    #[cfg(not(unix))]
+    return false;
+}
+
 impl std::fmt::Display for PingFailure {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::IncorrectBufferSize => f.write_str("buffer size was too small"),
             Self::MalformedPacket => f.write_str("malformed packet"),
             Self::Io(kind) => write!(f, "io error: {}", io::Error::from(*kind)),
-            Self::Timeout { seq } => write!(f, "request timeout for icmp_seq {seq}"),
+            Self::Timeout { seq } => write!(f, "request timeout for seq {seq}"),
             Self::EchoRequestPacket => f.write_str("echo request packet"),
             Self::NetworkError => f.write_str("network error"),
             Self::IdenticalRequests { host, ident, seq } => match ident {
@@ -63,6 +105,11 @@ impl std::fmt::Display for PingFailure {
             Self::ClientDestroyed => {
                 f.write_str("client has been destroyed, ping operations are no longer available")
             }
+            Self::FragmentationNeeded { mtu: Some(mtu) } => {
+                write!(f, "fragmentation needed (mtu {mtu})")
+            }
+            Self::FragmentationNeeded { mtu: None } => f.write_str("fragmentation needed"),
+            Self::TtlExceeded { from } => write!(f, "ttl exceeded at {from}"),
         }
     }
 }
@@ -75,18 +122,56 @@ impl std::fmt::Display for PingFailure {
 pub enum PingEvent {
     /// DNS resolution succeeded; the bar should show the resolved address.
     Resolved { idx: types::HostIdx, addr: IpAddr },
-    /// DNS resolution failed; the bar should be finished with an error message.
+    /// DNS resolution failed; the worker retries with backoff until it succeeds.
     ResolutionFailed {
         idx: types::HostIdx,
         error: types::ResolveError,
     },
-    /// A ping round-trip succeeded with the given latency.
-    Success { idx: types::HostIdx, rtt: Duration },
-    /// A ping failed; a persistent timestamped line should be printed above the bars.
+    /// Re-resolution returned a different address; later probes go to `to`.
+    AddressChanged {
+        idx: types::HostIdx,
+        from: IpAddr,
+        to: IpAddr,
+    },
+    /// Probe `seq` got a reply after `rtt`, within the timeout.
+    Success {
+        idx: types::HostIdx,
+        seq: u16,
+        rtt: Duration,
+    },
+    /// Probe `seq`, already reported as timed out, got a reply after `rtt`
+    /// anyway. It doesn't count as received. `reordered` when a newer probe
+    /// was answered first.
+    LateReply {
+        idx: types::HostIdx,
+        seq: u16,
+        rtt: Duration,
+        reordered: bool,
+    },
+    /// Probe `seq`, already answered, got another reply after `rtt`, which
+    /// ping(8) marks `DUP!`. It doesn't count as received.
+    Duplicate {
+        idx: types::HostIdx,
+        seq: u16,
+        rtt: Duration,
+    },
+    /// A probe failed; a persistent timestamped line should be printed above the bars.
     Failure {
         idx: types::HostIdx,
         error: PingFailure,
     },
+    /// `--mtu-discover` found the largest packet, IP header included, that
+    /// reaches the host unfragmented.
+    PathMtu { idx: types::HostIdx, mtu: u32 },
+    /// A host joined after the start, at the next free index, because the
+    /// config file or stdin named it.
+    HostAdded {
+        idx: types::HostIdx,
+        host: types::Hostname,
+    },
+    /// A host left the config file; its worker is gone and its index is
+    /// never reused.
+    HostRemoved { idx: types::HostIdx },
 }
 
 impl PingEvent {
@@ -94,8 +179,14 @@ impl PingEvent {
         match self {
             Self::Resolved { idx, .. }
             | Self::ResolutionFailed { idx, .. }
+            | Self::AddressChanged { idx, .. }
             | Self::Success { idx, .. }
-            | Self::Failure { idx, .. } => *idx,
+            | Self::LateReply { idx, .. }
+            | Self::Duplicate { idx, .. }
+            | Self::Failure { idx, .. }
+            | Self::PathMtu { idx, .. }
+            | Self::HostAdded { idx, .. }
+            | Self::HostRemoved { idx } => *idx,
         }
     }
 }
@@ -108,7 +199,30 @@ mod tests {
     fn ping_failure_formats_timeout_without_allocating_in_worker() {
         assert_eq!(
             PingFailure::Timeout { seq: 42 }.to_string(),
-            "request timeout for icmp_seq 42"
+            "request timeout for seq 42"
+        );
+    }
+
+    #[test]
+    fn classifies_icmp_errors() {
+        let router: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(
+            PingFailure::from_icmp(false, 3, 4, 1400, router),
+            Some(PingFailure::FragmentationNeeded { mtu: Some(1400) })
+        );
+        assert_eq!(
+            PingFailure::from_icmp(true, 2, 0, 1280, router),
+            Some(PingFailure::FragmentationNeeded { mtu: Some(1280) })
+        );
+        assert_eq!(
+            PingFailure::from_icmp(true, 3, 0, 0, router),
+            Some(PingFailure::TtlExceeded { from: router })
+        );
+        assert_eq!(
+            PingFailure::TtlExceeded { from: router }.to_string(),
+            "ttl exceeded at 10.0.0.1"
         );
+        // Port unreachable is not one of them.
+        assert_eq!(PingFailure::from_icmp(false, 3, 3, 0, router), None);
     }
 }
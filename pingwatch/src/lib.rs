@@ -1,87 +1,604 @@
 //! Pings one or more hosts simultaneously, showing live per-host status via
-//! an indicatif MultiProgress TUI.
+//! an indicatif MultiProgress TUI, or as timestamped log lines when stdout is
+//! not a terminal.
 //!
 //! # Architecture -- functional core / imperative shell
 //!
 //! ```text
 //!   +-------------------------------------------------------------+
 //!   |  async worker tasks (one per host)                          |
-//!   |  - DNS resolution + ICMP ping loop                          |
+//!   |  - DNS resolution (retried, refreshed) + probe loop         |
 //!   |  - zero display code; emit typed PingEvents                 |
+//!   |  - started and aborted by `run` as hosts come and go        |
 //!   +----------------------------+--------------------------------+
 //!                                |  tokio::sync::mpsc::Sender<PingEvent>
 //!                                v  (cloned into each task; bounded, async send)
 //!   +-------------------------------------------------------------+
-//!   |  async printer task                                         |
+//!   |  optional alert task (only with --alert-* actions)          |
+//!   |  - forwards every event; rings the bell / spawns hooks      |
+//!   +----------------------------+--------------------------------+
+//!                                |
+//!                                v
+//!   +-------------------------------------------------------------+
+//!   |  optional metrics task (only with --metrics-listen)         |
+//!   |  - forwards every event; updates what scrapes are served    |
+//!   +----------------------------+--------------------------------+
+//!                                |
+//!                                v
+//!   +-------------------------------------------------------------+
+//!   |  optional recorder task (only with --record)                |
+//!   |  - forwards every event; appends it to the recording        |
+//!   +----------------------------+--------------------------------+
+//!                                |
+//!                                v
+//!   +-------------------------------------------------------------+
+//!   |  async printer task (or logger task, see `--output`)        |
 //!   |  - owns MultiProgress + all ProgressBars + styles           |
 //!   |  - yields between events via Receiver::recv().await         |
 //!   |  - exits when all senders drop (channel exhausted)          |
+//!   |  - returns per-host `Stats` for the summary                 |
+//!   |  - runs a tracer task for the host shown in the path view   |
 //!   +-------------------------------------------------------------+
 //! ```
 //!
 //! `tokio::sync::mpsc::channel` (bounded) is used so that workers apply
 //! backpressure when the printer falls behind. Both sides are async: workers
 //! `.await` the send and the printer `.await` each recv.
+//!
+//! Workers stop on their own after `--count` pings or at `--deadline`; on
+//! Ctrl-C they are aborted instead. Either way their senders drop, the
+//! printer drains what is left, and `run` prints a ping(8)-style summary.
+//! A Ctrl-C while draining exits right away, without a summary.
+//!
+//! Hosts may also join and leave while running: `reload` watches the config
+//! file the hosts came from, or reads more hosts from stdin. `run` announces
+//! each new host with a `HostAdded` event at the next free index before its
+//! worker starts, and aborts the worker of a removed host before sending
+//! `HostRemoved`, so every task downstream sees the host list change in
+//! order with the events.
+//!
+//! `pingwatch replay` replaces the workers with a single task feeding a
+//! recording into the printer at its recorded pace.
 
+mod alert;
 pub mod cli;
 mod client;
 mod event;
+mod logger;
+mod metrics;
 mod printer;
+mod probe;
+mod record;
+mod reload;
 pub mod spinner_style;
+pub mod stats;
+mod trace;
 pub mod types;
 mod worker;
 
+use anyhow::Context as _;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
-pub async fn run(args: cli::Args) -> anyhow::Result<()> {
-    let hosts: Arc<[types::Hostname]> = Arc::from(args.hosts);
-    let interval = args.interval;
-    let spinner_style = args.spinner_style;
-    let timeout = args.timeout;
+/// Ping every host until the workers stop or Ctrl-C is pressed, print the
+/// summary and return the statistics it was built from.
+pub async fn run(mut args: cli::Args) -> anyhow::Result<stats::Stats> {
+    args.output = args
+        .output
+        .resolve(std::io::IsTerminal::is_terminal(&std::io::stdout()));
+    if let Some(cli::Command::Replay(replay)) = args.command.take() {
+        return run_replay(args, replay).await;
+    }
+    let display = display_options(&args);
+    let settings = Settings {
+        interval: args.interval,
+        timeout: args.timeout,
+        size: args.size,
+        socket_options: args.socket_options(),
+        mtu_discover: args.mtu_discover,
+        count: args.count,
+        deadline: args.deadline.map(|d| tokio::time::Instant::now() + d),
+        resolve_interval: args.resolve_interval,
+        resolve_after_failures: args.resolve_after_failures,
+        all_addresses: args.all_addresses,
+        // Derive a unique ICMP echo identifier per host from the process ID
+        // so concurrent pingers don't respond to each other's replies.
+        base_id: std::process::id() as u16,
+    };
+    let mut given = Vec::with_capacity(args.hosts.len());
+    for host in args.hosts {
+        let copies = settings.expand(host.clone()).await;
+        given.push((host, copies));
+    }
+    let hosts: Arc<[types::Hostname]> = given
+        .iter()
+        .flat_map(|(_, copies)| copies.iter().cloned())
+        .collect();
+    let output = args.output;
+    let record = args
+        .record
+        .map(|path| {
+            std::fs::File::create(&path)
+                .with_context(|| format!("creating recording {}", path.display()))
+        })
+        .transpose()?;
+    let metrics_listener = match args.metrics_listen {
+        Some(addr) => Some(
+            tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("listening for metrics on {addr}"))?,
+        ),
+        None => None,
+    };
+    let alerts = (args.alert_bell || args.alert_notify.is_some() || args.alert_exec.is_some())
+        .then(|| alert::AlertConfig {
+            down_after: args.alert_down_after,
+            slow_ms: args.alert_slow_ms.map(|d| d.as_secs_f64() * 1000.0),
+            slow_after: args.alert_slow_after,
+            cooldown: args.alert_cooldown,
+            bell: args.alert_bell,
+            notify: args.alert_notify,
+            exec: args.alert_exec,
+        });
+    let family = match (args.ipv4, args.ipv6) {
+        (true, _) => Some(types::Family::Ipv4),
+        (_, true) => Some(types::Family::Ipv6),
+        _ => None,
+    };
+
+    // Bounded channel: workers back-pressure when the printer lags. Two slots
+    // per host leave room for a round of pings from every host at once.
+    let capacity = hosts.len().saturating_mul(2).max(64);
+    let (tx, rx) = mpsc::channel::<event::PingEvent>(capacity);
+
+    // With alerts configured, events pass through the alert task on their way
+    // to the printer.
+    let rx = match alerts {
+        Some(config) => {
+            let (alert_tx, printer_rx) = mpsc::channel::<event::PingEvent>(capacity);
+            tokio::spawn(alert::run_alerts(Arc::clone(&hosts), config, rx, alert_tx));
+            printer_rx
+        }
+        None => rx,
+    };
 
-    // Bounded channel: workers back-pressure when the printer lags.
-    // At <=10 hosts x 1 ping/s, 64 slots is several seconds of headroom.
-    let (tx, rx) = mpsc::channel::<event::PingEvent>(64);
+    // Likewise through the metrics task, which shares its state with the
+    // server answering scrapes.
+    let (rx, metrics_server) = match metrics_listener {
+        Some(listener) => {
+            let shared = Arc::new(std::sync::Mutex::new(metrics::Metrics::new(Arc::clone(
+                &hosts,
+            ))));
+            let (metrics_tx, printer_rx) = mpsc::channel::<event::PingEvent>(capacity);
+            tokio::spawn(metrics::run_metrics(Arc::clone(&shared), rx, metrics_tx));
+            (
+                printer_rx,
+                Some(tokio::spawn(metrics::serve(listener, shared))),
+            )
+        }
+        None => (rx, None),
+    };
+
+    // And through the recorder with --record.
+    let rx = match record {
+        Some(file) => {
+            let (record_tx, printer_rx) = mpsc::channel::<event::PingEvent>(capacity);
+            tokio::spawn(record::run_recorder(
+                Arc::clone(&hosts),
+                rx,
+                record_tx,
+                std::io::BufWriter::new(file),
+            ));
+            printer_rx
+        }
+        None => rx,
+    };
+
+    // One ICMP client per protocol version, socket kind and set of options
+    // in use, shared across all workers; sharing avoids N duplicate sockets.
+    let clients = client::PingClients::new(settings.uses(&hosts))?;
+    let tracers: trace::Tracers = Arc::default();
+    // Messages about the host list while running, shown like events are.
+    let (notices_tx, notices) = mpsc::channel::<String>(16);
+    let mut workers = Workers {
+        tasks: tokio::task::JoinSet::new(),
+        aborts: Vec::new(),
+        given: Vec::new(),
+        expanding: tokio::task::JoinSet::new(),
+        pending: Vec::new(),
+        clients,
+        tracers: Arc::clone(&tracers),
+        settings,
+        tx,
+        notices: notices_tx.clone(),
+    };
+    let watched: Vec<_> = given.iter().map(|(host, _)| host.clone()).collect();
+    for (host, copies) in given {
+        workers.spawn(host, copies);
+    }
 
     let printer = tokio::spawn({
         let hosts = Arc::clone(&hosts);
-        async move { printer::run_printer(hosts, spinner_style, rx).await }
+        async move {
+            match output {
+                types::OutputMode::Auto | types::OutputMode::Tui => {
+                    printer::run_printer(hosts, display, rx, Some(tracers), Some(notices)).await
+                }
+                mode => {
+                    tokio::spawn(print_notices(notices));
+                    logger::run_logger(hosts, mode, rx, std::io::stdout()).await
+                }
+            }
+        }
     });
 
-    // One ICMP client per protocol version, shared across all workers.
-    // Each Client opens a single raw socket; sharing avoids N duplicate sockets.
-    let clients = client::PingClients::new()?;
+    // Hosts come and go with edits to the config file they came from, and
+    // more arrive on stdin with `-`.
+    let mut reloads = None;
+    let watcher = args.watch.map(|watch| {
+        let (tx, rx) = mpsc::channel(16);
+        reloads = Some(rx);
+        let notices = notices_tx.clone();
+        tokio::spawn(reload::watch_config(watch, watched, tx, notices))
+    });
+    let mut stdin = args.hosts_from_stdin.then(|| {
+        let (tx, rx) = mpsc::channel(16);
+        let notices = notices_tx.clone();
+        std::thread::spawn(move || {
+            reload::read_hosts(std::io::stdin().lock(), family, tx, notices)
+        });
+        rx
+    });
+    drop(notices_tx);
 
-    // Derive a unique ICMP echo identifier per host from the process ID so
-    // concurrent pingers don't respond to each other's replies.
-    let base_id = std::process::id() as u16;
+    // Run until the workers are done and no more hosts can come, or abort
+    // them on Ctrl-C. Either way their senders drop and the printer drains
+    // and returns. A watched config file only keeps an idle run going
+    // without --count, as workers never stop on their own then.
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let at_deadline = async {
+        match settings.deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(at_deadline);
+    let mut interrupted = false;
+    while !workers.tasks.is_empty()
+        || !workers.expanding.is_empty()
+        || stdin.is_some()
+        || (reloads.is_some() && settings.count.is_none())
+    {
+        tokio::select! {
+            biased;
+            _ = &mut ctrl_c => {
+                workers.tasks.abort_all();
+                interrupted = true;
+                break;
+            }
+            () = &mut at_deadline, if stdin.is_some() || reloads.is_some() => {
+                stdin = None;
+                reloads = None;
+            }
+            Some(change) = next_change(&mut reloads) => workers.apply(change).await,
+            Some(change) = next_change(&mut stdin) => workers.apply(change).await,
+            Some(Ok((id, copies))) = workers.expanding.join_next_with_id() => {
+                workers.add(id, copies).await;
+            }
+            Some(_) = workers.tasks.join_next() => {}
+        }
+    }
+    if let Some(watcher) = watcher {
+        watcher.abort();
+    }
+    // Drop the last sender so the printer exits once all tasks complete.
+    drop(workers);
 
-    let tasks: Vec<_> = hosts
-        .iter()
-        .enumerate()
-        .map(|(i, host)| {
-            let cfg = worker::WorkerConfig {
-                host: host.clone(),
-                idx: types::HostIdx::new(i),
-                id: surge_ping::PingIdentifier(base_id.wrapping_add(i as u16)),
-                clients: clients.clone(),
-                interval,
-                timeout,
-            };
-            tokio::spawn(worker::run_worker(cfg, tx.clone()))
-        })
-        .collect();
+    let stats = drain(printer).await?;
+    if let Some(server) = metrics_server {
+        server.abort();
+    }
+    if interrupted {
+        // indicatif's hidden cursor must not outlive an interrupted TUI.
+        let _ = console::Term::stdout().show_cursor();
+    }
+
+    // Keep the summary out of machine-readable streams.
+    match output {
+        types::OutputMode::Jsonl | types::OutputMode::Csv => eprint!("\n{stats}"),
+        _ => print!("\n{stats}"),
+    }
+
+    Ok(stats)
+}
+
+/// Wait for the printer to drain what is left, or exit the process on
+/// Ctrl-C, as a first one may have stopped the workers and not the drain.
+async fn drain<T>(printer: tokio::task::JoinHandle<T>) -> anyhow::Result<T> {
+    tokio::select! {
+        result = printer => Ok(result?),
+        _ = tokio::signal::ctrl_c() => {
+            let _ = console::Term::stdout().show_cursor();
+            // Like a shell reports death by SIGINT.
+            std::process::exit(130);
+        }
+    }
+}
+
+/// Write notices to stderr, where they don't mix with the log on stdout and
+/// no live display gets garbled.
+async fn print_notices(mut notices: mpsc::Receiver<String>) {
+    while let Some(notice) = notices.recv().await {
+        eprintln!("{notice}");
+    }
+}
+
+/// The next change from `changes`; never resolves without a receiver. The
+/// receiver is dropped once closed, the `None` it returns then telling that
+/// no more changes come from it.
+async fn next_change(
+    changes: &mut Option<mpsc::Receiver<reload::Change>>,
+) -> Option<reload::Change> {
+    let Some(rx) = changes else {
+        return std::future::pending().await;
+    };
+    let change = rx.recv().await;
+    if change.is_none() {
+        *changes = None;
+    }
+    change
+}
+
+/// What every worker is configured from besides its host.
+#[derive(Clone, Copy)]
+struct Settings {
+    interval: std::time::Duration,
+    timeout: std::time::Duration,
+    size: Option<u16>,
+    socket_options: types::SocketOptions,
+    mtu_discover: bool,
+    count: Option<u64>,
+    deadline: Option<tokio::time::Instant>,
+    resolve_interval: std::time::Duration,
+    resolve_after_failures: u32,
+    all_addresses: bool,
+    base_id: u16,
+}
+
+impl Settings {
+    /// Per-host socket options win over the global ones, except that MTU
+    /// discovery needs don't-fragment everywhere.
+    fn options(&self, host: &types::Hostname) -> types::SocketOptions {
+        let mut options = host.socket_options(self.socket_options);
+        options.dont_fragment |= self.mtu_discover;
+        options
+    }
+
+    /// The probe kind and socket options of every host, to open the ICMP
+    /// clients they need.
+    fn uses(&self, hosts: &[types::Hostname]) -> Vec<(types::Probe, types::SocketOptions)> {
+        hosts
+            .iter()
+            .map(|host| (host.probe(), self.options(host)))
+            .collect()
+    }
 
-    // Drop main's sender so the printer exits once all tasks complete.
-    drop(tx);
+    /// The worker of the host at index `i` and how the path view traces it,
+    /// with the settings of that worker.
+    fn configure(
+        &self,
+        host: &types::Hostname,
+        i: usize,
+        clients: &client::PingClients,
+    ) -> (worker::WorkerConfig, trace::TraceConfig) {
+        let id = surge_ping::PingIdentifier(self.base_id.wrapping_add(i as u16));
+        let interval = host.interval().unwrap_or(self.interval);
+        let timeout = host.timeout().unwrap_or(self.timeout);
+        let payload_len = host
+            .payload_size()
+            .or(self.size)
+            .map_or(probe::DEFAULT_PAYLOAD_LEN, usize::from);
+        let options = self.options(host);
+        let trace = trace::TraceConfig {
+            probe: host.probe(),
+            clients: clients.clone(),
+            id,
+            interval,
+            timeout,
+            payload_len,
+            options,
+        };
+        let worker = worker::WorkerConfig {
+            host: host.clone(),
+            idx: types::HostIdx::new(i),
+            id,
+            clients: clients.clone(),
+            interval,
+            timeout,
+            payload_len,
+            options,
+            mtu_discover: self.mtu_discover,
+            count: self.count,
+            deadline: self.deadline,
+            resolve_interval: self.resolve_interval,
+            resolve_after_failures: self.resolve_after_failures,
+        };
+        (worker, trace)
+    }
+
+    /// The hosts pinged for `host`: with `--all-addresses` one copy pinned to
+    /// each of its resolved addresses, kept next to each other so the printer
+    /// can group them, else just `host`. A host that doesn't resolve yet is
+    /// kept as it is and retried by its worker.
+    async fn expand(&self, host: types::Hostname) -> Vec<types::Hostname> {
+        if !self.all_addresses {
+            return vec![host];
+        }
+        match host.resolve_all().await {
+            Ok(addrs) => addrs.into_iter().map(|addr| host.pinned_to(addr)).collect(),
+            Err(_) => vec![host],
+        }
+    }
+}
+
+/// The running workers and what it takes to start more.
+struct Workers {
+    tasks: tokio::task::JoinSet<()>,
+    /// Abort handle of every host's worker by index; `None` once removed.
+    aborts: Vec<Option<tokio::task::AbortHandle>>,
+    /// Hosts as given, each with the indices of the hosts pinged for it.
+    given: Vec<(types::Hostname, Vec<types::HostIdx>)>,
+    /// Added hosts being expanded into the hosts pinged for them, off the
+    /// loop that handles Ctrl-C as that may take a DNS lookup.
+    expanding: tokio::task::JoinSet<Vec<types::Hostname>>,
+    /// The host each expansion is for, until it is added or removed again.
+    pending: Vec<(tokio::task::AbortHandle, types::Hostname)>,
+    clients: client::PingClients,
+    tracers: trace::Tracers,
+    settings: Settings,
+    tx: mpsc::Sender<event::PingEvent>,
+    notices: mpsc::Sender<String>,
+}
+
+impl Workers {
+    /// Start a worker for every copy of `host`, at the next free indices.
+    fn spawn(&mut self, host: types::Hostname, copies: Vec<types::Hostname>) {
+        let mut idxs = Vec::with_capacity(copies.len());
+        for copy in copies {
+            let i = self.aborts.len();
+            let (worker, trace) = self.settings.configure(&copy, i, &self.clients);
+            self.tracers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(trace);
+            let task = self
+                .tasks
+                .spawn(worker::run_worker(worker, self.tx.clone()));
+            self.aborts.push(Some(task));
+            idxs.push(types::HostIdx::new(i));
+        }
+        self.given.push((host, idxs));
+    }
 
-    // Awaiting keeps main alive; tasks run until the process is terminated.
-    for task in tasks {
-        let _ = task.await;
+    /// Start expanding an added host, or stop pinging a removed one. A host
+    /// removed while still expanding is never pinged.
+    async fn apply(&mut self, change: reload::Change) {
+        match change {
+            reload::Change::Add(host) => {
+                let (settings, expanded) = (self.settings, host.clone());
+                let task = self
+                    .expanding
+                    .spawn(async move { settings.expand(expanded).await });
+                self.pending.push((task, host));
+            }
+            reload::Change::Remove(host) => {
+                if let Some(position) = self.pending.iter().rposition(|(_, p)| *p == host) {
+                    let (task, _) = self.pending.remove(position);
+                    task.abort();
+                    return;
+                }
+                let Some(position) = self.given.iter().position(|(given, _)| *given == host) else {
+                    return;
+                };
+                let (_, idxs) = self.given.remove(position);
+                for idx in idxs {
+                    if let Some(task) = self.aborts[idx.as_usize()].take() {
+                        task.abort();
+                    }
+                    let _ = self.tx.send(event::PingEvent::HostRemoved { idx }).await;
+                }
+            }
+        }
     }
 
-    let _ = printer.await;
+    /// Start pinging the `copies` expansion `id` found, each announced
+    /// before its first event, unless its host was removed meanwhile.
+    async fn add(&mut self, id: tokio::task::Id, copies: Vec<types::Hostname>) {
+        let Some(position) = self.pending.iter().position(|(task, _)| task.id() == id) else {
+            return;
+        };
+        let (_, host) = self.pending.remove(position);
+        match self.clients.with(self.settings.uses(&copies)) {
+            Ok(clients) => self.clients = clients,
+            Err(error) => {
+                let _ = self
+                    .notices
+                    .send(format!("not pinging {host}: {error}"))
+                    .await;
+                return;
+            }
+        }
+        for (i, copy) in copies.iter().enumerate() {
+            let added = event::PingEvent::HostAdded {
+                idx: types::HostIdx::new(self.aborts.len() + i),
+                host: copy.clone(),
+            };
+            let _ = self.tx.send(added).await;
+        }
+        self.spawn(host, copies);
+    }
+}
+
+/// Play a recording back through the printer, or just summarise it, and
+/// return the statistics of the recorded session.
+async fn run_replay(args: cli::Args, replay: cli::Replay) -> anyhow::Result<stats::Stats> {
+    let text = std::fs::read_to_string(&replay.file)
+        .with_context(|| format!("reading recording {}", replay.file.display()))?;
+    let recording = record::Recording::parse(&text)
+        .with_context(|| format!("parsing recording {}", replay.file.display()))?;
 
-    Ok(())
+    let stats = if replay.summary || args.output != types::OutputMode::Tui {
+        let mut stats = stats::Stats::new(Arc::clone(&recording.hosts));
+        for (_, ev) in &recording.events {
+            stats.record(ev);
+        }
+        stats
+    } else {
+        let (tx, rx) = mpsc::channel::<event::PingEvent>(recording.hosts.len().max(64));
+        let player = tokio::spawn(record::play(recording.events, replay.speed, tx));
+        let abort = player.abort_handle();
+        let printer = tokio::spawn(printer::run_printer(
+            recording.hosts,
+            display_options(&args),
+            rx,
+            None,
+            None,
+        ));
+        let interrupted = tokio::select! {
+            biased;
+            _ = player => false,
+            _ = tokio::signal::ctrl_c() => {
+                abort.abort();
+                true
+            }
+        };
+        let stats = drain(printer).await?;
+        if interrupted {
+            let _ = console::Term::stdout().show_cursor();
+        }
+        stats
+    };
+
+    println!(
+        "recording started {}",
+        recording.start.format("%Y-%m-%d %H:%M:%S%.3f %:z")
+    );
+    print!("\n{stats}");
+    Ok(stats)
+}
+
+/// Live display settings from the command line and config file.
+fn display_options(args: &cli::Args) -> printer::Options {
+    printer::Options {
+        spinner_style: args.spinner_style,
+        sparkline: args.sparkline.map(|width| printer::Sparkline {
+            width,
+            scale: args.sparkline_scale,
+        }),
+        sort: args.sort,
+        compact: args.compact,
+        keyboard: std::io::IsTerminal::is_terminal(&std::io::stdin()) && !args.hosts_from_stdin,
+        path: args.path,
+    }
 }
@@ -0,0 +1,412 @@
+//! Non-interactive output: one timestamped line per `PingEvent`.
+//!
+//! This is the alternative to `printer::run_printer` for when nobody watches
+//! a terminal (systemd, CI, redirection to a file). It consumes the same
+//! channel, but instead of updating bars in place it appends one line per
+//! event in plain text, JSON Lines or CSV. Line rendering is pure and lives in
+//! `render_line`; the loop only tracks resolved addresses and writes.
+//! Duplicate and late replies are logged as `duplicate` and `late` events,
+//! hosts joining or leaving while running as `host_added` and `host_removed`.
+
+use std::{io::Write, net::IpAddr, sync::Arc};
+
+use chrono::{DateTime, FixedOffset};
+use tokio::sync::mpsc;
+
+use crate::{
+    event::PingEvent,
+    stats::Stats,
+    types::{self, OutputMode},
+};
+
+const CSV_HEADER: &str = "timestamp,host,addr,event,rtt_ms,error";
+
+/// Write one line per event to `out` until the channel is closed or `out`
+/// can't be written to anymore (e.g. a closed pipe), then hand back the
+/// statistics gathered along the way.
+///
+/// Each line is flushed immediately so that `tail -f` and log collectors see
+/// events as they happen.
+pub async fn run_logger<W>(
+    hosts: Arc<[types::Hostname]>,
+    mode: OutputMode,
+    mut rx: mpsc::Receiver<PingEvent>,
+    mut out: W,
+) -> Stats
+where
+    W: Write,
+{
+    let mut stats = Stats::new(Arc::clone(&hosts));
+    let mut hosts = hosts.to_vec();
+    let mut addrs: Vec<Option<IpAddr>> = vec![None; hosts.len()];
+    if mode == OutputMode::Csv && writeln!(out, "{CSV_HEADER}").is_err() {
+        return stats;
+    }
+    while let Some(ev) = rx.recv().await {
+        stats.record(&ev);
+        let i = ev.idx().as_usize();
+        if let PingEvent::HostAdded { host, .. } = &ev
+            && i == hosts.len()
+        {
+            hosts.push(host.clone());
+            addrs.push(None);
+        }
+        let Some(host) = hosts.get(i) else {
+            continue;
+        };
+        if let PingEvent::Resolved { addr, .. } | PingEvent::AddressChanged { to: addr, .. } = ev {
+            addrs[i] = Some(addr);
+        }
+        let now = chrono::Local::now().fixed_offset();
+        let line = render_line(mode, &now, host, addrs[i], &ev);
+        if writeln!(out, "{line}").and_then(|()| out.flush()).is_err() {
+            break;
+        }
+    }
+    stats
+}
+
+/// Render a single event in the given mode. `OutputMode::Auto` and
+/// `OutputMode::Tui` fall back to plain text.
+pub fn render_line(
+    mode: OutputMode,
+    timestamp: &DateTime<FixedOffset>,
+    host: &types::Hostname,
+    addr: Option<IpAddr>,
+    ev: &PingEvent,
+) -> String {
+    let (kind, rtt_ms, error) = match ev {
+        PingEvent::Resolved { .. } => ("resolved", None, None),
+        PingEvent::AddressChanged { .. } => ("address_changed", None, None),
+        PingEvent::ResolutionFailed { error, .. } => {
+            ("resolution_failed", None, Some(error.to_string()))
+        }
+        PingEvent::Success { rtt, .. } => ("success", Some(rtt.as_secs_f64() * 1000.0), None),
+        PingEvent::LateReply { rtt, .. } => ("late", Some(rtt.as_secs_f64() * 1000.0), None),
+        PingEvent::Duplicate { rtt, .. } => ("duplicate", Some(rtt.as_secs_f64() * 1000.0), None),
+        PingEvent::Failure { error, .. } => ("failure", None, Some(error.to_string())),
+        PingEvent::PathMtu { .. } => ("path_mtu", None, None),
+        PingEvent::HostAdded { .. } => ("host_added", None, None),
+        PingEvent::HostRemoved { .. } => ("host_removed", None, None),
+    };
+    let mtu = match ev {
+        PingEvent::PathMtu { mtu, .. } => Some(*mtu),
+        _ => None,
+    };
+    let reordered = matches!(
+        ev,
+        PingEvent::LateReply {
+            reordered: true,
+            ..
+        }
+    );
+    let addr = addr.map(|addr| addr.to_string());
+    let previous = match ev {
+        PingEvent::AddressChanged { from, .. } => Some(from.to_string()),
+        _ => None,
+    };
+    match mode {
+        OutputMode::Jsonl => {
+            let mut line = format!(
+                "{{\"timestamp\":{},\"host\":{},\"addr\":{},\"event\":\"{kind}\"",
+                json_string(&timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)),
+                json_string(host.as_str()),
+                addr.as_deref().map_or_else(|| "null".into(), json_string),
+            );
+            if let Some(rtt_ms) = rtt_ms {
+                line += &format!(",\"rtt_ms\":{rtt_ms:.3}");
+            }
+            if let Some(previous) = &previous {
+                line += &format!(",\"previous_addr\":\"{previous}\"");
+            }
+            if let Some(error) = error {
+                line += &format!(",\"error\":{}", json_string(&error));
+            }
+            if let Some(mtu) = mtu {
+                line += &format!(",\"mtu\":{mtu}");
+            }
+            if reordered {
+                line += ",\"reordered\":true";
+            }
+            line + "}"
+        }
+        OutputMode::Csv => [
+            timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            csv_field(host.as_str()),
+            addr.unwrap_or_default(),
+            kind.to_owned(),
+            rtt_ms.map(|ms| format!("{ms:.3}")).unwrap_or_default(),
+            csv_field(error.as_deref().unwrap_or_default()),
+        ]
+        .join(","),
+        OutputMode::Auto | OutputMode::Tui | OutputMode::Plain => {
+            let host = match addr {
+                Some(addr) if addr != host.as_str() => format!("{host} ({addr})"),
+                _ => host.to_string(),
+            };
+            let detail = match (previous, rtt_ms, error) {
+                (Some(previous), ..) => format!("address changed from {previous}"),
+                (None, Some(rtt_ms), _) => match ev {
+                    PingEvent::LateReply { .. } if reordered => {
+                        format!("rtt={rtt_ms:.1}ms (late, out of order)")
+                    }
+                    PingEvent::LateReply { .. } => format!("rtt={rtt_ms:.1}ms (late)"),
+                    PingEvent::Duplicate { .. } => format!("rtt={rtt_ms:.1}ms (DUP!)"),
+                    _ => format!("rtt={rtt_ms:.1}ms"),
+                },
+                (None, None, Some(error)) => format!("FAILED  {error}"),
+                (None, None, None) => match (mtu, ev) {
+                    (Some(mtu), _) => format!("path mtu {mtu}"),
+                    (None, PingEvent::HostAdded { .. }) => "added".to_owned(),
+                    (None, PingEvent::HostRemoved { .. }) => "removed".to_owned(),
+                    (None, _) => kind.to_owned(),
+                },
+            };
+            format!(
+                "{}  {host}  {detail}",
+                timestamp.format("%Y-%m-%d %H:%M:%S%.3f")
+            )
+        }
+    }
+}
+
+/// Quote and escape `s` as a JSON string, which is also a valid TOML basic
+/// string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quote a CSV field when it contains a separator, quote or newline.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::event::PingFailure;
+
+    fn timestamp() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-06T07:08:09.123+02:00").unwrap()
+    }
+
+    fn host(s: &str) -> types::Hostname {
+        s.parse().unwrap()
+    }
+
+    fn success() -> PingEvent {
+        PingEvent::Success {
+            idx: types::HostIdx::new(0),
+            seq: 0,
+            rtt: Duration::from_micros(12_345),
+        }
+    }
+
+    fn failure() -> PingEvent {
+        PingEvent::Failure {
+            idx: types::HostIdx::new(0),
+            error: PingFailure::Timeout { seq: 3 },
+        }
+    }
+
+    #[test]
+    fn plain_lines_show_host_address_and_outcome() {
+        let addr = Some("10.0.0.1".parse().unwrap());
+        assert_eq!(
+            render_line(
+                OutputMode::Plain,
+                &timestamp(),
+                &host("db"),
+                addr,
+                &success()
+            ),
+            "2024-05-06 07:08:09.123  db (10.0.0.1)  rtt=12.3ms"
+        );
+        assert_eq!(
+            render_line(
+                OutputMode::Plain,
+                &timestamp(),
+                &host("10.0.0.1"),
+                addr,
+                &failure()
+            ),
+            "2024-05-06 07:08:09.123  10.0.0.1  FAILED  request timeout for seq 3"
+        );
+    }
+
+    #[test]
+    fn jsonl_lines_are_json_objects() {
+        assert_eq!(
+            render_line(
+                OutputMode::Jsonl,
+                &timestamp(),
+                &host("db"),
+                None,
+                &success()
+            ),
+            r#"{"timestamp":"2024-05-06T07:08:09.123+02:00","host":"db","addr":null,"event":"success","rtt_ms":12.345}"#
+        );
+        assert_eq!(
+            render_line(
+                OutputMode::Jsonl,
+                &timestamp(),
+                &host("d\"b"),
+                None,
+                &failure()
+            ),
+            r#"{"timestamp":"2024-05-06T07:08:09.123+02:00","host":"d\"b","addr":null,"event":"failure","error":"request timeout for seq 3"}"#
+        );
+    }
+
+    #[test]
+    fn csv_lines_match_the_header() {
+        let addr = Some("10.0.0.1".parse().unwrap());
+        let line = render_line(
+            OutputMode::Csv,
+            &timestamp(),
+            &host("a,b"),
+            addr,
+            &success(),
+        );
+        assert_eq!(
+            line,
+            "2024-05-06T07:08:09.123+02:00,\"a,b\",10.0.0.1,success,12.345,"
+        );
+        assert_eq!(
+            CSV_HEADER.split(',').count(),
+            render_line(OutputMode::Csv, &timestamp(), &host("db"), None, &failure())
+                .split(',')
+                .count()
+        );
+    }
+
+    #[test]
+    fn address_changes_name_both_addresses() {
+        let ev = PingEvent::AddressChanged {
+            idx: types::HostIdx::new(0),
+            from: "10.0.0.1".parse().unwrap(),
+            to: "10.0.0.2".parse().unwrap(),
+        };
+        let addr = Some("10.0.0.2".parse().unwrap());
+        assert_eq!(
+            render_line(OutputMode::Plain, &timestamp(), &host("db"), addr, &ev),
+            "2024-05-06 07:08:09.123  db (10.0.0.2)  address changed from 10.0.0.1"
+        );
+        assert_eq!(
+            render_line(OutputMode::Jsonl, &timestamp(), &host("db"), addr, &ev),
+            r#"{"timestamp":"2024-05-06T07:08:09.123+02:00","host":"db","addr":"10.0.0.2","event":"address_changed","previous_addr":"10.0.0.1"}"#
+        );
+    }
+
+    #[test]
+    fn path_mtu_is_reported_with_its_size() {
+        let ev = PingEvent::PathMtu {
+            idx: types::HostIdx::new(0),
+            mtu: 1492,
+        };
+        assert_eq!(
+            render_line(OutputMode::Plain, &timestamp(), &host("db"), None, &ev),
+            "2024-05-06 07:08:09.123  db  path mtu 1492"
+        );
+        assert_eq!(
+            render_line(OutputMode::Jsonl, &timestamp(), &host("db"), None, &ev),
+            r#"{"timestamp":"2024-05-06T07:08:09.123+02:00","host":"db","addr":null,"event":"path_mtu","mtu":1492}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_header_and_one_line_per_event() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(success()).await.unwrap();
+        tx.send(failure()).await.unwrap();
+        drop(tx);
+        let mut out = Vec::new();
+        run_logger(Arc::from([host("db")]), OutputMode::Csv, rx, &mut out).await;
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].ends_with(",failure,,request timeout for seq 3"));
+    }
+
+    #[tokio::test]
+    async fn hosts_added_while_running_are_logged() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(PingEvent::HostAdded {
+            idx: types::HostIdx::new(0),
+            host: host("db"),
+        })
+        .await
+        .unwrap();
+        tx.send(success()).await.unwrap();
+        tx.send(PingEvent::HostRemoved {
+            idx: types::HostIdx::new(0),
+        })
+        .await
+        .unwrap();
+        drop(tx);
+        let mut out = Vec::new();
+        let stats = run_logger(Arc::from([]), OutputMode::Plain, rx, &mut out).await;
+        let out = String::from_utf8(out).unwrap();
+        let details: Vec<_> = out.lines().map(|line| &line[23..]).collect();
+        assert_eq!(
+            details,
+            ["  db  added", "  db  rtt=12.3ms", "  db  removed"]
+        );
+        assert!(stats.removed(types::HostIdx::new(0)));
+    }
+
+    #[test]
+    fn duplicate_and_late_replies_are_named() {
+        let late = |reordered| PingEvent::LateReply {
+            idx: types::HostIdx::new(0),
+            seq: 0,
+            rtt: Duration::from_micros(12_345),
+            reordered,
+        };
+        let duplicate = PingEvent::Duplicate {
+            idx: types::HostIdx::new(0),
+            seq: 0,
+            rtt: Duration::from_micros(12_345),
+        };
+        let (late, reordered) = (late(false), late(true));
+        let line = |mode, ev| render_line(mode, &timestamp(), &host("db"), None, ev);
+        assert_eq!(
+            line(OutputMode::Plain, &duplicate),
+            "2024-05-06 07:08:09.123  db  rtt=12.3ms (DUP!)"
+        );
+        assert_eq!(
+            line(OutputMode::Plain, &late),
+            "2024-05-06 07:08:09.123  db  rtt=12.3ms (late)"
+        );
+        assert_eq!(
+            line(OutputMode::Plain, &reordered),
+            "2024-05-06 07:08:09.123  db  rtt=12.3ms (late, out of order)"
+        );
+        assert!(line(OutputMode::Csv, &reordered).ends_with(",db,,late,12.345,"));
+        assert!(line(OutputMode::Csv, &duplicate).ends_with(",db,,duplicate,12.345,"));
+        assert!(
+            line(OutputMode::Jsonl, &reordered).ends_with(r#""rtt_ms":12.345,"reordered":true}"#)
+        );
+    }
+}
@@ -0,0 +1,563 @@
+//! OpenMetrics exporter for `--metrics-listen`.
+//!
+//! `Metrics` is the functional core: it folds every `PingEvent` into per-host
+//! counters and an RTT histogram and renders them in the OpenMetrics text
+//! format. The shell has two halves: `run_metrics` sits in the event pipeline
+//! like the alert task, forwarding every event unchanged after recording it,
+//! and `serve` answers scrapes over plain HTTP/1.1 from the same shared state.
+
+use std::{
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use crate::{event::PingEvent, types};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Upper bounds of the RTT histogram buckets in seconds; `+Inf` is implied.
+const BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Largest request head read before giving up on a client.
+const MAX_REQUEST_LEN: usize = 8192;
+
+/// How long a client gets to send its request head.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, so running out of file descriptors doesn't
+/// turn `serve` into a busy loop.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Per-host metrics.
+#[derive(Clone, Debug, Default)]
+struct HostMetrics {
+    sent: u64,
+    lost: u64,
+    /// Whether the latest ping got a reply.
+    up: bool,
+    last_rtt: Option<Duration>,
+    /// Non-cumulative counts per bucket, the last one being `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    rtt_sum: f64,
+    /// Discovered with `--mtu-discover`.
+    path_mtu: Option<u32>,
+    /// Extra replies to pings already answered.
+    duplicates: u64,
+    /// Replies to pings that had timed out already.
+    late: u64,
+    /// Late replies that came after the reply to a newer ping.
+    reordered: u64,
+    /// Removed from the host list, so no longer exported.
+    removed: bool,
+}
+
+impl HostMetrics {
+    fn record_reply(&mut self, rtt: Duration) {
+        let secs = rtt.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&le| secs <= le)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.rtt_sum += secs;
+        self.last_rtt = Some(rtt);
+        self.sent += 1;
+        self.up = true;
+    }
+}
+
+/// Metrics of every host, indexed like the host list.
+#[derive(Debug)]
+pub struct Metrics {
+    hosts: Vec<types::Hostname>,
+    per_host: Vec<HostMetrics>,
+}
+
+impl Metrics {
+    pub fn new(hosts: Arc<[types::Hostname]>) -> Self {
+        let n = hosts.len();
+        Self {
+            hosts: hosts.to_vec(),
+            per_host: vec![HostMetrics::default(); n],
+        }
+    }
+
+    /// Account for one event. Events with an out-of-range index are ignored,
+    /// as are hosts added anywhere but at the end.
+    pub fn record(&mut self, ev: &PingEvent) {
+        if let PingEvent::HostAdded { idx, host } = ev
+            && idx.as_usize() == self.hosts.len()
+        {
+            self.hosts.push(host.clone());
+            self.per_host.push(HostMetrics::default());
+            return;
+        }
+        let Some(host) = self.per_host.get_mut(ev.idx().as_usize()) else {
+            return;
+        };
+        match ev {
+            PingEvent::Resolved { .. } | PingEvent::AddressChanged { .. } => {}
+            PingEvent::ResolutionFailed { .. } => host.up = false,
+            PingEvent::Success { rtt, .. } => host.record_reply(*rtt),
+            PingEvent::LateReply { reordered, .. } => {
+                host.late += 1;
+                host.reordered += u64::from(*reordered);
+            }
+            PingEvent::Duplicate { .. } => host.duplicates += 1,
+            PingEvent::Failure { .. } => {
+                host.sent += 1;
+                host.lost += 1;
+                host.up = false;
+            }
+            PingEvent::PathMtu { mtu, .. } => host.path_mtu = Some(*mtu),
+            PingEvent::HostAdded { .. } => {}
+            PingEvent::HostRemoved { .. } => host.removed = true,
+        }
+    }
+
+    /// The exposition of every metric, ending in `# EOF`.
+    pub fn render(&self) -> String {
+        let hosts: Vec<_> = self
+            .hosts
+            .iter()
+            .zip(&self.per_host)
+            .filter(|(_, metrics)| !metrics.removed)
+            .map(|(host, metrics)| (labels(host), metrics))
+            .collect();
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "pingwatch_up",
+            "gauge",
+            "Whether the latest ping got a reply.",
+        );
+        for (labels, host) in &hosts {
+            let _ = writeln!(out, "pingwatch_up{{{labels}}} {}", u8::from(host.up));
+        }
+
+        family(
+            &mut out,
+            "pingwatch_last_rtt_seconds",
+            "gauge",
+            "Round-trip time of the latest reply.",
+        );
+        for (labels, host) in &hosts {
+            if let Some(rtt) = host.last_rtt {
+                let _ = writeln!(
+                    out,
+                    "pingwatch_last_rtt_seconds{{{labels}}} {}",
+                    rtt.as_secs_f64()
+                );
+            }
+        }
+
+        family(
+            &mut out,
+            "pingwatch_rtt_seconds",
+            "histogram",
+            "Round-trip times of replies.",
+        );
+        for (labels, host) in &hosts {
+            let mut count = 0;
+            for (i, n) in host.buckets.iter().enumerate() {
+                count += n;
+                let le = BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_owned(), f64::to_string);
+                let _ = writeln!(
+                    out,
+                    "pingwatch_rtt_seconds_bucket{{{labels},le=\"{le}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "pingwatch_rtt_seconds_sum{{{labels}}} {}",
+                host.rtt_sum
+            );
+            let _ = writeln!(out, "pingwatch_rtt_seconds_count{{{labels}}} {count}");
+        }
+
+        family(&mut out, "pingwatch_sent", "counter", "Pings sent.");
+        for (labels, host) in &hosts {
+            let _ = writeln!(out, "pingwatch_sent_total{{{labels}}} {}", host.sent);
+        }
+
+        family(
+            &mut out,
+            "pingwatch_lost",
+            "counter",
+            "Pings without a reply.",
+        );
+        for (labels, host) in &hosts {
+            let _ = writeln!(out, "pingwatch_lost_total{{{labels}}} {}", host.lost);
+        }
+
+        family(
+            &mut out,
+            "pingwatch_duplicate_replies",
+            "counter",
+            "Extra replies to pings already answered.",
+        );
+        for (labels, host) in &hosts {
+            let _ = writeln!(
+                out,
+                "pingwatch_duplicate_replies_total{{{labels}}} {}",
+                host.duplicates
+            );
+        }
+
+        family(
+            &mut out,
+            "pingwatch_late_replies",
+            "counter",
+            "Replies to pings that had timed out.",
+        );
+        for (labels, host) in &hosts {
+            let _ = writeln!(
+                out,
+                "pingwatch_late_replies_total{{{labels}}} {}",
+                host.late
+            );
+        }
+
+        family(
+            &mut out,
+            "pingwatch_reordered_replies",
+            "counter",
+            "Late replies that came after the reply to a newer ping.",
+        );
+        for (labels, host) in &hosts {
+            let _ = writeln!(
+                out,
+                "pingwatch_reordered_replies_total{{{labels}}} {}",
+                host.reordered
+            );
+        }
+
+        if hosts.iter().any(|(_, host)| host.path_mtu.is_some()) {
+            family(
+                &mut out,
+                "pingwatch_path_mtu_bytes",
+                "gauge",
+                "Path MTU found by --mtu-discover.",
+            );
+            for (labels, host) in &hosts {
+                if let Some(mtu) = host.path_mtu {
+                    let _ = writeln!(out, "pingwatch_path_mtu_bytes{{{labels}}} {mtu}");
+                }
+            }
+        }
+
+        out + "# EOF\n"
+    }
+}
+
+/// Write the `TYPE` and `HELP` lines of a metric family.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "# HELP {name} {help}");
+}
+
+/// Label set identifying a host: its spec, label and group when set, and the
+/// address it is pinned to so the copies made by `--all-addresses` stay apart.
+fn labels(host: &types::Hostname) -> String {
+    let mut labels = format!("host=\"{}\"", escape(host.as_str()));
+    if host.display_name() != host.as_str() {
+        let _ = write!(labels, ",label=\"{}\"", escape(host.display_name()));
+    }
+    if let Some(group) = host.group() {
+        let _ = write!(labels, ",group=\"{}\"", escape(group));
+    }
+    if let Some(addr) = host.pinned() {
+        let _ = write!(labels, ",addr=\"{addr}\"");
+    }
+    labels
+}
+
+/// Escape a label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Forward every event from `rx` to `tx` unchanged, recording it in
+/// `metrics` along the way. Returns when `rx` is exhausted or `tx` is closed.
+pub async fn run_metrics(
+    metrics: Arc<Mutex<Metrics>>,
+    mut rx: mpsc::Receiver<PingEvent>,
+    tx: mpsc::Sender<PingEvent>,
+) {
+    while let Some(ev) = rx.recv().await {
+        metrics
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .record(&ev);
+        if tx.send(ev).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Answer scrapes on `listener` until the task is aborted. Every path serves
+/// the metrics, so both `/metrics` and `/` work.
+pub async fn serve(listener: TcpListener, metrics: Arc<Mutex<Metrics>>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+            continue;
+        };
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let _ = respond(stream, &metrics).await;
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Mutex<Metrics>) -> std::io::Result<()> {
+    // Only the request line matters; read up to the end of the head.
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let read_head = async {
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        std::io::Result::Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read_head)
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    let response = match request.split(|&b| b == b' ').next() {
+        Some(b"GET") => {
+            let body = metrics
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::PingFailure;
+
+    fn idx(i: usize) -> types::HostIdx {
+        types::HostIdx::new(i)
+    }
+
+    fn success(i: usize, seq: u16, ms: u64) -> PingEvent {
+        PingEvent::Success {
+            idx: idx(i),
+            seq,
+            rtt: Duration::from_millis(ms),
+        }
+    }
+
+    fn failure(i: usize, seq: u16) -> PingEvent {
+        PingEvent::Failure {
+            idx: idx(i),
+            error: PingFailure::Timeout { seq },
+        }
+    }
+
+    fn metrics(names: &[&str], events: &[PingEvent]) -> Metrics {
+        let hosts = names.iter().map(|s| s.parse().unwrap()).collect();
+        let mut metrics = Metrics::new(hosts);
+        for ev in events {
+            metrics.record(ev);
+        }
+        metrics
+    }
+
+    #[test]
+    fn counts_sent_lost_and_state() {
+        let out = metrics(&["db"], &[success(0, 0, 3), failure(0, 1), failure(0, 2)]).render();
+        assert!(
+            out.contains("pingwatch_sent_total{host=\"db\"} 3\n"),
+            "{out}"
+        );
+        assert!(
+            out.contains("pingwatch_lost_total{host=\"db\"} 2\n"),
+            "{out}"
+        );
+        assert!(out.contains("pingwatch_up{host=\"db\"} 0\n"), "{out}");
+        assert!(
+            out.contains("pingwatch_last_rtt_seconds{host=\"db\"} 0.003\n"),
+            "{out}"
+        );
+        assert!(out.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let out = metrics(
+            &["db"],
+            &[success(0, 0, 3), success(0, 1, 30), success(0, 2, 3000)],
+        )
+        .render();
+        for line in [
+            "pingwatch_rtt_seconds_bucket{host=\"db\",le=\"0.001\"} 0",
+            "pingwatch_rtt_seconds_bucket{host=\"db\",le=\"0.005\"} 1",
+            "pingwatch_rtt_seconds_bucket{host=\"db\",le=\"0.05\"} 2",
+            "pingwatch_rtt_seconds_bucket{host=\"db\",le=\"2.5\"} 2",
+            "pingwatch_rtt_seconds_bucket{host=\"db\",le=\"+Inf\"} 3",
+            "pingwatch_rtt_seconds_sum{host=\"db\"} 3.033",
+            "pingwatch_rtt_seconds_count{host=\"db\"} 3",
+        ] {
+            assert!(
+                out.contains(&format!("{line}\n")),
+                "missing `{line}` in\n{out}"
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_late_and_reordered_replies_are_counted_apart() {
+        let events = [
+            success(0, 0, 3),
+            PingEvent::Duplicate {
+                idx: idx(0),
+                seq: 0,
+                rtt: Duration::from_millis(3),
+            },
+            failure(0, 1),
+            success(0, 2, 3),
+            PingEvent::LateReply {
+                idx: idx(0),
+                seq: 1,
+                rtt: Duration::from_millis(900),
+                reordered: true,
+            },
+        ];
+        let out = metrics(&["db"], &events).render();
+        for line in [
+            "pingwatch_sent_total{host=\"db\"} 3",
+            "pingwatch_lost_total{host=\"db\"} 1",
+            "pingwatch_up{host=\"db\"} 1",
+            "pingwatch_rtt_seconds_count{host=\"db\"} 2",
+            "pingwatch_duplicate_replies_total{host=\"db\"} 1",
+            "pingwatch_late_replies_total{host=\"db\"} 1",
+            "pingwatch_reordered_replies_total{host=\"db\"} 1",
+        ] {
+            assert!(
+                out.contains(&format!("{line}\n")),
+                "missing `{line}` in\n{out}"
+            );
+        }
+    }
+
+    #[test]
+    fn hosts_without_replies_have_no_last_rtt() {
+        let out = metrics(&["a", "b\"c"], &[success(0, 0, 1)]).render();
+        assert!(out.contains("pingwatch_up{host=\"b\\\"c\"} 0\n"), "{out}");
+        assert!(
+            !out.contains("pingwatch_last_rtt_seconds{host=\"b"),
+            "{out}"
+        );
+        assert!(out.contains("pingwatch_rtt_seconds_count{host=\"b\\\"c\"} 0\n"));
+    }
+
+    #[test]
+    fn path_mtu_is_only_exported_once_discovered() {
+        assert!(
+            !metrics(&["a"], &[success(0, 0, 1)])
+                .render()
+                .contains("path_mtu")
+        );
+        let ev = PingEvent::PathMtu {
+            idx: idx(1),
+            mtu: 1500,
+        };
+        let out = metrics(&["a", "b"], &[ev]).render();
+        assert!(
+            out.contains("pingwatch_path_mtu_bytes{host=\"b\"} 1500\n"),
+            "{out}"
+        );
+        assert!(
+            !out.contains("pingwatch_path_mtu_bytes{host=\"a\"}"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn added_hosts_are_exported_until_removed() {
+        let events = [
+            PingEvent::HostAdded {
+                idx: idx(1),
+                host: "b".parse().unwrap(),
+            },
+            success(1, 0, 2),
+            PingEvent::HostRemoved { idx: idx(0) },
+        ];
+        let out = metrics(&["a"], &events).render();
+        assert!(out.contains("pingwatch_up{host=\"b\"} 1\n"), "{out}");
+        assert!(!out.contains("host=\"a\""), "{out}");
+    }
+
+    #[test]
+    fn pinned_copies_have_distinct_labels() {
+        let host = "example.com".parse::<types::Hostname>().unwrap();
+        let hosts: Arc<[_]> = Arc::from([
+            host.pinned_to("10.0.0.1".parse().unwrap()),
+            host.pinned_to("::1".parse().unwrap()),
+        ]);
+        let out = Metrics::new(hosts).render();
+        assert!(out.contains("pingwatch_up{host=\"example.com\",addr=\"10.0.0.1\"} 0\n"));
+        assert!(out.contains("pingwatch_up{host=\"example.com\",addr=\"::1\"} 0\n"));
+    }
+
+    #[tokio::test]
+    async fn serves_the_exposition_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Mutex::new(metrics(&["db"], &[success(0, 0, 1)])));
+        let server = tokio::spawn(serve(listener, metrics));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        server.abort();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("pingwatch_sent_total{host=\"db\"} 1\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_clients_are_timed_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Mutex::new(metrics(&["db"], &[]));
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let error = respond(stream, &metrics).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -1,56 +1,221 @@
 //! Printer entry point and event loop.
 //!
 //! This module is the imperative shell of the printer. It owns the tokio
-//! event loop that multiplexes incoming `PingEvent`s with the spinner tick,
-//! and delegates all mutation of on-screen state to `PrinterState`. It does
-//! not know how state is represented or how strings are rendered.
+//! event loop that multiplexes incoming `PingEvent`s with keyboard commands,
+//! notices, the hops of the traced path, the redraw tick and the spinner
+//! tick, and
+//! delegates all mutation of on-screen state to `PrinterState`. It starts and stops the
+//! tracer of whichever host the path view shows. It does not know how state
+//! is represented or how strings are rendered.
 
-use std::sync::Arc;
-#[cfg(any(feature = "animated-spinners", test))]
-use std::time::Duration;
+use std::{net::IpAddr, sync::Arc, time::Duration};
 
 use tokio::sync::mpsc;
 
-use crate::{event, spinner_style::SpinnerStyle, types};
+use crate::{event, spinner_style::SpinnerStyle, stats::Stats, trace, types};
 
+mod input;
+mod layout;
 mod render;
 mod state;
 
 use state::PrinterState;
 
-/// Drive the printer until the incoming event channel is closed.
+/// How often the display is redrawn when events changed it; keyboard
+/// commands redraw right away.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Optional RTT history drawn next to each host.
+#[derive(Clone, Copy, Debug)]
+pub struct Sparkline {
+    /// Number of recent pings shown.
+    pub width: usize,
+    pub scale: types::SparklineScale,
+}
+
+/// How the live display looks and whether it takes keyboard commands.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    pub spinner_style: SpinnerStyle,
+    pub sparkline: Option<Sparkline>,
+    /// Initial host order; `s` cycles through the others.
+    pub sort: types::SortOrder,
+    /// Start with several hosts per row; `c` toggles.
+    pub compact: bool,
+    /// Read scroll, sort and compact commands from the terminal on stdin.
+    pub keyboard: bool,
+    /// Start in the path view of the first host; `p` toggles.
+    pub path: bool,
+}
+
+/// Drive the printer until the incoming event channel is closed, then hand
+/// back the statistics gathered along the way.
+///
+/// `tracers` holds how to trace every host; without them, as when replaying,
+/// there is no path view. Messages on `notices` are printed above the bars,
+/// as writing to stderr would garble the display.
 ///
-/// The loop is biased towards draining incoming events before firing the next
-/// spinner tick so that bursts of events don't get starved by the ticker.
+/// The loop is biased towards draining incoming events before handling
+/// keyboard commands, hops or firing the next spinner tick so that bursts of
+/// events don't get starved; the redraw tick comes first so that they don't
+/// starve the display either.
 pub async fn run_printer(
     hosts: Arc<[types::Hostname]>,
-    spinner_style: SpinnerStyle,
+    options: Options,
     mut rx: mpsc::Receiver<event::PingEvent>,
-) {
-    let mut state = PrinterState::new(hosts, spinner_style);
+    tracers: Option<trace::Tracers>,
+    mut notices: Option<mpsc::Receiver<String>>,
+) -> Stats {
+    let options = Options {
+        path: options.path && tracers.is_some(),
+        ..options
+    };
+    let mut state = PrinterState::new(hosts, &options);
+    let mut tracer = Tracer {
+        configs: tracers,
+        running: None,
+    };
+
+    // The terminal gets its settings back when `_keyboard` drops on return.
+    let _keyboard = options.keyboard.then(input::KeyboardMode::enable).flatten();
+    let mut commands = _keyboard.as_ref().map(|_| {
+        let (tx, rx) = mpsc::channel(16);
+        input::spawn_reader(tx);
+        rx
+    });
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+    redraw.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
     #[cfg(feature = "animated-spinners")]
     {
-        let tick_interval = Duration::from_millis(spinner_style.interval_ms());
+        let tick_interval = Duration::from_millis(options.spinner_style.interval_ms());
         let mut ticker = tokio::time::interval(tick_interval);
         ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
         loop {
             tokio::select! {
                 biased;
+                _ = redraw.tick() => state.flush(),
                 maybe_ev = rx.recv() => {
                     let Some(ev) = maybe_ev else { break };
                     state.handle(ev);
                 }
+                command = next_command(&mut commands) => {
+                    if tracer.allows(command) {
+                        state.command(command);
+                    }
+                }
+                notice = next_notice(&mut notices) => state.notice(&notice),
+                (target, hop) = tracer.next_hop() => state.handle_hop(target, hop),
                 _ = ticker.tick() => state.tick(),
             }
+            tracer.follow(state.path_target());
         }
     }
 
     #[cfg(not(feature = "animated-spinners"))]
-    while let Some(ev) = rx.recv().await {
-        state.handle(ev);
+    loop {
+        tokio::select! {
+            biased;
+            _ = redraw.tick() => state.flush(),
+            maybe_ev = rx.recv() => {
+                let Some(ev) = maybe_ev else { break };
+                state.handle(ev);
+            }
+            command = next_command(&mut commands) => {
+                if tracer.allows(command) {
+                    state.command(command);
+                }
+            }
+            notice = next_notice(&mut notices) => state.notice(&notice),
+            (target, hop) = tracer.next_hop() => state.handle_hop(target, hop),
+        }
+        tracer.follow(state.path_target());
+    }
+
+    tracer.follow(None);
+    state.into_stats()
+}
+
+/// The task tracing the path shown, if any.
+struct Tracer {
+    configs: Option<trace::Tracers>,
+    running: Option<Running>,
+}
+
+struct Running {
+    target: (usize, IpAddr),
+    task: tokio::task::JoinHandle<()>,
+    rx: mpsc::Receiver<trace::HopEvent>,
+}
+
+impl Tracer {
+    /// Whether the printer acts on `command`; the path view needs tracers.
+    fn allows(&self, command: input::Command) -> bool {
+        self.configs.is_some() || command != input::Command::TogglePath
+    }
+
+    /// Trace `target`, restarting when it changed, or stop tracing.
+    fn follow(&mut self, target: Option<(usize, IpAddr)>) {
+        if self.running.as_ref().map(|running| running.target) == target {
+            return;
+        }
+        if let Some(running) = self.running.take() {
+            running.task.abort();
+        }
+        let (Some((i, addr)), Some(configs)) = (target, &self.configs) else {
+            return;
+        };
+        let Some(cfg) = configs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(i)
+            .cloned()
+        else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel(usize::from(trace::MAX_TTL));
+        self.running = Some(Running {
+            target: (i, addr),
+            task: tokio::spawn(trace::run_tracer(cfg, addr, tx)),
+            rx,
+        });
+    }
+
+    /// The next hop of the traced path; never resolves while nothing is
+    /// traced.
+    async fn next_hop(&mut self) -> ((usize, IpAddr), trace::HopEvent) {
+        if let Some(running) = &mut self.running
+            && let Some(hop) = running.rx.recv().await
+        {
+            return (running.target, hop);
+        }
+        std::future::pending().await
+    }
+}
+
+/// The next keyboard command; never resolves without a keyboard or once the
+/// reader has stopped.
+async fn next_command(commands: &mut Option<mpsc::Receiver<input::Command>>) -> input::Command {
+    if let Some(rx) = commands {
+        if let Some(command) = rx.recv().await {
+            return command;
+        }
+        *commands = None;
     }
+    std::future::pending().await
+}
+
+/// The next notice; never resolves without notices or once every sender is
+/// gone.
+async fn next_notice(notices: &mut Option<mpsc::Receiver<String>>) -> String {
+    if let Some(rx) = notices {
+        if let Some(notice) = rx.recv().await {
+            return notice;
+        }
+        *notices = None;
+    }
+    std::future::pending().await
 }
 
 #[cfg(test)]
@@ -78,7 +243,7 @@ mod tests {
         drop(tx);
         tokio::time::timeout(
             Duration::from_secs(1),
-            run_printer(make_hosts(&["h1"]), SpinnerStyle::default(), rx),
+            run_printer(make_hosts(&["h1"]), Options::default(), rx, None, None),
         )
         .await
         .expect("printer should exit immediately when the channel is already closed");
@@ -91,6 +256,7 @@ mod tests {
     })]
     #[case::success(event::PingEvent::Success {
         idx: idx(0),
+        seq: 0,
         rtt: Duration::from_millis(10),
     })]
     #[case::failure(event::PingEvent::Failure {
@@ -108,7 +274,7 @@ mod tests {
         drop(tx);
         tokio::time::timeout(
             Duration::from_secs(1),
-            run_printer(make_hosts(&["h1"]), SpinnerStyle::default(), rx),
+            run_printer(make_hosts(&["h1"]), Options::default(), rx, None, None),
         )
         .await
         .expect("printer should handle this event and exit");
@@ -119,6 +285,7 @@ mod tests {
         let (tx, rx) = mpsc::channel(8);
         tx.send(event::PingEvent::Success {
             idx: idx(99),
+            seq: 0,
             rtt: Duration::from_millis(1),
         })
         .await
@@ -126,7 +293,7 @@ mod tests {
         drop(tx);
         tokio::time::timeout(
             Duration::from_secs(1),
-            run_printer(make_hosts(&["h1"]), SpinnerStyle::default(), rx),
+            run_printer(make_hosts(&["h1"]), Options::default(), rx, None, None),
         )
         .await
         .expect("printer should skip out-of-range events without panicking");
@@ -142,6 +309,7 @@ mod tests {
                 .unwrap();
             tx.send(event::PingEvent::Success {
                 idx: idx(i),
+                seq: 0,
                 rtt: Duration::from_millis(5 * (i as u64 + 1)),
             })
             .await
@@ -152,11 +320,106 @@ mod tests {
             Duration::from_secs(2),
             run_printer(
                 make_hosts(&["host-a", "host-b", "host-c"]),
-                SpinnerStyle::default(),
+                Options {
+                    sparkline: Some(Sparkline {
+                        width: 4,
+                        scale: types::SparklineScale::Global,
+                    }),
+                    sort: types::SortOrder::Latency,
+                    compact: true,
+                    ..Options::default()
+                },
                 rx,
+                None,
+                None,
             ),
         )
         .await
         .expect("printer should handle events across multiple hosts");
     }
+
+    #[tokio::test]
+    async fn returns_statistics_gathered_from_events() {
+        let (tx, rx) = mpsc::channel(8);
+        for ev in [
+            event::PingEvent::Success {
+                idx: idx(1),
+                seq: 0,
+                rtt: Duration::from_millis(4),
+            },
+            event::PingEvent::Failure {
+                idx: idx(1),
+                error: event::PingFailure::Timeout { seq: 1 },
+            },
+        ] {
+            tx.send(ev).await.unwrap();
+        }
+        drop(tx);
+        let stats = run_printer(make_hosts(&["a", "b"]), Options::default(), rx, None, None).await;
+        let b = stats.host(idx(1)).unwrap();
+        assert_eq!((b.transmitted, b.received, b.streak), (2, 1, -1));
+        assert_eq!(stats.host(idx(0)).unwrap().transmitted, 0);
+    }
+
+    #[tokio::test]
+    async fn traces_the_host_shown_in_path_view() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let tracers: trace::Tracers = Arc::new(std::sync::Mutex::new(vec![trace::TraceConfig {
+            probe: types::Probe::Icmp,
+            clients: crate::client::PingClients::new([]).unwrap(),
+            id: surge_ping::PingIdentifier(1),
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(100),
+            payload_len: crate::probe::DEFAULT_PAYLOAD_LEN,
+            options: types::SocketOptions::default(),
+        }]));
+        let (tx, rx) = mpsc::channel(8);
+        let printer = tokio::spawn(run_printer(
+            make_hosts(&["h1"]),
+            Options {
+                path: true,
+                ..Options::default()
+            },
+            rx,
+            Some(tracers),
+            None,
+        ));
+        tx.send(event::PingEvent::Resolved { idx: idx(0), addr })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(1), printer)
+            .await
+            .expect("printer should stop its tracer and exit")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_running_after_the_notices_end() {
+        let (tx, rx) = mpsc::channel(8);
+        let (notices_tx, notices) = mpsc::channel(8);
+        notices_tx.send("a notice".to_owned()).await.unwrap();
+        drop(notices_tx);
+        let printer = tokio::spawn(run_printer(
+            make_hosts(&["h1"]),
+            Options::default(),
+            rx,
+            None,
+            Some(notices),
+        ));
+        tx.send(event::PingEvent::Success {
+            idx: idx(0),
+            seq: 0,
+            rtt: Duration::from_millis(1),
+        })
+        .await
+        .unwrap();
+        drop(tx);
+        let stats = tokio::time::timeout(Duration::from_secs(1), printer)
+            .await
+            .expect("printer should exit once the events end")
+            .unwrap();
+        assert_eq!(stats.host(idx(0)).unwrap().received, 1);
+    }
 }
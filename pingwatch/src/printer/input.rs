@@ -0,0 +1,197 @@
+//! Keyboard commands for the live display.
+//!
+//! `parse_keys` is the functional core: it turns raw terminal input into
+//! `Command`s. The rest is a thin shell: `KeyboardMode` switches the terminal
+//! to unbuffered, unechoed input for as long as it is alive, and
+//! `spawn_reader` forwards commands from a blocking reader thread. Ctrl-C
+//! still raises SIGINT, so interrupting works as without a keyboard.
+
+use tokio::sync::mpsc;
+
+/// What a key press asks the display to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    CycleSort,
+    ToggleCompact,
+    /// Highlight the host after (or before) the highlighted one.
+    NextHost,
+    PreviousHost,
+    /// Switch between the host list and the path to the highlighted host.
+    TogglePath,
+}
+
+/// Decode the commands in a chunk of terminal input, ignoring unknown keys.
+///
+/// Besides the arrow, page and home/end keys, vi-style `k`/`j`, `b`/space
+/// and `g`/`G` scroll; `s` cycles the sort order and `c` toggles compact rows.
+/// Tab and shift-Tab move the highlight, `p` or Enter toggles the path view.
+pub fn parse_keys(mut input: &[u8]) -> Vec<Command> {
+    let mut commands = Vec::new();
+    while let Some((&byte, rest)) = input.split_first() {
+        input = rest;
+        let command = match byte {
+            b'k' => Command::ScrollUp,
+            b'j' => Command::ScrollDown,
+            b'b' => Command::PageUp,
+            b' ' => Command::PageDown,
+            b'g' => Command::Home,
+            b'G' => Command::End,
+            b's' => Command::CycleSort,
+            b'c' => Command::ToggleCompact,
+            b'\t' => Command::NextHost,
+            b'p' | b'\r' | b'\n' => Command::TogglePath,
+            0x1b => {
+                // CSI (`ESC [`) or SS3 (`ESC O`) sequence: parameters, then a
+                // final byte.
+                let Some((b'[' | b'O', rest)) = input.split_first() else {
+                    continue;
+                };
+                let params = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                let Some(&last) = rest.get(params) else {
+                    input = &[];
+                    continue;
+                };
+                input = &rest[params + 1..];
+                match (&rest[..params], last) {
+                    (_, b'A') => Command::ScrollUp,
+                    (_, b'B') => Command::ScrollDown,
+                    (_, b'H') | (b"1" | b"7", b'~') => Command::Home,
+                    (_, b'F') | (b"4" | b"8", b'~') => Command::End,
+                    (b"5", b'~') => Command::PageUp,
+                    (b"6", b'~') => Command::PageDown,
+                    (_, b'Z') => Command::PreviousHost,
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        commands.push(command);
+    }
+    commands
+}
+
+/// Unbuffered, unechoed terminal input on stdin while alive; the original
+/// settings are restored on drop.
+#[cfg(unix)]
+pub struct KeyboardMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl KeyboardMode {
+    /// Switch stdin's terminal mode, or return `None` when stdin is not a
+    /// terminal.
+    pub fn enable() -> Option<Self> {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: `tcgetattr` initialises `termios` when it returns 0.
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: checked above.
+        let original = unsafe { termios.assume_init() };
+        let mut keyboard = original;
+        keyboard.c_lflag &= !(libc::ICANON | libc::ECHO);
+        keyboard.c_cc[libc::VMIN] = 1;
+        keyboard.c_cc[libc::VTIME] = 0;
+        // SAFETY: `keyboard` is a valid termios obtained from `tcgetattr`.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keyboard) } != 0 {
+            return None;
+        }
+        Some(Self { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyboardMode {
+    fn drop(&mut self) {
+        // SAFETY: `original` came from `tcgetattr` on the same descriptor.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// No keyboard support outside Unix terminals.
+#[cfg(not(unix))]
+pub struct KeyboardMode;
+
+#[cfg(not(unix))]
+impl KeyboardMode {
+    pub fn enable() -> Option<Self> {
+        None
+    }
+}
+
+/// Read stdin on a dedicated thread and forward the commands it decodes.
+///
+/// The thread stops at end of input or once the receiver is gone and the
+/// next key arrives; a thread still blocked on stdin doesn't keep the
+/// process alive.
+pub fn spawn_reader(tx: mpsc::Sender<Command>) {
+    std::thread::spawn(move || {
+        use std::io::Read as _;
+        let mut buf = [0u8; 32];
+        let mut stdin = std::io::stdin();
+        loop {
+            let n = match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            for command in parse_keys(&buf[..n]) {
+                if tx.blocking_send(command).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_letters_and_escape_sequences() {
+        assert_eq!(
+            parse_keys(b"jk\x1b[A\x1b[B\x1b[5~\x1b[6~\x1b[H\x1bOF sc"),
+            [
+                Command::ScrollDown,
+                Command::ScrollUp,
+                Command::ScrollUp,
+                Command::ScrollDown,
+                Command::PageUp,
+                Command::PageDown,
+                Command::Home,
+                Command::End,
+                Command::PageDown,
+                Command::CycleSort,
+                Command::ToggleCompact,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_highlight_and_path_keys() {
+        assert_eq!(
+            parse_keys(b"\t\x1b[Zp\r"),
+            [
+                Command::NextHost,
+                Command::PreviousHost,
+                Command::TogglePath,
+                Command::TogglePath,
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_and_truncated_input() {
+        assert_eq!(parse_keys(b"x\x1b[C\x1b[2~q"), []);
+        assert_eq!(parse_keys(b"\x1b"), []);
+        assert_eq!(parse_keys(b"\x1b[5"), []);
+        assert_eq!(parse_keys(b"\x1bj"), [Command::ScrollDown]);
+    }
+}
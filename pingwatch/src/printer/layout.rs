@@ -0,0 +1,366 @@
+//! Pure layout of the live display.
+//!
+//! Decides which host goes on which row: the display order (sorting, failing
+//! hosts pinned to the top, group sections), packing of hosts into compact
+//! rows and the window of rows that fits the terminal. Like `render`, it is
+//! side-effect free; `PrinterState` turns the rows into progress bars.
+
+use std::{cmp::Ordering, ops::Range};
+
+use crate::{
+    stats::Stats,
+    types::{self, SortOrder},
+};
+
+/// A headed section of the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section<'a> {
+    /// Hosts whose latest ping failed, pinned to the top while sorting.
+    Failing,
+    Group(&'a str),
+    /// Ungrouped hosts following another section.
+    Others,
+}
+
+/// One line of the display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Row<'a> {
+    Header(Section<'a>),
+    /// A host on its own line; `continues` when it is another address of the
+    /// host above it, as expanded by `--all-addresses`.
+    Host {
+        idx: usize,
+        continues: bool,
+    },
+    /// Several hosts side by side, in compact mode.
+    Cells(Vec<usize>),
+}
+
+/// Lay the hosts out in display order, one row per host, or `per_row` hosts
+/// per row when compact.
+///
+/// Without sorting, hosts keep their order, except that hosts added while
+/// running join their group. Otherwise failing hosts come first under their
+/// own heading, then every group sorted on its own. Removed hosts are left
+/// out.
+pub fn rows<'a>(
+    hosts: &'a [types::Hostname],
+    stats: &Stats,
+    sort: SortOrder,
+    per_row: Option<usize>,
+) -> Vec<Row<'a>> {
+    let failing = |i: usize| sort != SortOrder::None && stats.failing(types::HostIdx::new(i));
+    let mut group_ranks: Vec<Option<&str>> = Vec::new();
+    for host in hosts {
+        if !group_ranks.contains(&host.group()) {
+            group_ranks.push(host.group());
+        }
+    }
+    let group_rank = |i: usize| group_ranks.iter().position(|&g| g == hosts[i].group());
+
+    let mut order: Vec<usize> = (0..hosts.len())
+        .filter(|&i| !stats.removed(types::HostIdx::new(i)))
+        .collect();
+    if sort == SortOrder::None {
+        order.sort_by_key(|&i| group_rank(i));
+    } else {
+        order.sort_by(|&a, &b| match (failing(a), failing(b)) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (true, true) => compare(hosts, stats, sort, a, b),
+            (false, false) => group_rank(a)
+                .cmp(&group_rank(b))
+                .then_with(|| compare(hosts, stats, sort, a, b)),
+        });
+    }
+
+    let mut rows = Vec::new();
+    let mut section = None;
+    let mut previous: Option<usize> = None;
+    for i in order {
+        let host_section = if failing(i) {
+            Some(Section::Failing)
+        } else {
+            hosts[i].group().map(Section::Group)
+        };
+        if host_section != section {
+            let headed = section.is_some();
+            section = host_section;
+            previous = None;
+            match section {
+                Some(section) => rows.push(Row::Header(section)),
+                None if headed => rows.push(Row::Header(Section::Others)),
+                None => {}
+            }
+        }
+        match (per_row, rows.last_mut()) {
+            (Some(per_row), Some(Row::Cells(cells)))
+                if previous.is_some() && cells.len() < per_row =>
+            {
+                cells.push(i);
+            }
+            (Some(_), _) => rows.push(Row::Cells(vec![i])),
+            (None, _) => {
+                let continues = previous.is_some_and(|p| {
+                    hosts[p].pinned().is_some()
+                        && hosts[i].pinned().is_some()
+                        && hosts[p].as_str() == hosts[i].as_str()
+                });
+                rows.push(Row::Host { idx: i, continues });
+            }
+        }
+        previous = Some(i);
+    }
+    rows
+}
+
+/// Hosts in display order, as laid out in `rows`.
+pub fn hosts_in_order(rows: &[Row<'_>]) -> Vec<usize> {
+    rows.iter()
+        .flat_map(|row| match row {
+            Row::Header(_) => vec![],
+            Row::Host { idx, .. } => vec![*idx],
+            Row::Cells(cells) => cells.clone(),
+        })
+        .collect()
+}
+
+/// The row showing host `idx`.
+pub fn row_of(rows: &[Row<'_>], idx: usize) -> Option<usize> {
+    rows.iter().position(|row| match row {
+        Row::Header(_) => false,
+        Row::Host { idx: i, .. } => *i == idx,
+        Row::Cells(cells) => cells.contains(&idx),
+    })
+}
+
+/// Order two hosts by `sort`, ties broken by name.
+fn compare(
+    hosts: &[types::Hostname],
+    stats: &Stats,
+    sort: SortOrder,
+    a: usize,
+    b: usize,
+) -> Ordering {
+    let host = |i: usize| stats.host(types::HostIdx::new(i));
+    let by_name = || hosts[a].display_name().cmp(hosts[b].display_name());
+    match sort {
+        SortOrder::None => Ordering::Equal,
+        SortOrder::Name => by_name(),
+        SortOrder::Loss => {
+            let loss = |i| host(i).map_or(0.0, |h| h.loss_percent());
+            loss(b).total_cmp(&loss(a)).then_with(by_name)
+        }
+        SortOrder::Latency => {
+            // Hosts without a reply yet sort last.
+            let avg = |i| {
+                host(i)
+                    .and_then(|h| h.rtt_ms())
+                    .map_or(f64::NEG_INFINITY, |(_, avg, ..)| avg)
+            };
+            avg(b).total_cmp(&avg(a)).then_with(by_name)
+        }
+    }
+}
+
+/// Number of compact cells of `cell_width` columns that fit in `width`.
+pub fn cells_per_row(width: usize, cell_width: usize) -> usize {
+    (width / cell_width.max(1)).max(1)
+}
+
+/// The rows to draw out of `total`, starting at `offset` when they don't all
+/// fit in `capacity` lines. The start is clamped so the last page is full.
+pub fn window(total: usize, offset: usize, capacity: usize) -> Range<usize> {
+    let capacity = capacity.max(1);
+    if total <= capacity {
+        return 0..total;
+    }
+    let start = offset.min(total - capacity);
+    start..start + capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::event::{PingEvent, PingFailure};
+
+    fn hosts(names: &[&str]) -> Arc<[types::Hostname]> {
+        names.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn ping(stats: &mut Stats, i: usize, seq: u16, rtt_ms: Option<u64>) {
+        let idx = types::HostIdx::new(i);
+        stats.record(&match rtt_ms {
+            Some(ms) => PingEvent::Success {
+                idx,
+                seq,
+                rtt: Duration::from_millis(ms),
+            },
+            None => PingEvent::Failure {
+                idx,
+                error: PingFailure::Timeout { seq },
+            },
+        });
+    }
+
+    #[test]
+    fn keeps_the_given_order_without_sorting() {
+        let hosts = hosts(&["c", "a", "b"]);
+        let mut stats = Stats::new(Arc::clone(&hosts));
+        ping(&mut stats, 2, 0, None);
+
+        let rows = rows(&hosts, &stats, SortOrder::None, None);
+        assert_eq!(hosts_in_order(&rows), [0, 1, 2]);
+        assert!(!rows.iter().any(|row| matches!(row, Row::Header(_))));
+    }
+
+    #[test]
+    fn sorts_with_failing_hosts_pinned_to_the_top() {
+        let hosts = hosts(&["c", "a", "b", "d"]);
+        let mut stats = Stats::new(Arc::clone(&hosts));
+        ping(&mut stats, 0, 0, Some(30));
+        ping(&mut stats, 1, 0, Some(10));
+        ping(&mut stats, 2, 0, Some(20));
+        ping(&mut stats, 3, 0, None);
+
+        let by_name = rows(&hosts, &stats, SortOrder::Name, None);
+        assert_eq!(by_name[0], Row::Header(Section::Failing));
+        assert_eq!(hosts_in_order(&by_name), [3, 1, 2, 0]);
+        let by_latency = rows(&hosts, &stats, SortOrder::Latency, None);
+        assert_eq!(hosts_in_order(&by_latency), [3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn sorts_by_loss_within_groups() {
+        let hosts: Vec<types::Hostname> = toml_edit::de::from_str::<Hosts>(
+            r#"hosts = [
+                { name = "a", group = "x" },
+                { name = "b", group = "y" },
+                { name = "c", group = "x" },
+            ]"#,
+        )
+        .unwrap()
+        .hosts;
+        let mut stats = Stats::new(hosts.iter().cloned().collect());
+        ping(&mut stats, 0, 0, None);
+        ping(&mut stats, 0, 1, Some(1));
+        ping(&mut stats, 2, 0, Some(1));
+
+        let rows = rows(&hosts, &stats, SortOrder::Loss, None);
+        assert_eq!(
+            rows,
+            [
+                Row::Header(Section::Group("x")),
+                Row::Host {
+                    idx: 0,
+                    continues: false
+                },
+                Row::Host {
+                    idx: 2,
+                    continues: false
+                },
+                Row::Header(Section::Group("y")),
+                Row::Host {
+                    idx: 1,
+                    continues: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn added_hosts_join_their_group_and_removed_ones_leave() {
+        let hosts: Vec<types::Hostname> = toml_edit::de::from_str::<Hosts>(
+            r#"hosts = [
+                "a",
+                { name = "b", group = "x" },
+                { name = "c", group = "y" },
+                { name = "d", group = "x" },
+                "e",
+            ]"#,
+        )
+        .unwrap()
+        .hosts;
+        let mut stats = Stats::new(hosts[..3].iter().cloned().collect());
+        for (i, host) in hosts.iter().enumerate().skip(3) {
+            stats.record(&PingEvent::HostAdded {
+                idx: types::HostIdx::new(i),
+                host: host.clone(),
+            });
+        }
+        stats.record(&PingEvent::HostRemoved {
+            idx: types::HostIdx::new(2),
+        });
+
+        let rows = rows(&hosts, &stats, SortOrder::None, None);
+        assert_eq!(hosts_in_order(&rows), [0, 4, 1, 3]);
+        assert!(!rows.contains(&Row::Header(Section::Group("y"))));
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Hosts {
+        hosts: Vec<types::Hostname>,
+    }
+
+    #[test]
+    fn groups_consecutive_addresses_of_the_same_host() {
+        let host = "example.com".parse::<types::Hostname>().unwrap();
+        let other = "other.example".parse::<types::Hostname>().unwrap();
+        let v4 = "93.184.216.34".parse().unwrap();
+        let v6 = "2606:2800:220:1::".parse().unwrap();
+        let hosts = [
+            host.pinned_to(v4),
+            host.pinned_to(v6),
+            other.pinned_to(v4),
+            host.clone(),
+        ];
+        let stats = Stats::new(hosts.iter().cloned().collect());
+
+        let continues: Vec<bool> = rows(&hosts, &stats, SortOrder::None, None)
+            .into_iter()
+            .map(|row| {
+                matches!(
+                    row,
+                    Row::Host {
+                        continues: true,
+                        ..
+                    }
+                )
+            })
+            .collect();
+        assert_eq!(continues, [false, true, false, false]);
+    }
+
+    #[test]
+    fn packs_compact_rows_per_section() {
+        let hosts = hosts(&["a", "b", "c", "d", "e"]);
+        let mut stats = Stats::new(Arc::clone(&hosts));
+        ping(&mut stats, 4, 0, None);
+
+        let rows = rows(&hosts, &stats, SortOrder::Name, Some(2));
+        assert_eq!(
+            rows,
+            [
+                Row::Header(Section::Failing),
+                Row::Cells(vec![4]),
+                Row::Header(Section::Others),
+                Row::Cells(vec![0, 1]),
+                Row::Cells(vec![2, 3]),
+            ]
+        );
+        assert_eq!(row_of(&rows, 4), Some(1));
+        assert_eq!(row_of(&rows, 3), Some(4));
+        assert_eq!(cells_per_row(80, 30), 2);
+        assert_eq!(cells_per_row(10, 30), 1);
+    }
+
+    #[test]
+    fn window_clamps_to_the_last_page() {
+        assert_eq!(window(5, 3, 10), 0..5);
+        assert_eq!(window(200, 0, 40), 0..40);
+        assert_eq!(window(200, 170, 40), 160..200);
+        assert_eq!(window(200, usize::MAX, 40), 160..200);
+    }
+}
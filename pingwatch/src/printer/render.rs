@@ -6,9 +6,22 @@
 //! values into styled strings and how to push those strings onto existing
 //! progress bars.
 
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Duration};
 
-use crate::{spinner_style::SpinnerStyle, types};
+use super::layout::Section;
+use crate::{spinner_style::SpinnerStyle, stats::HostStats, types};
+
+/// Width of the status column (`rtt=…ms`, `waiting`, `down …s`) so the
+/// statistics columns after it line up across bars and failure lines.
+const STATUS_WIDTH: usize = 14;
+
+/// Width of the status in a compact cell (`123.4ms`, `down`).
+const CELL_STATUS_WIDTH: usize = 8;
+
+/// Sparkline blocks from the fastest to the slowest RTT.
+const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Sparkline glyph for a lost ping.
+const SPARKLINE_LOST: char = '×';
 
 /// Build a spinner style from the given template, sharing frames and tick
 /// interval with the globally-selected `SpinnerStyle`.
@@ -30,37 +43,30 @@ pub fn resolved_addr_for_display(host: &types::Hostname, addr: IpAddr) -> Option
     }
 }
 
-/// Re-render every bar's prefix.
-///
-/// Used when the resolved-address column grows and every existing prefix needs
-/// to be re-aligned to the new width.
-pub fn refresh_prefixes(
-    bars: &[indicatif::ProgressBar],
-    hosts: &[types::Hostname],
-    host_width: usize,
-    resolved_width: usize,
-    resolved_addrs: &[Option<IpAddr>],
-) {
-    for ((bar, host), &resolved_addr) in bars.iter().zip(hosts.iter()).zip(resolved_addrs.iter()) {
-        bar.set_prefix(render_prefix(
-            host,
-            host_width,
-            resolved_width,
-            resolved_addr,
-        ));
-    }
-}
-
-/// Build the prefix shown on the left of a spinner line.
+/// Build the prefix shown on the left of a spinner line. Hosts continuing a
+/// group show a tree branch instead of repeating the group's name; the
+/// `selected` host is shown in reverse video.
 pub fn render_prefix(
     host: &types::Hostname,
+    continues: bool,
+    selected: bool,
     host_width: usize,
     resolved_width: usize,
     resolved_addr: Option<IpAddr>,
 ) -> String {
+    let host_text = if continues {
+        format!("{:<host_width$}", "  └")
+    } else {
+        render_host_text(host, host_width)
+    };
+    let host_text = if selected {
+        console::style(host_text).reverse().to_string()
+    } else {
+        host_text
+    };
     format!(
         "{}{}",
-        render_host_text(host, host_width),
+        host_text,
         render_resolved_text(resolved_width, resolved_addr)
             .map(|text| console::style(text).dim().to_string())
             .unwrap_or_else(|| " ".repeat(resolved_width))
@@ -86,9 +92,116 @@ pub fn render_failure_prefix(
     )
 }
 
-/// Left-pad a hostname to `host_width` columns.
+/// Left-pad a host's display name to `host_width` columns.
 pub fn render_host_text(host: &types::Hostname, host_width: usize) -> String {
-    format!("{:<host_width$}", host.as_str())
+    format!("{:<host_width$}", host.display_name())
+}
+
+/// Heading line shown above the hosts of a section.
+pub fn render_header(section: Section<'_>) -> String {
+    match section {
+        Section::Failing => console::style("[failing]").red().bold().to_string(),
+        Section::Group(group) => console::style(format!("[{group}]")).bold().to_string(),
+        Section::Others => console::style("[others]").bold().to_string(),
+    }
+}
+
+/// Width of a compact cell for hosts of `host_width`, gap included.
+pub fn cell_width(host_width: usize) -> usize {
+    // Glyph, space, name, space, status, gap.
+    2 + host_width + 1 + CELL_STATUS_WIDTH + 2
+}
+
+/// One host in compact mode: a coloured dot, its name and the latest RTT,
+/// `down` while failing, or `...` before the first reply. The name of the
+/// `selected` host is shown in reverse video.
+pub fn render_cell(
+    host: &types::Hostname,
+    host_width: usize,
+    selected: bool,
+    stats: &HostStats,
+) -> String {
+    let (dot, status) = match (stats.streak, stats.last_ms()) {
+        (streak, _) if streak < 0 => (console::style('●').red(), "down".to_owned()),
+        (_, Some(ms)) => (console::style('●').green(), format!("{ms:.1}ms")),
+        (_, None) => (console::style('●').yellow(), "...".to_owned()),
+    };
+    let name = render_host_text(host, host_width);
+    let name = if selected {
+        console::style(name).reverse().to_string()
+    } else {
+        name
+    };
+    format!("{dot} {name} {status:>CELL_STATUS_WIDTH$}  ")
+}
+
+/// Status line below a display that doesn't fit the terminal or is sorted.
+pub fn render_footer(
+    shown: std::ops::Range<usize>,
+    total: usize,
+    sort: types::SortOrder,
+    compact: bool,
+) -> String {
+    let rows = if shown.len() < total {
+        format!("rows {}-{} of {total} · ", shown.start + 1, shown.end)
+    } else {
+        String::new()
+    };
+    let compact = if compact { "compact · " } else { "" };
+    console::style(format!(
+        "{rows}sort: {sort} · {compact}↑↓ PgUp PgDn scroll · s sort · c compact · ⇥ select · p path"
+    ))
+    .dim()
+    .to_string()
+}
+
+/// Heading of the path view, naming the probe the hops are traced with.
+pub fn render_path_header(
+    host: &types::Hostname,
+    addr: Option<IpAddr>,
+    probe: types::Probe,
+) -> String {
+    let title = console::style(format!("path to {}", host.display_name())).bold();
+    let addr = addr.map_or_else(|| "resolving...".to_owned(), |addr| addr.to_string());
+    let probe = match probe {
+        types::Probe::Icmp | types::Probe::IcmpDgram => "ICMP echo".to_owned(),
+        types::Probe::Tcp(port) => format!("TCP port {port}"),
+        types::Probe::Udp(port) => format!("UDP port {port}"),
+    };
+    format!(
+        "{title} {}",
+        console::style(format!("({addr}) via {probe}")).dim()
+    )
+}
+
+/// Prefix of a hop in the path view: its TTL and the address that answered,
+/// `???` while none did, padded to `addr_width`.
+pub fn render_hop_prefix(ttl: usize, addr: Option<IpAddr>, addr_width: usize) -> String {
+    let addr = addr.map_or_else(|| "???".to_owned(), |addr| addr.to_string());
+    format!("{ttl:>2}. {addr:<addr_width$}")
+}
+
+/// Width of the address column of the path view.
+pub fn hop_addr_width(addrs: impl IntoIterator<Item = Option<IpAddr>>) -> usize {
+    addrs
+        .into_iter()
+        .map(|addr| addr.map_or(3, |addr| addr.to_string().len()))
+        .max()
+        .unwrap_or(3)
+}
+
+/// Status line below the path view.
+pub fn render_path_footer(shown: std::ops::Range<usize>, total: usize) -> String {
+    let rows = if shown.len() < total {
+        format!("hops {}-{} of {total} · ", shown.start + 1, shown.end)
+    } else {
+        String::new()
+    };
+    console::style(format!(
+        "{rows}↑↓ PgUp PgDn scroll · ⇥ next host · p back to all hosts"
+    ))
+    .dim()
+    .to_string()
 }
 
 /// Width of the "resolved address" column: the widest ` (addr)` seen so far.
@@ -118,6 +231,75 @@ pub fn render_resolved_text(
     ))
 }
 
+/// Pad a short status (`rtt=…ms`, `waiting`, …) to the status column width.
+pub fn render_status(status: &str) -> String {
+    format!("{status:<STATUS_WIDTH$}")
+}
+
+/// Status column for a failure line: how long the host has been failing.
+pub fn render_outage(down_for: Duration) -> String {
+    render_status(&format!("down {:.1}s", down_for.as_secs_f64()))
+}
+
+/// Render the rolling statistics as fixed-width columns.
+///
+/// Missing RTT figures (no reply yet) are shown as `-` so every line keeps the
+/// same width. A negative streak counts consecutive failures.
+pub fn render_stats(stats: &HostStats) -> String {
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_owned(), |ms| format!("{ms:.1}"));
+    let rtt = stats.rtt_ms();
+    let mut line = format!(
+        "sent {:>5}  lost {:>5} ({:>5.1}%)  min/avg/max {:>7}/{:>7}/{:>7} ms  jitter {:>6} ms  streak {:>+5}",
+        stats.transmitted,
+        stats.lost(),
+        stats.loss_percent(),
+        ms(rtt.map(|(min, ..)| min)),
+        ms(rtt.map(|(_, avg, ..)| avg)),
+        ms(rtt.map(|(_, _, max, _)| max)),
+        ms(stats.jitter_ms()),
+        stats.streak,
+    );
+    // Rare enough to only take room once they happen.
+    for (count, what) in [
+        (stats.duplicates, "dup"),
+        (stats.late, "late"),
+        (stats.reordered, "reordered"),
+    ] {
+        if count > 0 {
+            line += &format!("  {what} {count}");
+        }
+    }
+    line
+}
+
+/// Slowest RTT among sparkline samples, or 0 when none got a reply.
+pub fn sparkline_max<'a>(samples: impl IntoIterator<Item = &'a Option<f64>>) -> f64 {
+    samples.into_iter().flatten().copied().fold(0.0, f64::max)
+}
+
+/// Render RTT samples (in ms, `None` for lost pings) as a sparkline where the
+/// tallest block stands for `max_ms`. The line is right-aligned in `width`
+/// columns so that sparklines of different lengths end in the same column.
+pub fn render_sparkline<'a>(
+    samples: impl IntoIterator<Item = &'a Option<f64>>,
+    max_ms: f64,
+    width: usize,
+) -> String {
+    let top = SPARKLINE_LEVELS.len() - 1;
+    let line: String = samples
+        .into_iter()
+        .map(|sample| match sample {
+            None => SPARKLINE_LOST,
+            Some(_) if max_ms <= 0.0 => SPARKLINE_LEVELS[0],
+            Some(ms) => {
+                let level = (ms / max_ms * top as f64).round() as usize;
+                SPARKLINE_LEVELS[level.min(top)]
+            }
+        })
+        .collect();
+    format!("{line:>width$}")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,6 +314,18 @@ mod tests {
         assert_eq!(render_resolved_text(0, None), None);
     }
 
+    #[test]
+    fn path_header_names_the_hop_probe() {
+        let host = "db".parse::<types::Hostname>().unwrap();
+        let header = console::strip_ansi_codes(&render_path_header(
+            &host,
+            Some("10.0.0.1".parse().unwrap()),
+            types::Probe::Udp(33434),
+        ))
+        .into_owned();
+        assert_eq!(header, "path to db (10.0.0.1) via UDP port 33434");
+    }
+
     #[test]
     fn shows_resolved_addr_for_hostname_inputs() {
         let host = "example.com".parse::<types::Hostname>().unwrap();
@@ -173,6 +367,120 @@ mod tests {
         assert!(!without_addr.contains('('));
     }
 
+    #[test]
+    fn labels_replace_the_host_in_the_display() {
+        let hosts: Vec<types::Hostname> =
+            toml_edit::de::from_str::<Hosts>("hosts = [{ name = \"10.0.0.5\", label = \"db\" }]")
+                .unwrap()
+                .hosts;
+
+        assert_eq!(render_host_text(&hosts[0], 4), "db  ");
+        assert!(render_header(Section::Group("backend")).contains("backend"));
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Hosts {
+        hosts: Vec<types::Hostname>,
+    }
+
+    #[test]
+    fn compact_cells_keep_their_width() {
+        let host = "db".parse::<types::Hostname>().unwrap();
+        let mut stats = HostStats::default();
+        let fresh = console::strip_ansi_codes(&render_cell(&host, 5, false, &stats)).into_owned();
+        stats.record_success(Duration::from_micros(123_400));
+        let up = console::strip_ansi_codes(&render_cell(&host, 5, false, &stats)).into_owned();
+        stats.record_failure();
+        let down = console::strip_ansi_codes(&render_cell(&host, 5, false, &stats)).into_owned();
+
+        assert_eq!(fresh, "● db         ...  ");
+        assert_eq!(up, "● db     123.4ms  ");
+        assert_eq!(down, "● db        down  ");
+        assert_eq!(up.chars().count(), cell_width(5));
+    }
+
+    #[test]
+    fn selected_cells_keep_their_width() {
+        let host = "db".parse::<types::Hostname>().unwrap();
+        let stats = HostStats::default();
+        let selected = render_cell(&host, 5, true, &stats);
+        assert_eq!(
+            console::strip_ansi_codes(&selected),
+            console::strip_ansi_codes(&render_cell(&host, 5, false, &stats))
+        );
+    }
+
+    #[test]
+    fn hops_line_up_whatever_answered() {
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let width = hop_addr_width([None, Some(addr)]);
+        assert_eq!(width, 8);
+        assert_eq!(render_hop_prefix(1, Some(addr), width), " 1. 10.0.0.1");
+        assert_eq!(render_hop_prefix(12, None, width), "12. ???     ");
+        assert_eq!(hop_addr_width([]), 3);
+    }
+
+    #[test]
+    fn footer_shows_the_visible_rows_only_when_scrolling() {
+        let scrolled = render_footer(10..50, 200, types::SortOrder::Loss, false);
+        assert!(scrolled.contains("rows 11-50 of 200"));
+        assert!(scrolled.contains("sort: loss"));
+        assert!(!render_footer(0..5, 5, types::SortOrder::Name, true).contains("rows"));
+    }
+
+    #[test]
+    fn stats_columns_keep_their_width() {
+        let fresh = HostStats::default();
+        let mut busy = HostStats::default();
+        for ms in [12, 1500, 3] {
+            busy.record_success(Duration::from_millis(ms));
+        }
+        busy.record_failure();
+
+        assert_eq!(
+            render_stats(&fresh),
+            "sent     0  lost     0 (  0.0%)  min/avg/max       -/      -/      - ms  jitter      - ms  streak    +0"
+        );
+        assert_eq!(
+            render_stats(&busy),
+            "sent     4  lost     1 ( 25.0%)  min/avg/max     3.0/  505.0/ 1500.0 ms  jitter 1492.5 ms  streak    -1"
+        );
+        assert_eq!(
+            render_status("waiting").len(),
+            render_outage(Duration::from_secs(3)).len()
+        );
+    }
+
+    #[test]
+    fn stray_replies_are_counted_once_seen() {
+        let mut stats = HostStats::default();
+        stats.record_success(Duration::from_millis(1));
+        assert!(render_stats(&stats).ends_with("streak    +1"));
+        stats.late += 1;
+        assert!(render_stats(&stats).ends_with("streak    +1  late 1"));
+        stats.duplicates += 1;
+        stats.reordered += 1;
+        assert!(render_stats(&stats).ends_with("streak    +1  dup 1  late 1  reordered 1"));
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_given_maximum() {
+        let samples = [Some(0.0), Some(5.0), None, Some(10.0)];
+        assert_eq!(sparkline_max(&samples), 10.0);
+        assert_eq!(render_sparkline(&samples, 10.0, 4), "▁▅×█");
+        assert_eq!(render_sparkline(&samples, 20.0, 4), "▁▃×▅");
+        // Samples above the maximum are clamped to the tallest block.
+        assert_eq!(render_sparkline(&[Some(30.0)], 20.0, 1), "█");
+    }
+
+    #[test]
+    fn sparkline_is_right_aligned_and_survives_total_loss() {
+        let samples = [None, None];
+        assert_eq!(sparkline_max(&samples), 0.0);
+        assert_eq!(render_sparkline(&samples, 0.0, 5), "   ××");
+        assert_eq!(render_sparkline(&[Some(1.0)], 0.0, 3), "  ▁");
+    }
+
     #[test]
     fn resolved_width_tracks_longest_seen_addr() {
         let ipv4: IpAddr = "93.184.216.34".parse().unwrap();
@@ -1,130 +1,318 @@
 //! Mutable printer state.
 //!
-//! This module owns the set of progress bars and the bookkeeping that tracks
-//! per-host status (resolved address, ok/waiting style, column widths). It
-//! applies one `PingEvent` at a time via `handle`, delegating all string
-//! construction to `super::render`. It does not know about the tokio event
-//! loop or channels.
+//! This module owns the per-host bookkeeping (resolved address, ok/waiting
+//! style, column widths, rolling statistics, ongoing outages and recent RTTs),
+//! the highlighted host and its path, and a pool of progress bars, one per
+//! visible row. It applies one `PingEvent`, `HopEvent` or keyboard `Command`
+//! at a time; events only mark the display stale and `flush` redraws it, so
+//! a burst of events costs one layout. Redrawing asks `super::layout` which
+//! rows fit the terminal and delegates all string construction to
+//! `super::render`. It does not know about the
+//! tokio event loop or channels; the printer traces whichever host
+//! `path_target` names.
 
-use std::{net::IpAddr, sync::Arc, time::Duration};
+use std::{
+    collections::VecDeque,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use super::render;
-use crate::{event, spinner_style::SpinnerStyle, types};
+use super::{
+    Options, Sparkline,
+    input::Command,
+    layout::{self, Row},
+    render,
+};
+use crate::{event, stats::Stats, trace, types};
 
 const WAIT_TEMPLATE: &str = "{spinner:.yellow} {prefix} {msg}";
-const OK_TEMPLATE: &str = "{spinner:.green} {prefix} rtt={msg}";
+const OK_TEMPLATE: &str = "{spinner:.green} {prefix} {msg}";
+const PLAIN_TEMPLATE: &str = "{msg}";
 
-/// Aggregates the indicatif bars and the derived state needed to keep their
-/// prefixes aligned as new addresses are resolved.
+/// Terminal lines left below the display so that it never scrolls.
+const RESERVED_LINES: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RowStyle {
+    Ok,
+    Wait,
+    Plain,
+}
+
+/// A bar of the row pool and what it currently shows, so unchanged rows are
+/// not redrawn.
+struct RowBar {
+    bar: indicatif::ProgressBar,
+    style: Option<RowStyle>,
+    prefix: String,
+    message: String,
+}
+
+/// Aggregates the per-host display state and the bars showing the rows that
+/// fit the terminal.
 pub(super) struct PrinterState {
     multi: indicatif::MultiProgress,
-    bars: Vec<indicatif::ProgressBar>,
+    /// One bar per visible row; hosts move between bars as the layout changes.
+    rows: Vec<RowBar>,
     hosts: Arc<[types::Hostname]>,
     host_width: usize,
     style_ok: indicatif::ProgressStyle,
     style_wait: indicatif::ProgressStyle,
-    bar_is_ok: Vec<bool>,
+    style_plain: indicatif::ProgressStyle,
+    host_is_ok: Vec<bool>,
+    /// What to show before the first ping ("resolving...", "resolved", ...).
+    notes: Vec<String>,
     resolved_addrs: Vec<Option<IpAddr>>,
     resolved_width: usize,
+    stats: Stats,
+    /// When the current run of failures started, per host.
+    down_since: Vec<Option<Instant>>,
+    /// Latest status column per host, once pings have started.
+    statuses: Vec<Option<String>>,
+    sparkline: Option<Sparkline>,
+    /// Recent RTTs in milliseconds, `None` for lost pings; at most
+    /// `sparkline.width` entries per host.
+    history: Vec<VecDeque<Option<f64>>>,
+    sort: types::SortOrder,
+    compact: bool,
+    /// First visible row when not every row fits.
+    offset: usize,
+    /// Rows per page at the last redraw.
+    page: usize,
+    /// Whether keyboard commands arrive, so the highlight is worth showing.
+    keyboard: bool,
+    /// The highlighted host.
+    selected: usize,
+    /// Scroll to the highlighted host at the next redraw.
+    follow_selected: bool,
+    /// Hosts in display order at the last redraw.
+    order: Vec<usize>,
+    /// Latest address of every host, literal IPs included.
+    addrs: Vec<Option<IpAddr>>,
+    /// Show the path to the highlighted host instead of the host list.
+    path_view: bool,
+    /// The path being traced and the host it leads to, in the path view.
+    path: Option<(usize, trace::Path)>,
+    /// First visible hop of the path view.
+    path_offset: usize,
+    /// Something changed since the last redraw.
+    dirty: bool,
 }
 
 impl PrinterState {
-    /// Build a `PrinterState` with one spinner per host in "resolving..." state.
-    pub(super) fn new(hosts: Arc<[types::Hostname]>, spinner_style: SpinnerStyle) -> Self {
-        let multi = indicatif::MultiProgress::new();
-        let host_width = hosts.iter().map(|h| h.as_str().len()).max().unwrap_or(0);
-        let style_ok = render::make_style(OK_TEMPLATE, spinner_style);
-        let style_wait = render::make_style(WAIT_TEMPLATE, spinner_style);
-
-        let bars: Vec<indicatif::ProgressBar> = hosts
+    /// Build a `PrinterState` with every host in "resolving..." state.
+    pub(super) fn new(hosts: Arc<[types::Hostname]>, options: &Options) -> Self {
+        let host_width = hosts
             .iter()
-            .map(|host| {
-                let pb = multi.add(indicatif::ProgressBar::new_spinner());
-                pb.set_style(style_wait.clone());
-                pb.set_prefix(render::render_prefix(host, host_width, 0, None));
-                pb.set_message("resolving...");
-                pb
-            })
-            .collect();
-
-        let n = bars.len();
-        Self {
-            multi,
-            bars,
-            hosts,
+            .map(|h| h.display_name().chars().count())
+            .max()
+            .unwrap_or(0);
+        let n = hosts.len();
+        let mut state = Self {
+            multi: indicatif::MultiProgress::new(),
+            rows: Vec::new(),
             host_width,
-            style_ok,
-            style_wait,
-            bar_is_ok: vec![false; n],
+            style_ok: render::make_style(OK_TEMPLATE, options.spinner_style),
+            style_wait: render::make_style(WAIT_TEMPLATE, options.spinner_style),
+            style_plain: render::make_style(PLAIN_TEMPLATE, options.spinner_style),
+            host_is_ok: vec![false; n],
+            notes: vec!["resolving...".to_owned(); n],
             resolved_addrs: vec![None; n],
             resolved_width: 0,
-        }
+            stats: Stats::new(Arc::clone(&hosts)),
+            down_since: vec![None; n],
+            statuses: vec![None; n],
+            sparkline: options.sparkline,
+            history: vec![VecDeque::new(); n],
+            sort: options.sort,
+            compact: options.compact,
+            offset: 0,
+            page: 1,
+            keyboard: options.keyboard,
+            selected: 0,
+            follow_selected: false,
+            order: (0..n).collect(),
+            addrs: vec![None; n],
+            path_view: options.path,
+            path: None,
+            path_offset: 0,
+            dirty: false,
+            hosts,
+        };
+        state.redraw();
+        state
+    }
+
+    /// The host whose path the printer should trace and the address to
+    /// trace, while the path view is shown.
+    pub(super) fn path_target(&self) -> Option<(usize, IpAddr)> {
+        self.path.as_ref().map(|(i, path)| (*i, path.target()))
     }
 
-    /// Apply a single event to the bar identified by its `idx`. Events whose
+    /// Give up the display and keep the statistics gathered so far.
+    pub(super) fn into_stats(self) -> Stats {
+        self.stats
+    }
+
+    /// Apply a single event to the host identified by its `idx`. Events whose
     /// index is out of range are silently ignored, preserving robustness when
     /// the upstream channel briefly becomes inconsistent with the host list.
     pub(super) fn handle(&mut self, ev: event::PingEvent) {
         let i = ev.idx().as_usize();
-        if self.bars.get(i).is_none() {
+        if let event::PingEvent::HostAdded { host, .. } = &ev
+            && i == self.hosts.len()
+        {
+            self.on_added(host.clone());
+        }
+        if self.hosts.get(i).is_none() {
             return;
         }
+        self.stats.record(&ev);
         match ev {
             event::PingEvent::Resolved { addr, .. } => self.on_resolved(i, addr),
             event::PingEvent::ResolutionFailed { error, .. } => self.on_resolution_failed(i, error),
+            event::PingEvent::AddressChanged { from, to, .. } => {
+                self.on_address_changed(i, from, to)
+            }
             event::PingEvent::Success { rtt, .. } => self.on_success(i, rtt),
+            event::PingEvent::LateReply { seq, rtt, .. } => {
+                self.on_stray_reply(i, "LATE", seq, rtt)
+            }
+            event::PingEvent::Duplicate { seq, rtt, .. } => {
+                self.on_stray_reply(i, "DUP!", seq, rtt)
+            }
             event::PingEvent::Failure { error, .. } => self.on_failure(i, error),
+            event::PingEvent::PathMtu { mtu, .. } => self.on_path_mtu(i, mtu),
+            event::PingEvent::HostAdded { .. } => self.log(i, console::style("ADDED").cyan(), ""),
+            event::PingEvent::HostRemoved { .. } => self.on_removed(i),
         }
+        self.sync_path();
+        self.dirty = true;
     }
 
-    /// Advance every spinner by one frame.
-    #[cfg(feature = "animated-spinners")]
-    pub(super) fn tick(&self) {
-        for bar in &self.bars {
-            bar.tick();
+    /// Account for a probe of the traced path. Probes of a path no longer
+    /// shown are ignored.
+    pub(super) fn handle_hop(&mut self, target: (usize, IpAddr), ev: trace::HopEvent) {
+        match &mut self.path {
+            Some((i, path)) if (*i, path.target()) == target => path.record(&ev),
+            _ => return,
         }
+        self.dirty = true;
     }
 
-    fn on_resolved(&mut self, i: usize, addr: IpAddr) {
-        let display_addr = render::resolved_addr_for_display(&self.hosts[i], addr);
-        self.resolved_addrs[i] = display_addr;
-        let next_resolved_width = render::resolved_text_width(&self.resolved_addrs);
-        // A growing resolved-address column forces us to re-render every
-        // prefix so columns stay aligned; otherwise only this bar changes.
-        if next_resolved_width != self.resolved_width {
-            self.resolved_width = next_resolved_width;
-            render::refresh_prefixes(
-                &self.bars,
-                &self.hosts,
-                self.host_width,
-                self.resolved_width,
-                &self.resolved_addrs,
-            );
+    /// Redraw if anything changed since the last redraw.
+    pub(super) fn flush(&mut self) {
+        if self.dirty {
+            self.redraw();
+        }
+    }
+
+    /// Scroll, re-sort or re-pack the display, move the highlight or switch
+    /// between the host list and the path view.
+    pub(super) fn command(&mut self, command: Command) {
+        // Act on what is on screen, not on the layout before pending events.
+        self.flush();
+        let page = self.page;
+        let offset = if self.path_view {
+            &mut self.path_offset
         } else {
-            self.bars[i].set_prefix(render::render_prefix(
-                &self.hosts[i],
-                self.host_width,
-                self.resolved_width,
-                display_addr,
-            ));
+            &mut self.offset
+        };
+        match command {
+            Command::ScrollUp => *offset = offset.saturating_sub(1),
+            Command::ScrollDown => *offset = offset.saturating_add(1),
+            Command::PageUp => *offset = offset.saturating_sub(page),
+            Command::PageDown => *offset = offset.saturating_add(page),
+            Command::Home => *offset = 0,
+            // Clamped to the last page by the next redraw.
+            Command::End => *offset = usize::MAX,
+            Command::CycleSort => self.sort = self.sort.next(),
+            Command::ToggleCompact => self.compact = !self.compact,
+            Command::NextHost => self.select(1),
+            Command::PreviousHost => self.select(self.order.len().saturating_sub(1)),
+            Command::TogglePath => self.path_view = !self.path_view,
         }
-        self.bars[i].set_message("resolved");
+        self.sync_path();
+        self.redraw();
     }
 
-    fn on_resolution_failed(&mut self, i: usize, error: types::ResolveError) {
-        self.bars[i].finish_with_message(format!("resolution failed: {error}"));
+    /// Highlight the host `step` places after the highlighted one in display
+    /// order, wrapping around.
+    fn select(&mut self, step: usize) {
+        if self.order.is_empty() {
+            return;
+        }
+        let position = self
+            .order
+            .iter()
+            .position(|&i| i == self.selected)
+            .unwrap_or(0);
+        self.selected = self.order[(position + step) % self.order.len()];
+        self.follow_selected = true;
     }
 
-    fn on_success(&mut self, i: usize, rtt: Duration) {
-        let ms = rtt.as_secs_f64() * 1000.0;
-        if !self.bar_is_ok[i] {
-            self.bars[i].set_style(self.style_ok.clone());
-            self.bar_is_ok[i] = true;
+    /// Start a new path whenever the traced host or its address changes, and
+    /// drop it outside the path view.
+    fn sync_path(&mut self) {
+        let target = self
+            .path_view
+            .then(|| self.addrs.get(self.selected).copied().flatten())
+            .flatten()
+            .map(|addr| (self.selected, addr));
+        if target != self.path_target() {
+            self.path = target.map(|(i, addr)| (i, trace::Path::new(addr)));
+            self.path_offset = 0;
         }
-        self.bars[i].set_message(format!("{ms:.1}ms"));
     }
 
-    fn on_failure(&mut self, i: usize, error: event::PingFailure) {
+    /// Advance every spinner by one frame.
+    #[cfg(feature = "animated-spinners")]
+    pub(super) fn tick(&self) {
+        for row in &self.rows {
+            row.bar.tick();
+        }
+    }
+
+    /// Make room for a host joining at the end of the list, in "resolving..."
+    /// state like the ones there from the start.
+    fn on_added(&mut self, host: types::Hostname) {
+        self.host_width = self.host_width.max(host.display_name().chars().count());
+        self.hosts = self.hosts.iter().cloned().chain([host]).collect();
+        self.host_is_ok.push(false);
+        self.notes.push("resolving...".to_owned());
+        self.resolved_addrs.push(None);
+        self.down_since.push(None);
+        self.statuses.push(None);
+        self.history.push(VecDeque::new());
+        self.addrs.push(None);
+    }
+
+    /// Finish a removed host's row with its final statistics above the bars;
+    /// the highlight moves on to the next host.
+    fn on_removed(&mut self, i: usize) {
+        let stats = self
+            .stats
+            .host(types::HostIdx::new(i))
+            .expect("index checked in handle");
+        let detail = render::render_stats(stats);
+        self.log(i, console::style("REMOVED").cyan(), &detail);
+        self.addrs[i] = None;
+        if self.selected == i {
+            self.select(1);
+        }
+    }
+
+    /// Print a message that concerns no host in particular above the bars.
+    pub(super) fn notice(&self, message: &str) {
+        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        let _ = self
+            .multi
+            .println(format!("{}  {message}", console::style(timestamp).dim()));
+    }
+
+    /// Print a line about host `i` above the bars.
+    fn log(&self, i: usize, label: console::StyledObject<&str>, detail: &str) {
         let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
         let prefix = render::render_failure_prefix(
             &self.hosts[i],
@@ -133,15 +321,453 @@ impl PrinterState {
             self.resolved_addrs[i],
         );
         let _ = self.multi.println(format!(
-            "{}  {}  {}  {error}",
+            "{}  {}  {}  {detail}",
             console::style(timestamp).dim(),
             prefix,
-            console::style("FAILED").red().bold(),
+            label.bold(),
+        ));
+    }
+
+    fn on_resolved(&mut self, i: usize, addr: IpAddr) {
+        self.addrs[i] = Some(addr);
+        self.set_resolved_addr(i, addr);
+        self.notes[i] = "resolved".to_owned();
+    }
+
+    /// Show the new address in the resolved-address column and log the change
+    /// above the bars.
+    fn on_address_changed(&mut self, i: usize, from: IpAddr, to: IpAddr) {
+        self.addrs[i] = Some(to);
+        self.set_resolved_addr(i, to);
+        self.log(
+            i,
+            console::style("ADDRESS CHANGED").yellow(),
+            &format!("{from} -> {to}"),
+        );
+    }
+
+    /// Log the discovered path MTU above the bars.
+    fn on_path_mtu(&mut self, i: usize, mtu: u32) {
+        self.log(i, console::style("PATH MTU").cyan(), &mtu.to_string());
+    }
+
+    /// Record the address shown next to a host; the column grows to the
+    /// widest address so that every row stays aligned.
+    fn set_resolved_addr(&mut self, i: usize, addr: IpAddr) {
+        self.resolved_addrs[i] = render::resolved_addr_for_display(&self.hosts[i], addr);
+        self.resolved_width = render::resolved_text_width(&self.resolved_addrs);
+    }
+
+    fn on_resolution_failed(&mut self, i: usize, error: types::ResolveError) {
+        self.notes[i] = format!("resolution failed: {error}, retrying");
+    }
+
+    fn on_success(&mut self, i: usize, rtt: Duration) {
+        let ms = rtt.as_secs_f64() * 1000.0;
+        self.host_is_ok[i] = true;
+        self.down_since[i] = None;
+        self.push_history(i, Some(ms));
+        self.statuses[i] = Some(format!("rtt={ms:.1}ms"));
+    }
+
+    /// Note a duplicate or late reply above the bars, like ping(8) notes
+    /// duplicates; the bar keeps showing the probes that count.
+    fn on_stray_reply(&self, i: usize, label: &'static str, seq: u16, rtt: Duration) {
+        let detail = format!("seq={seq} rtt={:.1}ms", rtt.as_secs_f64() * 1000.0);
+        self.log(i, console::style(label).yellow(), &detail);
+    }
+
+    fn on_failure(&mut self, i: usize, error: event::PingFailure) {
+        let down_since = *self.down_since[i].get_or_insert_with(Instant::now);
+        let stats = self
+            .stats
+            .host(types::HostIdx::new(i))
+            .expect("index checked in handle");
+        let detail = format!(
+            "{}{}  {error}",
+            render::render_outage(down_since.elapsed()),
+            render::render_stats(stats),
+        );
+        self.log(i, console::style("FAILED").red(), &detail);
+        self.host_is_ok[i] = false;
+        self.push_history(i, None);
+        self.statuses[i] = Some("waiting".to_owned());
+    }
+
+    fn push_history(&mut self, i: usize, sample: Option<f64>) {
+        let Some(sparkline) = self.sparkline else {
+            return;
+        };
+        let history = &mut self.history[i];
+        if history.len() == sparkline.width {
+            history.pop_front();
+        }
+        history.push_back(sample);
+    }
+
+    /// Lay the rows out for the current terminal size and show the visible
+    /// ones, with a footer when they don't all fit or are sorted or packed.
+    fn redraw(&mut self) {
+        self.dirty = false;
+        let (height, width) = console::Term::stderr().size();
+        let capacity = usize::from(height).saturating_sub(RESERVED_LINES).max(2);
+        let per_row = self
+            .compact
+            .then(|| layout::cells_per_row(width.into(), render::cell_width(self.host_width)));
+        let hosts = Arc::clone(&self.hosts);
+        let rows = layout::rows(&hosts, &self.stats, self.sort, per_row);
+        self.order = layout::hosts_in_order(&rows);
+        if self.path_view && self.order.contains(&self.selected) {
+            self.redraw_path(capacity);
+            return;
+        }
+        let footer = rows.len() > capacity || self.sort != types::SortOrder::None || self.compact;
+        let page = capacity - usize::from(footer);
+        if std::mem::take(&mut self.follow_selected)
+            && let Some(row) = layout::row_of(&rows, self.selected)
+        {
+            // Scroll just enough to bring the highlighted host into view.
+            let start = layout::window(rows.len(), self.offset, page).start;
+            if row < start {
+                self.offset = row;
+            } else if row >= start + page {
+                self.offset = row + 1 - page;
+            }
+        }
+        let shown = layout::window(rows.len(), self.offset, page);
+        self.offset = shown.start;
+        self.page = shown.len().max(1);
+        let selected = self.keyboard.then_some(self.selected);
+
+        // With a global sparkline scale every host shares the slowest sample.
+        let sparkline_max = match self.sparkline {
+            Some(Sparkline {
+                scale: types::SparklineScale::Global,
+                ..
+            }) => Some(render::sparkline_max(self.history.iter().flatten())),
+            _ => None,
+        };
+        let mut lines: Vec<(RowStyle, String, String)> = rows[shown.clone()]
+            .iter()
+            .map(|row| match row {
+                Row::Header(section) => (
+                    RowStyle::Plain,
+                    String::new(),
+                    render::render_header(*section),
+                ),
+                &Row::Host { idx, continues } => (
+                    if self.host_is_ok[idx] {
+                        RowStyle::Ok
+                    } else {
+                        RowStyle::Wait
+                    },
+                    render::render_prefix(
+                        &hosts[idx],
+                        continues,
+                        selected == Some(idx),
+                        self.host_width,
+                        self.resolved_width,
+                        self.resolved_addrs[idx],
+                    ),
+                    self.message(idx, sparkline_max),
+                ),
+                Row::Cells(cells) => (
+                    RowStyle::Plain,
+                    String::new(),
+                    cells
+                        .iter()
+                        .map(|&i| {
+                            let stats = self
+                                .stats
+                                .host(types::HostIdx::new(i))
+                                .expect("layout yields valid indices");
+                            render::render_cell(
+                                &hosts[i],
+                                self.host_width,
+                                selected == Some(i),
+                                stats,
+                            )
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+        if footer {
+            lines.push((
+                RowStyle::Plain,
+                String::new(),
+                render::render_footer(shown, rows.len(), self.sort, self.compact),
+            ));
+        }
+        self.show(lines);
+    }
+
+    /// Show the path to the highlighted host: a heading, one row per hop and
+    /// a footer.
+    fn redraw_path(&mut self, capacity: usize) {
+        let i = self.selected;
+        let mut lines = vec![(
+            RowStyle::Plain,
+            String::new(),
+            render::render_path_header(
+                &self.hosts[i],
+                self.addrs[i],
+                trace::hop_probe(self.hosts[i].probe()),
+            ),
+        )];
+        let hops = self.path.as_ref().map_or(&[][..], |(_, path)| path.hops());
+        let shown = layout::window(hops.len(), self.path_offset, capacity - 2);
+        self.path_offset = shown.start;
+        self.page = shown.len().max(1);
+        let addr_width = render::hop_addr_width(hops.iter().map(|hop| hop.addr));
+        for (ttl, hop) in hops.iter().enumerate().skip(shown.start).take(shown.len()) {
+            lines.push((
+                if hop.stats.streak > 0 {
+                    RowStyle::Ok
+                } else {
+                    RowStyle::Wait
+                },
+                render::render_hop_prefix(ttl + 1, hop.addr, addr_width),
+                render::render_stats(&hop.stats),
+            ));
+        }
+        lines.push((
+            RowStyle::Plain,
+            String::new(),
+            render::render_path_footer(shown, hops.len()),
         ));
-        if self.bar_is_ok[i] {
-            self.bars[i].set_style(self.style_wait.clone());
-            self.bar_is_ok[i] = false;
+        self.show(lines);
+    }
+
+    /// Message of a host's row: its status, sparkline and statistics once
+    /// pings have started, else its note.
+    fn message(&self, i: usize, sparkline_max: Option<f64>) -> String {
+        let Some(status) = &self.statuses[i] else {
+            return self.notes[i].clone();
+        };
+        let stats = self
+            .stats
+            .host(types::HostIdx::new(i))
+            .expect("index checked in handle");
+        let sparkline = self
+            .sparkline
+            .map(|sparkline| {
+                let max_ms =
+                    sparkline_max.unwrap_or_else(|| render::sparkline_max(&self.history[i]));
+                let line = render::render_sparkline(&self.history[i], max_ms, sparkline.width);
+                line + "  "
+            })
+            .unwrap_or_default();
+        format!(
+            "{}{sparkline}{}",
+            render::render_status(status),
+            render::render_stats(stats)
+        )
+    }
+
+    /// Grow or shrink the row pool to `lines` and update the bars whose
+    /// content changed.
+    fn show(&mut self, lines: Vec<(RowStyle, String, String)>) {
+        while self.rows.len() < lines.len() {
+            self.rows.push(RowBar {
+                bar: self.multi.add(indicatif::ProgressBar::new_spinner()),
+                style: None,
+                prefix: String::new(),
+                message: String::new(),
+            });
+        }
+        for row in self.rows.drain(lines.len()..) {
+            self.multi.remove(&row.bar);
+        }
+        for (row, (style, prefix, message)) in self.rows.iter_mut().zip(lines) {
+            if row.style != Some(style) {
+                row.bar.set_style(match style {
+                    RowStyle::Ok => self.style_ok.clone(),
+                    RowStyle::Wait => self.style_wait.clone(),
+                    RowStyle::Plain => self.style_plain.clone(),
+                });
+                row.style = Some(style);
+            }
+            if row.prefix != prefix {
+                row.bar.set_prefix(prefix.clone());
+                row.prefix = prefix;
+            }
+            if row.message != message {
+                row.bar.set_message(message.clone());
+                row.message = message;
+            }
         }
-        self.bars[i].set_message("waiting");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{PingEvent, PingFailure};
+
+    fn state(names: &[&str]) -> PrinterState {
+        let hosts = names.iter().map(|s| s.parse().unwrap()).collect();
+        PrinterState::new(hosts, &Options::default())
+    }
+
+    fn reply(seq: u16, ms: u64) -> PingEvent {
+        PingEvent::Success {
+            idx: types::HostIdx::new(0),
+            seq,
+            rtt: Duration::from_millis(ms),
+        }
+    }
+
+    fn late(seq: u16, ms: u64) -> PingEvent {
+        PingEvent::LateReply {
+            idx: types::HostIdx::new(0),
+            seq,
+            rtt: Duration::from_millis(ms),
+            reordered: true,
+        }
+    }
+
+    fn duplicate(seq: u16, ms: u64) -> PingEvent {
+        PingEvent::Duplicate {
+            idx: types::HostIdx::new(0),
+            seq,
+            rtt: Duration::from_millis(ms),
+        }
+    }
+
+    fn timeout(seq: u16) -> PingEvent {
+        PingEvent::Failure {
+            idx: types::HostIdx::new(0),
+            error: PingFailure::Timeout { seq },
+        }
+    }
+
+    #[test]
+    fn counts_duplicate_late_and_reordered_replies() {
+        let mut state = state(&["a"]);
+        for ev in [
+            reply(0, 5),
+            duplicate(0, 6),
+            timeout(1),
+            reply(2, 5),
+            late(1, 700),
+            duplicate(1, 710),
+        ] {
+            state.handle(ev);
+        }
+        let stats = state.into_stats();
+        let host = stats.host(types::HostIdx::new(0)).unwrap();
+        assert_eq!((host.duplicates, host.late, host.reordered), (2, 1, 1));
+        assert_eq!((host.transmitted, host.received), (3, 2));
+        assert!(
+            stats
+                .to_string()
+                .contains("2 received, +2 duplicates, +1 late, 1 out of order,")
+        );
+    }
+
+    #[test]
+    fn late_replies_leave_the_host_failing() {
+        let mut state = state(&["a"]);
+        state.handle(reply(0, 5));
+        state.handle(timeout(1));
+        state.handle(late(1, 700));
+        assert!(!state.host_is_ok[0]);
+        assert_eq!(state.statuses[0].as_deref(), Some("waiting"));
+        let host = state.stats.host(types::HostIdx::new(0)).unwrap();
+        assert_eq!((host.streak, host.late), (-1, 1));
+    }
+
+    #[test]
+    fn hosts_join_and_leave_the_display() {
+        let mut state = state(&["a"]);
+        state.handle(PingEvent::HostAdded {
+            idx: types::HostIdx::new(1),
+            host: "longer.example".parse().unwrap(),
+        });
+        assert_eq!(state.host_width, "longer.example".len());
+        assert_eq!(state.order, [0]);
+        state.flush();
+        assert_eq!(state.order, [0, 1]);
+        assert_eq!(state.notes[1], "resolving...");
+
+        state.handle(PingEvent::HostRemoved {
+            idx: types::HostIdx::new(0),
+        });
+        state.flush();
+        assert_eq!(state.order, [1]);
+        assert_eq!(state.selected, 1);
+        assert!(state.into_stats().removed(types::HostIdx::new(0)));
+    }
+
+    /// More hosts than any terminal has lines.
+    fn crowded() -> PrinterState {
+        let names: Vec<_> = (0..300).map(|i| format!("h{i:03}")).collect();
+        let names: Vec<_> = names.iter().map(String::as_str).collect();
+        state(&names)
+    }
+
+    #[test]
+    fn scrolls_by_row_and_page_and_to_either_end() {
+        let mut state = crowded();
+        let page = state.page;
+        let last = 300 - page;
+        let mut offsets = Vec::new();
+        for command in [
+            Command::ScrollDown,
+            Command::PageDown,
+            Command::ScrollUp,
+            Command::End,
+            Command::ScrollDown,
+            Command::Home,
+            Command::PageUp,
+        ] {
+            state.command(command);
+            offsets.push(state.offset);
+        }
+        assert_eq!(offsets, [1, 1 + page, page, last, last, 0, 0]);
+    }
+
+    #[test]
+    fn sorting_pins_failing_hosts_and_cycles_back() {
+        let mut state = crowded();
+        state.handle(PingEvent::Failure {
+            idx: types::HostIdx::new(299),
+            error: PingFailure::Timeout { seq: 0 },
+        });
+        state.command(Command::CycleSort);
+        assert_eq!(state.sort, types::SortOrder::Name);
+        assert_eq!(state.order[..3], [299, 0, 1]);
+        for _ in 0..3 {
+            state.command(Command::CycleSort);
+        }
+        assert_eq!(state.sort, types::SortOrder::None);
+        assert_eq!(state.order[..3], [0, 1, 2]);
+    }
+
+    #[test]
+    fn compact_mode_packs_hosts_into_fewer_rows() {
+        let mut state = crowded();
+        state.command(Command::End);
+        let last = state.offset;
+        state.command(Command::ToggleCompact);
+        state.command(Command::End);
+        assert!(state.compact);
+        assert!(state.offset < last, "{} < {last}", state.offset);
+        assert_eq!(state.order.len(), 300);
+        state.command(Command::ToggleCompact);
+        assert!(!state.compact);
+    }
+
+    #[test]
+    fn highlight_moves_in_display_order_and_wraps() {
+        let mut state = state(&["a", "b", "c"]);
+        state.command(Command::NextHost);
+        assert_eq!(state.selected, 1);
+        state.command(Command::PreviousHost);
+        state.command(Command::PreviousHost);
+        assert_eq!(state.selected, 2);
+        state.command(Command::NextHost);
+        assert_eq!(state.selected, 0);
     }
 }
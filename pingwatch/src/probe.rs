@@ -0,0 +1,629 @@
+//! Probe kinds: how a worker times one round trip to a resolved address.
+//!
+//! ICMP probes go through the shared `client::PingClients`. TCP and UDP
+//! probes open a fresh socket for every round trip, so a late answer to an
+//! earlier probe can never be taken for the current one. Whatever the kind,
+//! the outcome is an RTT or a `PingFailure`, which is all the worker and the
+//! printer ever see.
+//!
+//! `Prober::send` starts a round trip that owns everything it needs and may
+//! wait longer than the probe timeout, so that a worker can keep listening
+//! for a late reply while it sends the next probe. Replies that come after
+//! the round trip is over are `Stray`s, reported where the prober can see
+//! them: ICMP probes get them from their client, UDP probes keep reading
+//! their socket. A TCP handshake completes once, so TCP probes have none.
+//!
+//! `SocketOptions` (TTL, TOS and don't-fragment) apply to every kind: TCP and
+//! UDP probes set them on their own sockets, ICMP probes get a shared client
+//! opened with them.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    client,
+    event::{self, PingFailure},
+    types::{self, SocketOptions},
+};
+
+/// Default payload size of ICMP echo requests and UDP datagrams.
+pub const DEFAULT_PAYLOAD_LEN: usize = 8;
+
+/// A reply to probe `seq` that no round trip waited for any more: another
+/// answer to a probe already answered, or one that came after the round
+/// trip gave up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stray {
+    pub seq: u16,
+    pub at: Instant,
+}
+
+#[derive(Clone)]
+pub enum Prober {
+    Icmp {
+        client: client::IcmpClient,
+        addr: IpAddr,
+        id: surge_ping::PingIdentifier,
+        timeout: Duration,
+        payload: Vec<u8>,
+        dont_fragment: bool,
+        /// Routes stray replies to the worker while any clone is alive.
+        _listener: Option<Arc<client::Listener>>,
+    },
+    Tcp {
+        addr: SocketAddr,
+        timeout: Duration,
+        options: SocketOptions,
+    },
+    Udp {
+        addr: SocketAddr,
+        timeout: Duration,
+        payload_len: usize,
+        options: SocketOptions,
+        strays: Option<mpsc::Sender<Stray>>,
+    },
+}
+
+impl Prober {
+    /// Prepare probes of kind `probe` towards `addr`, carrying `payload_len`
+    /// bytes of payload where the probe has one.
+    ///
+    /// # Panics
+    ///
+    /// For ICMP probes when `clients` was not created for this probe kind
+    /// and these options.
+    pub async fn new(
+        probe: types::Probe,
+        addr: IpAddr,
+        clients: &client::PingClients,
+        id: surge_ping::PingIdentifier,
+        timeout: Duration,
+        payload_len: usize,
+        options: SocketOptions,
+    ) -> Self {
+        match probe {
+            types::Probe::Icmp | types::Probe::IcmpDgram => {
+                let client = clients
+                    .for_addr(probe, options, addr)
+                    .expect("PingClients opened for every ICMP probe kind in use");
+                Self::Icmp {
+                    client: client.clone(),
+                    addr,
+                    id,
+                    timeout,
+                    payload: vec![0; payload_len],
+                    dont_fragment: options.dont_fragment,
+                    _listener: None,
+                }
+            }
+            types::Probe::Tcp(port) => Self::Tcp {
+                addr: SocketAddr::new(addr, port),
+                timeout,
+                options,
+            },
+            types::Probe::Udp(port) => Self::Udp {
+                addr: SocketAddr::new(addr, port),
+                timeout,
+                payload_len,
+                options,
+                strays: None,
+            },
+        }
+    }
+
+    /// Report the stray replies to later probes on `strays`, where the probe
+    /// kind can see them.
+    pub fn report_strays(&mut self, tx: mpsc::Sender<Stray>) {
+        match self {
+            Self::Icmp {
+                client,
+                addr,
+                id,
+                _listener,
+                ..
+            } => *_listener = Some(Arc::new(client.listen(*addr, *id, tx))),
+            Self::Udp { strays, .. } => *strays = Some(tx),
+            Self::Tcp { .. } => {}
+        }
+    }
+
+    /// Change the payload size of later probes; TCP probes have none.
+    pub fn set_payload_len(&mut self, len: usize) {
+        match self {
+            Self::Icmp { payload, .. } => payload.resize(len, 0),
+            Self::Udp { payload_len, .. } => *payload_len = len,
+            Self::Tcp { .. } => {}
+        }
+    }
+
+    /// How long a round trip may take before it counts as lost.
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Self::Icmp { timeout, .. } | Self::Tcp { timeout, .. } | Self::Udp { timeout, .. } => {
+                *timeout
+            }
+        }
+    }
+
+    /// Time one round trip with sequence number `seq`.
+    pub async fn probe(&self, seq: u16) -> Result<Duration, PingFailure> {
+        self.send(seq, self.timeout()).await
+    }
+
+    /// Start a round trip with sequence number `seq` that gives up after
+    /// `wait` rather than the probe timeout.
+    pub fn send(
+        &self,
+        seq: u16,
+        wait: Duration,
+    ) -> impl Future<Output = Result<Duration, PingFailure>> + Send + 'static {
+        let mut prober = self.clone();
+        match &mut prober {
+            Self::Icmp { timeout, .. } | Self::Tcp { timeout, .. } | Self::Udp { timeout, .. } => {
+                *timeout = wait;
+            }
+        }
+        prober.round_trip(seq)
+    }
+
+    async fn round_trip(self, seq: u16) -> Result<Duration, PingFailure> {
+        match self {
+            Self::Icmp {
+                client,
+                addr,
+                id,
+                timeout,
+                payload,
+                dont_fragment,
+                _listener: _,
+            } => {
+                let (packet, rtt) = client
+                    .ping(addr, id, seq, &payload, timeout)
+                    .await
+                    .map_err(|error| match error {
+                        surge_ping::SurgeError::IOError(e)
+                            if dont_fragment && event::message_too_long(&e) =>
+                        {
+                            PingFailure::FragmentationNeeded {
+                                mtu: path_mtu(addr),
+                            }
+                        }
+                        error => PingFailure::from(error),
+                    })?;
+                match icmp_error(&packet, addr) {
+                    Some(error) => Err(error),
+                    None => Ok(rtt),
+                }
+            }
+            Self::Tcp {
+                addr,
+                timeout,
+                options,
+            } => {
+                let start = Instant::now();
+                match tokio::time::timeout(timeout, tcp_connect(addr, options)).await {
+                    Ok(Ok(())) => Ok(start.elapsed()),
+                    Ok(Err(e)) => Err(PingFailure::Io(e.kind())),
+                    Err(_) => Err(PingFailure::Timeout { seq }),
+                }
+            }
+            Self::Udp {
+                addr,
+                timeout,
+                payload_len,
+                options,
+                strays,
+            } => {
+                let start = Instant::now();
+                let round_trip = udp_round_trip(addr, seq, payload_len, options);
+                match tokio::time::timeout(timeout, round_trip).await {
+                    Ok(Ok(socket)) => {
+                        let rtt = start.elapsed();
+                        if let Some(strays) = strays {
+                            tokio::spawn(udp_strays(socket, seq, strays, start + timeout));
+                        }
+                        Ok(rtt)
+                    }
+                    Ok(Err(error)) => Err(error),
+                    Err(_) => Err(PingFailure::Timeout { seq }),
+                }
+            }
+        }
+    }
+}
+
+/// The failure an ICMP error message stands for when the host itself sent
+/// one instead of an echo reply to a probe towards `addr`.
+///
+/// surge-ping doesn't keep the next-hop MTU of a "fragmentation needed"
+/// message, but the kernel stored it as the path MTU on receipt, so that's
+/// where it's read back from.
+fn icmp_error(packet: &surge_ping::IcmpPacket, addr: IpAddr) -> Option<PingFailure> {
+    let (v6, icmp_type, icmp_code, from) = match packet {
+        surge_ping::IcmpPacket::V4(packet) => (
+            false,
+            packet.get_icmp_type().0,
+            packet.get_icmp_code().0,
+            packet.get_source().into(),
+        ),
+        surge_ping::IcmpPacket::V6(packet) => (
+            true,
+            packet.get_icmpv6_type().0,
+            packet.get_icmpv6_code().0,
+            packet.get_source().into(),
+        ),
+    };
+    let info = match PingFailure::from_icmp(v6, icmp_type, icmp_code, 0, from)? {
+        PingFailure::FragmentationNeeded { .. } => path_mtu(addr).unwrap_or(0),
+        _ => 0,
+    };
+    PingFailure::from_icmp(v6, icmp_type, icmp_code, info, from)
+}
+
+/// The MTU the kernel knows for the path towards `addr`, as learned from
+/// "fragmentation needed" messages or the outgoing interface.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn path_mtu(addr: IpAddr) -> Option<u32> {
+    use std::os::fd::AsRawFd as _;
+
+    let local = match addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    // Connecting a UDP socket sends nothing; it only looks up the route.
+    let socket = std::net::UdpSocket::bind((local, 0)).ok()?;
+    socket.connect((addr, 9)).ok()?;
+    let (level, name) = match addr {
+        IpAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_MTU),
+        IpAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_MTU),
+    };
+    let mut mtu: libc::c_int = 0;
+    let mut len = std::mem::size_of_val(&mtu) as libc::socklen_t;
+    // SAFETY: `mtu` and `len` outlive the call and `len` is its size.
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            (&raw mut mtu).cast(),
+            &raw mut len,
+        )
+    };
+    (ret == 0).then(|| u32::try_from(mtu).ok()).flatten()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn path_mtu(_: IpAddr) -> Option<u32> {
+    None
+}
+
+async fn tcp_connect(addr: SocketAddr, options: SocketOptions) -> io::Result<()> {
+    let socket = match addr {
+        SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+    };
+    options.apply(socket2::SockRef::from(&socket), addr.is_ipv6())?;
+    socket.connect(addr).await?;
+    Ok(())
+}
+
+/// Send one datagram of `payload_len` bytes, starting with `seq` when it
+/// fits, and wait for any answer from `addr`. Returns the socket, which
+/// further answers would still come to.
+///
+/// The socket is connected, so an ICMP port-unreachable surfaces as
+/// `ConnectionRefused` instead of a timeout. Where the kernel queues ICMP
+/// errors for the socket, TTL-exceeded and fragmentation-needed messages are
+/// reported as such.
+async fn udp_round_trip(
+    addr: SocketAddr,
+    seq: u16,
+    payload_len: usize,
+    options: SocketOptions,
+) -> Result<tokio::net::UdpSocket, PingFailure> {
+    let local = match addr {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let io_error = |e: io::Error| PingFailure::Io(e.kind());
+    let socket = tokio::net::UdpSocket::bind(local).await.map_err(io_error)?;
+    let sock_ref = socket2::SockRef::from(&socket);
+    options.apply(sock_ref, addr.is_ipv6()).map_err(io_error)?;
+    errqueue::enable(&socket, addr.is_ipv6());
+    socket.connect(addr).await.map_err(io_error)?;
+    let mut payload = vec![0u8; payload_len];
+    if let Some(head) = payload.get_mut(..2) {
+        head.copy_from_slice(&seq.to_be_bytes());
+    }
+    let mut buf = [0u8; 64];
+    let result = match socket.send(&payload).await {
+        Ok(_) => socket.recv(&mut buf).await.map(drop),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Ok(socket),
+        Err(e) => Err(errqueue::take(&socket).unwrap_or(
+            if options.dont_fragment && event::message_too_long(&e) {
+                PingFailure::FragmentationNeeded {
+                    mtu: path_mtu(addr.ip()),
+                }
+            } else {
+                PingFailure::Io(e.kind())
+            },
+        )),
+    }
+}
+
+/// Report every further datagram on `socket` as a stray reply to `seq`,
+/// until `until`.
+async fn udp_strays(
+    socket: tokio::net::UdpSocket,
+    seq: u16,
+    strays: mpsc::Sender<Stray>,
+    until: Instant,
+) {
+    let mut buf = [0u8; 64];
+    let _ = tokio::time::timeout_at(until.into(), async {
+        while socket.recv(&mut buf).await.is_ok() {
+            let stray = Stray {
+                seq,
+                at: Instant::now(),
+            };
+            if let Err(mpsc::error::TrySendError::Closed(_)) = strays.try_send(stray) {
+                break;
+            }
+        }
+    })
+    .await;
+}
+
+/// ICMP errors queued on a socket with `IP_RECVERR`, a Linux feature.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod errqueue {
+    use std::{
+        mem,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        os::fd::{AsRawFd, RawFd},
+    };
+
+    use crate::event::PingFailure;
+
+    /// Queue the ICMP errors `socket` causes instead of only flagging them.
+    pub fn enable(socket: &impl AsRawFd, v6: bool) {
+        let (level, name) = if v6 {
+            (libc::IPPROTO_IPV6, libc::IPV6_RECVERR)
+        } else {
+            (libc::IPPROTO_IP, libc::IP_RECVERR)
+        };
+        // Without it errors are still reported, just less precisely.
+        let _ = crate::types::setsockopt(socket.as_raw_fd(), level, name, 1);
+    }
+
+    /// The failure the oldest queued error stands for, if any.
+    pub fn take(socket: &impl AsRawFd) -> Option<PingFailure> {
+        read(socket.as_raw_fd())
+    }
+
+    fn read(fd: RawFd) -> Option<PingFailure> {
+        let mut data = [0u8; 512];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        let mut control = [0u64; 64];
+        // SAFETY: an all-zero `msghdr` is valid; the buffers it points to
+        // outlive the `recvmsg` call below.
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        // SAFETY: `msg` is initialised above.
+        if unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) } < 0 {
+            return None;
+        }
+        // SAFETY: the kernel filled in `msg_control` with valid headers,
+        // which the CMSG macros walk within `msg_controllen`.
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            // SAFETY: `cmsg` is non-null and within the control buffer.
+            let header = unsafe { &*cmsg };
+            let v6 = match (header.cmsg_level, header.cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_RECVERR) => Some(false),
+                (libc::IPPROTO_IPV6, libc::IPV6_RECVERR) => Some(true),
+                _ => None,
+            };
+            if let Some(v6) = v6 {
+                // SAFETY: `IP_RECVERR` data is a `sock_extended_err`
+                // followed by the offender's address.
+                let (err, offender) = unsafe {
+                    let err = libc::CMSG_DATA(cmsg).cast::<libc::sock_extended_err>();
+                    (err.read_unaligned(), offender(err.add(1).cast()))
+                };
+                return classify(&err, v6, offender);
+            }
+            // SAFETY: as for `CMSG_FIRSTHDR`.
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        None
+    }
+
+    fn classify(
+        err: &libc::sock_extended_err,
+        v6: bool,
+        offender: Option<IpAddr>,
+    ) -> Option<PingFailure> {
+        match err.ee_origin {
+            libc::SO_EE_ORIGIN_ICMP | libc::SO_EE_ORIGIN_ICMP6 => PingFailure::from_icmp(
+                v6,
+                err.ee_type,
+                err.ee_code,
+                err.ee_info,
+                offender.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            ),
+            libc::SO_EE_ORIGIN_LOCAL if err.ee_errno == libc::EMSGSIZE as u32 => {
+                Some(PingFailure::FragmentationNeeded {
+                    mtu: (err.ee_info > 0).then_some(err.ee_info),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `addr` must point to a socket address as written by the kernel.
+    unsafe fn offender(addr: *const libc::sockaddr) -> Option<IpAddr> {
+        // SAFETY: guaranteed by the caller; the family tells the layout.
+        unsafe {
+            match i32::from((*addr).sa_family) {
+                libc::AF_INET => {
+                    let addr = addr.cast::<libc::sockaddr_in>().read_unaligned();
+                    Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into())
+                }
+                libc::AF_INET6 => {
+                    let addr = addr.cast::<libc::sockaddr_in6>().read_unaligned();
+                    Some(Ipv6Addr::from(addr.sin6_addr.s6_addr).into())
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod errqueue {
+    use crate::event::PingFailure;
+
+    pub fn enable<T>(_: &T, _: bool) {}
+
+    pub fn take<T>(_: &T) -> Option<PingFailure> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localhost() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+
+    async fn prober(probe: types::Probe) -> Prober {
+        prober_with(probe, SocketOptions::default()).await
+    }
+
+    async fn prober_with(probe: types::Probe, options: SocketOptions) -> Prober {
+        Prober::new(
+            probe,
+            localhost(),
+            &client::PingClients::new([]).unwrap(),
+            surge_ping::PingIdentifier(1),
+            Duration::from_millis(500),
+            DEFAULT_PAYLOAD_LEN,
+            options,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_applies_socket_options() {
+        let listener = tokio::net::TcpListener::bind((localhost(), 0))
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = SocketOptions {
+            ttl: Some(1),
+            tos: Some(0x10),
+            dont_fragment: false,
+        };
+        let prober = prober_with(types::Probe::Tcp(port), options).await;
+        assert!(prober.probe(0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_times_the_handshake() {
+        let listener = tokio::net::TcpListener::bind((localhost(), 0))
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let prober = prober(types::Probe::Tcp(port)).await;
+        assert!(prober.probe(0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_reports_refused_connections() {
+        // Bind and drop to get a port nobody listens on.
+        let port = std::net::TcpListener::bind((localhost(), 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let prober = prober(types::Probe::Tcp(port)).await;
+        assert_eq!(
+            prober.probe(0).await,
+            Err(PingFailure::Io(std::io::ErrorKind::ConnectionRefused))
+        );
+    }
+
+    #[tokio::test]
+    async fn udp_probe_waits_for_the_echo() {
+        let echo = tokio::net::UdpSocket::bind((localhost(), 0)).await.unwrap();
+        let port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let (n, from) = echo.recv_from(&mut buf).await.unwrap();
+            echo.send_to(&buf[..n], from).await.unwrap();
+        });
+        let prober = prober(types::Probe::Udp(port)).await;
+        assert!(prober.probe(0).await.is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn oversized_udp_probes_need_fragmentation() {
+        let silent = tokio::net::UdpSocket::bind((localhost(), 0)).await.unwrap();
+        let port = silent.local_addr().unwrap().port();
+        let options = SocketOptions {
+            dont_fragment: true,
+            ..SocketOptions::default()
+        };
+        let mut prober = prober_with(types::Probe::Udp(port), options).await;
+        prober.set_payload_len(70_000);
+        assert!(matches!(
+            prober.probe(0).await,
+            Err(PingFailure::FragmentationNeeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_udp_probes_without_dont_fragment_are_io_errors() {
+        let silent = tokio::net::UdpSocket::bind((localhost(), 0)).await.unwrap();
+        let port = silent.local_addr().unwrap().port();
+        let mut prober = prober(types::Probe::Udp(port)).await;
+        prober.set_payload_len(70_000);
+        assert!(matches!(prober.probe(0).await, Err(PingFailure::Io(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn path_mtu_is_known_for_local_routes() {
+        assert!(path_mtu(localhost()).is_some_and(|mtu| mtu >= 1280));
+    }
+
+    #[tokio::test]
+    async fn udp_probe_times_out_without_an_echo() {
+        let silent = tokio::net::UdpSocket::bind((localhost(), 0)).await.unwrap();
+        let port = silent.local_addr().unwrap().port();
+        let prober = prober(types::Probe::Udp(port)).await;
+        assert_eq!(prober.probe(7).await, Err(PingFailure::Timeout { seq: 7 }));
+        drop(silent);
+    }
+}
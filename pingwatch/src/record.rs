@@ -0,0 +1,697 @@
+//! Session recordings: `--record FILE` and `pingwatch replay FILE`.
+//!
+//! A recording is a line-oriented text file:
+//!
+//! ```text
+//! pingwatch-recording 1 2024-05-06T07:08:09.123+02:00
+//! host { name = "db.example.com", label = "db", group = "backend" }
+//! host { name = "tcp://10.0.0.7:22" }
+//! 0 0 resolved 10.0.0.5
+//! 12 1 resolved 10.0.0.7
+//! 1003 0 success 0 12345
+//! 2004 1 failure io connection_refused
+//! 2500 2 added { name = "10.0.0.9" }
+//! 3000 1 removed
+//! ```
+//!
+//! The first line names the format version and the wall-clock start of the
+//! session. One `host` line per host follows, in index order, as a config
+//! file host table. Every other line is an event: milliseconds since the
+//! start, host index, event kind and its fields; RTTs are in microseconds
+//! and replies name the sequence number of their probe, `duplicate` and
+//! `late` ones too. Hosts joining later are `added` with a host table of
+//! their own.
+//!
+//! `encode`, `Recording::parse` and friends are the functional core.
+//! `run_recorder` is the shell on the recording side: like the alert task it
+//! sits in the event pipeline and forwards every event unchanged. `play` is
+//! the shell on the replay side: it feeds recorded events into a channel at
+//! their original pace, so the printer can't tell a replay from a live run.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use chrono::{DateTime, FixedOffset};
+use tokio::sync::mpsc;
+
+use crate::{
+    event::{PingEvent, PingFailure},
+    logger::json_string,
+    types::{self, Family, ResolveError},
+};
+
+const MAGIC: &str = "pingwatch-recording";
+const VERSION: u32 = 1;
+/// How often `run_recorder` flushes what it wrote.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Names of the I/O error kinds a recording keeps; any other kind is
+/// recorded as `other`.
+const IO_KINDS: &[(io::ErrorKind, &str)] = &[
+    (io::ErrorKind::NotFound, "not_found"),
+    (io::ErrorKind::PermissionDenied, "permission_denied"),
+    (io::ErrorKind::ConnectionRefused, "connection_refused"),
+    (io::ErrorKind::ConnectionReset, "connection_reset"),
+    (io::ErrorKind::ConnectionAborted, "connection_aborted"),
+    (io::ErrorKind::NotConnected, "not_connected"),
+    (io::ErrorKind::HostUnreachable, "host_unreachable"),
+    (io::ErrorKind::NetworkUnreachable, "network_unreachable"),
+    (io::ErrorKind::NetworkDown, "network_down"),
+    (io::ErrorKind::AddrInUse, "addr_in_use"),
+    (io::ErrorKind::AddrNotAvailable, "addr_not_available"),
+    (io::ErrorKind::BrokenPipe, "broken_pipe"),
+    (io::ErrorKind::WouldBlock, "would_block"),
+    (io::ErrorKind::InvalidInput, "invalid_input"),
+    (io::ErrorKind::InvalidData, "invalid_data"),
+    (io::ErrorKind::TimedOut, "timed_out"),
+    (io::ErrorKind::Interrupted, "interrupted"),
+    (io::ErrorKind::Unsupported, "unsupported"),
+    (io::ErrorKind::UnexpectedEof, "unexpected_eof"),
+    (io::ErrorKind::OutOfMemory, "out_of_memory"),
+    (io::ErrorKind::Other, "other"),
+];
+
+/// A parsed recording.
+#[derive(Debug)]
+pub struct Recording {
+    /// Wall-clock time the session started.
+    pub start: DateTime<FixedOffset>,
+    pub hosts: Arc<[types::Hostname]>,
+    /// Every event with its offset from `start`, in recorded order.
+    pub events: Vec<(Duration, PingEvent)>,
+}
+
+impl Recording {
+    /// Parse the text of a recording, naming the offending line on error.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let (_, first) = lines.next().context("empty recording")?;
+        let start = parse_header(first).context("line 1")?;
+        let mut hosts = Vec::new();
+        let mut events = Vec::new();
+        // Hosts known so far, the ones added after the start included.
+        let mut known = 0;
+        for (n, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix("host ") {
+                anyhow::ensure!(events.is_empty(), "line {n}: host after the first event");
+                hosts.push(parse_host(table).with_context(|| format!("line {n}"))?);
+                known += 1;
+                continue;
+            }
+            let (elapsed, ev) = decode(line).with_context(|| format!("line {n}"))?;
+            let i = ev.idx().as_usize();
+            if let PingEvent::HostAdded { .. } = ev {
+                anyhow::ensure!(i == known, "line {n}: host added at index {i}, not {known}");
+                known += 1;
+            }
+            anyhow::ensure!(i < known, "line {n}: no host with index {i}");
+            events.push((elapsed, ev));
+        }
+        anyhow::ensure!(known > 0, "recording has no hosts");
+        Ok(Self {
+            start,
+            hosts: Arc::from(hosts),
+            events,
+        })
+    }
+}
+
+/// The first lines of a recording: the version line and one line per host.
+pub fn header(start: &DateTime<FixedOffset>, hosts: &[types::Hostname]) -> String {
+    let mut out = format!(
+        "{MAGIC} {VERSION} {}\n",
+        start.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+    );
+    for host in hosts {
+        let _ = writeln!(out, "host {}", host_table(host));
+    }
+    out
+}
+
+/// A host as a config file host table, with what the display shows of it.
+fn host_table(host: &types::Hostname) -> String {
+    let mut table = format!("{{ name = {}", json_string(host.as_str()));
+    if host.display_name() != host.as_str() {
+        let _ = write!(table, ", label = {}", json_string(host.display_name()));
+    }
+    if let Some(group) = host.group() {
+        let _ = write!(table, ", group = {}", json_string(group));
+    }
+    if let Some(addr) = host.pinned() {
+        let _ = write!(table, ", address = \"{addr}\"");
+    }
+    table + " }"
+}
+
+fn parse_header(line: &str) -> anyhow::Result<DateTime<FixedOffset>> {
+    let mut fields = line.split(' ');
+    anyhow::ensure!(fields.next() == Some(MAGIC), "not a pingwatch recording");
+    let version = fields.next().unwrap_or_default();
+    anyhow::ensure!(
+        version == VERSION.to_string(),
+        "unsupported recording version `{version}`"
+    );
+    let start = fields.next().context("missing start time")?;
+    DateTime::parse_from_rfc3339(start).with_context(|| format!("invalid start time `{start}`"))
+}
+
+fn parse_host(table: &str) -> anyhow::Result<types::Hostname> {
+    #[derive(serde::Deserialize)]
+    struct Line {
+        host: types::Hostname,
+    }
+    let line: Line = toml_edit::de::from_str(&format!("host = {table}"))?;
+    Ok(line.host)
+}
+
+/// One event line, without the trailing newline.
+pub fn encode(elapsed: Duration, ev: &PingEvent) -> String {
+    let mut line = format!("{} {}", elapsed.as_millis(), ev.idx().as_usize());
+    let _ = match ev {
+        PingEvent::Resolved { addr, .. } => write!(line, " resolved {addr}"),
+        PingEvent::AddressChanged { from, to, .. } => {
+            write!(line, " address_changed {from} {to}")
+        }
+        PingEvent::ResolutionFailed { error, .. } => match error {
+            ResolveError::DnsLookup(kind) => {
+                write!(
+                    line,
+                    " resolution_failed dns_lookup {}",
+                    io_kind_name(*kind)
+                )
+            }
+            ResolveError::NoAddresses => write!(line, " resolution_failed no_addresses"),
+            ResolveError::NoAddressesInFamily(family) => write!(
+                line,
+                " resolution_failed no_addresses_in_family {}",
+                family_name(*family)
+            ),
+        },
+        PingEvent::Success { seq, rtt, .. } => {
+            write!(line, " success {seq} {}", rtt.as_micros())
+        }
+        PingEvent::LateReply {
+            seq,
+            rtt,
+            reordered,
+            ..
+        } => {
+            let order = if *reordered { "reordered" } else { "in_order" };
+            write!(line, " late {seq} {} {order}", rtt.as_micros())
+        }
+        PingEvent::Duplicate { seq, rtt, .. } => {
+            write!(line, " duplicate {seq} {}", rtt.as_micros())
+        }
+        PingEvent::Failure { error, .. } => {
+            line += " failure ";
+            match error {
+                PingFailure::IncorrectBufferSize => write!(line, "incorrect_buffer_size"),
+                PingFailure::MalformedPacket => write!(line, "malformed_packet"),
+                PingFailure::Io(kind) => write!(line, "io {}", io_kind_name(*kind)),
+                PingFailure::Timeout { seq } => write!(line, "timeout {seq}"),
+                PingFailure::EchoRequestPacket => write!(line, "echo_request_packet"),
+                PingFailure::NetworkError => write!(line, "network_error"),
+                PingFailure::IdenticalRequests { host, ident, seq } => match ident {
+                    Some(ident) => write!(line, "identical_requests {host} {ident} {seq}"),
+                    None => write!(line, "identical_requests {host} - {seq}"),
+                },
+                PingFailure::ClientDestroyed => write!(line, "client_destroyed"),
+                PingFailure::FragmentationNeeded { mtu } => match mtu {
+                    Some(mtu) => write!(line, "fragmentation_needed {mtu}"),
+                    None => write!(line, "fragmentation_needed -"),
+                },
+                PingFailure::TtlExceeded { from } => write!(line, "ttl_exceeded {from}"),
+            }
+        }
+        PingEvent::PathMtu { mtu, .. } => write!(line, " path_mtu {mtu}"),
+        PingEvent::HostAdded { host, .. } => write!(line, " added {}", host_table(host)),
+        PingEvent::HostRemoved { .. } => write!(line, " removed"),
+    };
+    line
+}
+
+/// Parse an event line written by `encode`.
+pub fn decode(line: &str) -> anyhow::Result<(Duration, PingEvent)> {
+    let mut fields = line.split(' ');
+    let mut next = |what: &str| {
+        fields
+            .next()
+            .with_context(|| format!("missing {what} in `{line}`"))
+    };
+    let elapsed = Duration::from_millis(parse(next("time")?)?);
+    let idx = types::HostIdx::new(parse(next("host index")?)?);
+    let ev = match next("event")? {
+        "resolved" => PingEvent::Resolved {
+            idx,
+            addr: parse(next("address")?)?,
+        },
+        "address_changed" => PingEvent::AddressChanged {
+            idx,
+            from: parse(next("address")?)?,
+            to: parse(next("address")?)?,
+        },
+        "resolution_failed" => PingEvent::ResolutionFailed {
+            idx,
+            error: match next("error")? {
+                "dns_lookup" => ResolveError::DnsLookup(io_kind(next("error kind")?)),
+                "no_addresses" => ResolveError::NoAddresses,
+                "no_addresses_in_family" => {
+                    ResolveError::NoAddressesInFamily(match next("family")? {
+                        "ipv4" => Family::Ipv4,
+                        "ipv6" => Family::Ipv6,
+                        family => anyhow::bail!("unknown family `{family}`"),
+                    })
+                }
+                error => anyhow::bail!("unknown resolution error `{error}`"),
+            },
+        },
+        "success" => PingEvent::Success {
+            idx,
+            seq: parse(next("sequence number")?)?,
+            rtt: Duration::from_micros(parse(next("rtt")?)?),
+        },
+        "late" => PingEvent::LateReply {
+            idx,
+            seq: parse(next("sequence number")?)?,
+            rtt: Duration::from_micros(parse(next("rtt")?)?),
+            reordered: match next("order")? {
+                "in_order" => false,
+                "reordered" => true,
+                order => anyhow::bail!("unknown order `{order}`"),
+            },
+        },
+        "duplicate" => PingEvent::Duplicate {
+            idx,
+            seq: parse(next("sequence number")?)?,
+            rtt: Duration::from_micros(parse(next("rtt")?)?),
+        },
+        "failure" => PingEvent::Failure {
+            idx,
+            error: match next("error")? {
+                "incorrect_buffer_size" => PingFailure::IncorrectBufferSize,
+                "malformed_packet" => PingFailure::MalformedPacket,
+                "io" => PingFailure::Io(io_kind(next("error kind")?)),
+                "timeout" => PingFailure::Timeout {
+                    seq: parse(next("sequence number")?)?,
+                },
+                "echo_request_packet" => PingFailure::EchoRequestPacket,
+                "network_error" => PingFailure::NetworkError,
+                "identical_requests" => PingFailure::IdenticalRequests {
+                    host: parse::<IpAddr>(next("address")?)?,
+                    ident: match next("identifier")? {
+                        "-" => None,
+                        ident => Some(parse(ident)?),
+                    },
+                    seq: parse(next("sequence number")?)?,
+                },
+                "client_destroyed" => PingFailure::ClientDestroyed,
+                "fragmentation_needed" => PingFailure::FragmentationNeeded {
+                    mtu: match next("mtu")? {
+                        "-" => None,
+                        mtu => Some(parse(mtu)?),
+                    },
+                },
+                "ttl_exceeded" => PingFailure::TtlExceeded {
+                    from: parse(next("address")?)?,
+                },
+                error => anyhow::bail!("unknown failure `{error}`"),
+            },
+        },
+        "path_mtu" => PingEvent::PathMtu {
+            idx,
+            mtu: parse(next("mtu")?)?,
+        },
+        // The host table has spaces of its own and takes the rest of the line.
+        "added" => PingEvent::HostAdded {
+            idx,
+            host: parse_host(&fields.collect::<Vec<_>>().join(" "))?,
+        },
+        "removed" => PingEvent::HostRemoved { idx },
+        kind => anyhow::bail!("unknown event `{kind}`"),
+    };
+    Ok((elapsed, ev))
+}
+
+fn parse<T>(field: &str) -> anyhow::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    field
+        .parse()
+        .with_context(|| format!("invalid field `{field}`"))
+}
+
+fn io_kind_name(kind: io::ErrorKind) -> &'static str {
+    IO_KINDS
+        .iter()
+        .find(|&&(k, _)| k == kind)
+        .map_or("other", |&(_, name)| name)
+}
+
+/// The kind named `name`; unknown names read as `Other`, like unknown kinds
+/// are written.
+fn io_kind(name: &str) -> io::ErrorKind {
+    IO_KINDS
+        .iter()
+        .find(|&&(_, n)| n == name)
+        .map_or(io::ErrorKind::Other, |&(k, _)| k)
+}
+
+fn family_name(family: Family) -> &'static str {
+    match family {
+        Family::Ipv4 => "ipv4",
+        Family::Ipv6 => "ipv6",
+    }
+}
+
+/// Forward every event from `rx` to `tx` unchanged, writing it to `out`
+/// along the way. Returns when `rx` is exhausted or `tx` is closed.
+///
+/// `out` should be buffered: lines are only flushed every `FLUSH_INTERVAL`
+/// and on return, so a slow disk doesn't stall the pipeline on every event.
+/// Once `out` can't be written to anymore, recording stops but events keep
+/// flowing.
+pub async fn run_recorder<W>(
+    hosts: Arc<[types::Hostname]>,
+    mut rx: mpsc::Receiver<PingEvent>,
+    tx: mpsc::Sender<PingEvent>,
+    mut out: W,
+) where
+    W: Write,
+{
+    let start = tokio::time::Instant::now();
+    let started = chrono::Local::now().fixed_offset();
+    let mut recording = write!(out, "{}", header(&started, &hosts))
+        .and_then(|()| out.flush())
+        .is_ok();
+    let mut unflushed = false;
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            ev = rx.recv() => {
+                let Some(ev) = ev else { break };
+                if recording {
+                    let line = encode(start.elapsed(), &ev);
+                    recording = writeln!(out, "{line}").is_ok();
+                    unflushed = true;
+                }
+                if tx.send(ev).await.is_err() {
+                    break;
+                }
+            }
+            _ = flush.tick(), if unflushed => {
+                recording = recording && out.flush().is_ok();
+                unflushed = false;
+            }
+        }
+    }
+    if recording && unflushed {
+        let _ = out.flush();
+    }
+}
+
+/// Send recorded events to `tx` at their recorded pace, `speed` times faster.
+/// Returns once every event is sent or `tx` is closed.
+pub async fn play(events: Vec<(Duration, PingEvent)>, speed: f64, tx: mpsc::Sender<PingEvent>) {
+    let start = tokio::time::Instant::now();
+    for (elapsed, ev) in events {
+        tokio::time::sleep_until(start + elapsed.div_f64(speed)).await;
+        if tx.send(ev).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idx(i: usize) -> types::HostIdx {
+        types::HostIdx::new(i)
+    }
+
+    fn start() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-06T07:08:09.123+02:00").unwrap()
+    }
+
+    #[rstest::rstest]
+    #[case::resolved(PingEvent::Resolved { idx: idx(1), addr: "10.0.0.1".parse().unwrap() }, "1 resolved 10.0.0.1")]
+    #[case::address_changed(
+        PingEvent::AddressChanged { idx: idx(0), from: "10.0.0.1".parse().unwrap(), to: "::1".parse().unwrap() },
+        "0 address_changed 10.0.0.1 ::1"
+    )]
+    #[case::dns_lookup(
+        PingEvent::ResolutionFailed { idx: idx(0), error: ResolveError::DnsLookup(io::ErrorKind::TimedOut) },
+        "0 resolution_failed dns_lookup timed_out"
+    )]
+    #[case::no_addresses_in_family(
+        PingEvent::ResolutionFailed { idx: idx(0), error: ResolveError::NoAddressesInFamily(Family::Ipv6) },
+        "0 resolution_failed no_addresses_in_family ipv6"
+    )]
+    #[case::success(
+        PingEvent::Success { idx: idx(0), seq: 3, rtt: Duration::from_micros(12_345) },
+        "0 success 3 12345"
+    )]
+    #[case::late(
+        PingEvent::LateReply { idx: idx(0), seq: 3, rtt: Duration::from_micros(912_345), reordered: false },
+        "0 late 3 912345 in_order"
+    )]
+    #[case::late_reordered(
+        PingEvent::LateReply { idx: idx(0), seq: 3, rtt: Duration::from_micros(912_345), reordered: true },
+        "0 late 3 912345 reordered"
+    )]
+    #[case::duplicate(
+        PingEvent::Duplicate { idx: idx(0), seq: 3, rtt: Duration::from_micros(12_346) },
+        "0 duplicate 3 12346"
+    )]
+    #[case::timeout(PingEvent::Failure { idx: idx(0), error: PingFailure::Timeout { seq: 7 } }, "0 failure timeout 7")]
+    #[case::io(
+        PingEvent::Failure { idx: idx(0), error: PingFailure::Io(io::ErrorKind::ConnectionRefused) },
+        "0 failure io connection_refused"
+    )]
+    #[case::fragmentation_needed(
+        PingEvent::Failure { idx: idx(0), error: PingFailure::FragmentationNeeded { mtu: Some(1400) } },
+        "0 failure fragmentation_needed 1400"
+    )]
+    #[case::fragmentation_needed_without_mtu(
+        PingEvent::Failure { idx: idx(0), error: PingFailure::FragmentationNeeded { mtu: None } },
+        "0 failure fragmentation_needed -"
+    )]
+    #[case::ttl_exceeded(
+        PingEvent::Failure { idx: idx(0), error: PingFailure::TtlExceeded { from: "192.0.2.1".parse().unwrap() } },
+        "0 failure ttl_exceeded 192.0.2.1"
+    )]
+    #[case::path_mtu(PingEvent::PathMtu { idx: idx(1), mtu: 1500 }, "1 path_mtu 1500")]
+    #[case::added(
+        PingEvent::HostAdded { idx: idx(2), host: "10.0.0.9".parse().unwrap() },
+        "2 added { name = \"10.0.0.9\" }"
+    )]
+    #[case::removed(PingEvent::HostRemoved { idx: idx(1) }, "1 removed")]
+    #[case::identical_requests(
+        PingEvent::Failure {
+            idx: idx(0),
+            error: PingFailure::IdenticalRequests { host: "10.0.0.1".parse().unwrap(), ident: None, seq: 3 },
+        },
+        "0 failure identical_requests 10.0.0.1 - 3"
+    )]
+    fn events_round_trip(#[case] ev: PingEvent, #[case] fields: &str) {
+        let line = encode(Duration::from_millis(1500), &ev);
+        assert_eq!(line, format!("1500 {fields}"));
+        let (elapsed, decoded) = decode(&line).unwrap();
+        assert_eq!(elapsed, Duration::from_millis(1500));
+        assert_eq!(format!("{decoded:?}"), format!("{ev:?}"));
+    }
+
+    #[test]
+    fn unnamed_io_kinds_are_recorded_as_other() {
+        let ev = PingEvent::Failure {
+            idx: idx(0),
+            error: PingFailure::Io(io::ErrorKind::QuotaExceeded),
+        };
+        let (_, decoded) = decode(&encode(Duration::ZERO, &ev)).unwrap();
+        assert!(matches!(
+            decoded,
+            PingEvent::Failure {
+                error: PingFailure::Io(io::ErrorKind::Other),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn hosts_keep_their_label_group_and_address() {
+        #[derive(serde::Deserialize)]
+        struct Hosts {
+            hosts: Vec<types::Hostname>,
+        }
+        let hosts = toml_edit::de::from_str::<Hosts>(
+            r#"hosts = [
+                "tcp://[::1]:22",
+                { name = "db", label = "d\"b", group = "backend", address = "10.0.0.5" },
+            ]"#,
+        )
+        .unwrap()
+        .hosts;
+        let text = header(&start(), &hosts) + "0 1 success 0 1000\n";
+        let recording = Recording::parse(&text).unwrap();
+        assert_eq!(recording.start, start());
+        assert_eq!(recording.hosts[0].as_str(), "tcp://[::1]:22");
+        assert_eq!(recording.hosts[1].display_name(), "d\"b");
+        assert_eq!(recording.hosts[1].group(), Some("backend"));
+        assert_eq!(
+            recording.hosts[1].pinned(),
+            Some("10.0.0.5".parse().unwrap())
+        );
+        assert_eq!(recording.events.len(), 1);
+    }
+
+    #[test]
+    fn hosts_added_later_take_the_next_index() {
+        let text = format!(
+            "{}0 1 added {{ name = \"b\", label = \"the b\" }}\n5 1 success 0 1000\n",
+            header(&start(), &["a".parse().unwrap()])
+        );
+        let recording = Recording::parse(&text).unwrap();
+        assert_eq!(recording.hosts.len(), 1);
+        let PingEvent::HostAdded { host, .. } = &recording.events[0].1 else {
+            panic!("{:?}", recording.events[0]);
+        };
+        assert_eq!(host.display_name(), "the b");
+        // Even a recording started without hosts has some then.
+        let text = "pingwatch-recording 1 2024-05-06T07:08:09Z\n0 0 added { name = \"a\" }\n";
+        assert_eq!(Recording::parse(text).unwrap().events.len(), 1);
+    }
+
+    #[rstest::rstest]
+    #[case::not_a_recording("hello\n", "line 1")]
+    #[case::newer_version("pingwatch-recording 2 2024-05-06T07:08:09Z\n", "line 1")]
+    #[case::no_hosts("pingwatch-recording 1 2024-05-06T07:08:09Z\n", "no hosts")]
+    #[case::unknown_host(
+        "pingwatch-recording 1 2024-05-06T07:08:09Z\nhost { name = \"a\" }\n0 1 success 0 1\n",
+        "line 3"
+    )]
+    #[case::garbled_event(
+        "pingwatch-recording 1 2024-05-06T07:08:09Z\nhost { name = \"a\" }\n0 0 success 0\n",
+        "line 3"
+    )]
+    #[case::added_out_of_turn(
+        "pingwatch-recording 1 2024-05-06T07:08:09Z\nhost { name = \"a\" }\n0 2 added { name = \"b\" }\n",
+        "line 3"
+    )]
+    fn rejects_malformed_recordings(#[case] text: &str, #[case] message: &str) {
+        let error = Recording::parse(text).unwrap_err();
+        assert!(
+            format!("{error:#}").contains(message),
+            "`{error:#}` should mention `{message}`"
+        );
+    }
+
+    #[tokio::test]
+    async fn recorder_forwards_and_writes_every_event() {
+        let hosts: Arc<[types::Hostname]> = Arc::from(["db".parse().unwrap()]);
+        let (tx, rx) = mpsc::channel(8);
+        let (out_tx, mut out_rx) = mpsc::channel(8);
+        tx.send(PingEvent::Success {
+            idx: idx(0),
+            seq: 0,
+            rtt: Duration::from_millis(2),
+        })
+        .await
+        .unwrap();
+        drop(tx);
+        let mut out = Vec::new();
+        run_recorder(Arc::clone(&hosts), rx, out_tx, &mut out).await;
+
+        assert!(matches!(
+            out_rx.recv().await,
+            Some(PingEvent::Success { .. })
+        ));
+        let recording = Recording::parse(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(recording.hosts[0].as_str(), "db");
+        assert!(matches!(
+            recording.events[..],
+            [(_, PingEvent::Success { rtt, .. })] if rtt == Duration::from_millis(2)
+        ));
+    }
+
+    #[derive(Default)]
+    struct CountingWriter {
+        written: Vec<u8>,
+        flushed: usize,
+        flushes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed = self.written.len();
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn recorder_flushes_in_batches_and_on_return() {
+        let hosts: Arc<[types::Hostname]> = Arc::from(["db".parse().unwrap()]);
+        let (tx, rx) = mpsc::channel(16);
+        let (out_tx, mut out_rx) = mpsc::channel(16);
+        for seq in 0..10 {
+            tx.send(PingEvent::Success {
+                idx: idx(0),
+                seq,
+                rtt: Duration::ZERO,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+        let mut out = CountingWriter::default();
+        run_recorder(hosts, rx, out_tx, &mut out).await;
+
+        // The header, then at most a tick's worth and the final flush.
+        assert!(out.flushes <= 3, "{} flushes", out.flushes);
+        assert_eq!(out.flushed, out.written.len());
+        let recording = Recording::parse(&String::from_utf8(out.written).unwrap()).unwrap();
+        assert_eq!(recording.events.len(), 10);
+        for _ in 0..10 {
+            assert!(out_rx.recv().await.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn play_keeps_the_recorded_pace() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let success = |seq| PingEvent::Success {
+            idx: idx(0),
+            seq,
+            rtt: Duration::ZERO,
+        };
+        let events = vec![
+            (Duration::ZERO, success(0)),
+            (Duration::from_millis(500), success(1)),
+        ];
+        let start = tokio::time::Instant::now();
+        tokio::spawn(play(events, 10.0, tx));
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+        assert!(rx.recv().await.is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! Changes to the host list while running.
+//!
+//! `diff` is the functional core: it tells which hosts an edit of the config
+//! file removed and which it added. `watch_config` and `read_hosts` are the
+//! shell: the first polls the config file and sends what `diff` finds, the
+//! second sends every host read from stdin until it ends. `run` starts and
+//! stops workers accordingly. Polling needs no platform file-notification
+//! API, and a config file is small enough to read twice a second.
+
+use std::{io::BufRead, time::Duration};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    cli,
+    types::{Family, Hostname},
+};
+
+/// How often the config file is checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to the host list, naming the host as given: on stdin or in the
+/// config file, before `--all-addresses` expands it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Add(Hostname),
+    Remove(Hostname),
+}
+
+/// The changes turning the host list `old` into `new`: removals first, then
+/// additions in the order of `new`. A host whose settings changed is removed
+/// and added again.
+pub fn diff(old: &[Hostname], new: &[Hostname]) -> Vec<Change> {
+    let mut unmatched: Vec<Option<&Hostname>> = new.iter().map(Some).collect();
+    let mut changes = Vec::new();
+    for host in old {
+        match unmatched.iter_mut().find(|slot| *slot == &Some(host)) {
+            Some(slot) => *slot = None,
+            None => changes.push(Change::Remove(host.clone())),
+        }
+    }
+    changes.extend(unmatched.into_iter().flatten().cloned().map(Change::Add));
+    changes
+}
+
+/// Poll the config file and send the changes to its hosts, starting from
+/// `hosts`, until `tx` is closed. A file that can't be read or parsed is
+/// reported on `notices` and the hosts stay as they are until it changes
+/// again.
+pub async fn watch_config(
+    watch: cli::ConfigWatch,
+    mut hosts: Vec<Hostname>,
+    tx: mpsc::Sender<Change>,
+    notices: mpsc::Sender<String>,
+) {
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut seen = std::fs::read(watch.path()).ok();
+    loop {
+        ticker.tick().await;
+        let content = std::fs::read(watch.path()).ok();
+        if content == seen {
+            continue;
+        }
+        seen = content;
+        let new = match watch.hosts() {
+            Ok(new) => new,
+            Err(error) => {
+                let notice = format!("{error:#}; keeping the hosts as they are");
+                let _ = notices.send(notice).await;
+                continue;
+            }
+        };
+        for change in diff(&hosts, &new) {
+            if tx.send(change).await.is_err() {
+                return;
+            }
+        }
+        hosts = new;
+    }
+}
+
+/// Send every host read from `input`, one per line and restricted to
+/// `family` like -4/-6 ask, until it ends or `tx` is closed. Blank lines and
+/// `#` comments are skipped; other lines that aren't hosts are reported on
+/// `notices`. Blocks, so it runs on a thread of its own.
+pub fn read_hosts(
+    input: impl BufRead,
+    family: Option<Family>,
+    tx: mpsc::Sender<Change>,
+    notices: mpsc::Sender<String>,
+) {
+    for line in input.lines() {
+        let Ok(line) = line else { return };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse::<Hostname>() {
+            Ok(host) => {
+                let host = match family {
+                    Some(family) => host.with_family(family),
+                    None => host,
+                };
+                if tx.blocking_send(Change::Add(host)).is_err() {
+                    return;
+                }
+            }
+            Err(error) => {
+                let _ = notices.blocking_send(format!("stdin: {error}"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(names: &[&str]) -> Vec<Hostname> {
+        names.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn diff_removes_then_adds() {
+        let old = hosts(&["a", "b", "c", "b"]);
+        let new = hosts(&["d", "c", "b", "a"]);
+        assert_eq!(
+            diff(&old, &new),
+            [Change::Remove(old[3].clone()), Change::Add(new[0].clone())]
+        );
+        assert_eq!(diff(&new, &new), []);
+    }
+
+    #[test]
+    fn hosts_with_other_settings_are_replaced() {
+        let old = hosts(&["a"]);
+        let new = vec![old[0].clone().with_family(Family::Ipv6)];
+        assert_eq!(
+            diff(&old, &new),
+            [Change::Remove(old[0].clone()), Change::Add(new[0].clone())]
+        );
+    }
+
+    #[test]
+    fn reads_one_host_per_line() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let (notices_tx, mut notices) = mpsc::channel(8);
+        let input = "a\n\n  # a comment\n  tcp://b:22  \nbogus://c\n";
+        read_hosts(input.as_bytes(), Some(Family::Ipv4), tx, notices_tx);
+        let mut read = Vec::new();
+        while let Ok(Change::Add(host)) = rx.try_recv() {
+            read.push((host.as_str().to_owned(), host.family()));
+        }
+        assert_eq!(
+            read,
+            [
+                ("a".to_owned(), Some(Family::Ipv4)),
+                ("tcp://b:22".to_owned(), Some(Family::Ipv4))
+            ]
+        );
+        assert!(notices.try_recv().unwrap().starts_with("stdin: "));
+        assert!(notices.try_recv().is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Per-host ping statistics and the ping(8)-style summary printed on exit.
+//!
+//! `Stats` is fed every `PingEvent` by whichever task consumes the channel
+//! (printer or logger) and handed back to `run` once the channel closes.
+//! Duplicate, late and reordered replies, which the worker tells apart by
+//! sequence number, are counted like ping(8) counts duplicates.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{event::PingEvent, types};
+
+/// Counters and running RTT aggregates for a single host.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostStats {
+    /// Pings whose outcome (reply or failure) is known.
+    pub transmitted: u64,
+    /// Pings that got a reply.
+    pub received: u64,
+    /// Consecutive replies (positive) or consecutive failures (negative) up
+    /// to and including the latest ping.
+    pub streak: i64,
+    /// Path MTU found by `--mtu-discover`.
+    pub path_mtu: Option<u32>,
+    /// Extra replies to probes already answered, not counted as received.
+    pub duplicates: u64,
+    /// Replies after the probe timed out, not counted as received.
+    pub late: u64,
+    /// Late replies that came after the reply to a newer probe.
+    pub reordered: u64,
+    min_ms: f64,
+    max_ms: f64,
+    sum_ms: f64,
+    sum_sq_ms: f64,
+    last_ms: Option<f64>,
+    sum_jitter_ms: f64,
+}
+
+impl HostStats {
+    pub fn record_success(&mut self, rtt: Duration) {
+        let ms = rtt.as_secs_f64() * 1000.0;
+        if self.received == 0 || ms < self.min_ms {
+            self.min_ms = ms;
+        }
+        if ms > self.max_ms {
+            self.max_ms = ms;
+        }
+        self.sum_ms += ms;
+        self.sum_sq_ms += ms * ms;
+        if let Some(last_ms) = self.last_ms.replace(ms) {
+            self.sum_jitter_ms += (ms - last_ms).abs();
+        }
+        self.transmitted += 1;
+        self.received += 1;
+        self.streak = self.streak.max(0) + 1;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.transmitted += 1;
+        self.streak = self.streak.min(0) - 1;
+    }
+
+    /// Pings that did not get a reply.
+    pub fn lost(&self) -> u64 {
+        self.transmitted - self.received
+    }
+
+    /// Percentage of transmitted pings without a reply; 0 before the first ping.
+    pub fn loss_percent(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        self.lost() as f64 * 100.0 / self.transmitted as f64
+    }
+
+    /// RTT of the latest reply in milliseconds.
+    pub fn last_ms(&self) -> Option<f64> {
+        self.last_ms
+    }
+
+    /// Mean absolute difference between consecutive RTTs in milliseconds, or
+    /// `None` before the second reply.
+    pub fn jitter_ms(&self) -> Option<f64> {
+        (self.received > 1).then(|| self.sum_jitter_ms / (self.received - 1) as f64)
+    }
+
+    /// `(min, avg, max, mdev)` in milliseconds, or `None` without any reply.
+    pub fn rtt_ms(&self) -> Option<(f64, f64, f64, f64)> {
+        if self.received == 0 {
+            return None;
+        }
+        let n = self.received as f64;
+        let avg = self.sum_ms / n;
+        let mdev = (self.sum_sq_ms / n - avg * avg).max(0.0).sqrt();
+        Some((self.min_ms, avg, self.max_ms, mdev))
+    }
+}
+
+/// Statistics for every host, indexed like the host list. Hosts added while
+/// running are appended; removed ones keep their statistics.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    hosts: Vec<types::Hostname>,
+    per_host: Vec<HostStats>,
+    resolution_errors: Vec<Option<types::ResolveError>>,
+    removed: Vec<bool>,
+}
+
+impl Stats {
+    pub fn new(hosts: Arc<[types::Hostname]>) -> Self {
+        let n = hosts.len();
+        Self {
+            hosts: hosts.to_vec(),
+            per_host: vec![HostStats::default(); n],
+            resolution_errors: vec![None; n],
+            removed: vec![false; n],
+        }
+    }
+
+    /// Account for one event. Events with an out-of-range index are ignored,
+    /// as are hosts added anywhere but at the end.
+    pub fn record(&mut self, ev: &PingEvent) {
+        let i = ev.idx().as_usize();
+        if let PingEvent::HostAdded { host, .. } = ev
+            && i == self.hosts.len()
+        {
+            self.hosts.push(host.clone());
+            self.per_host.push(HostStats::default());
+            self.resolution_errors.push(None);
+            self.removed.push(false);
+            return;
+        }
+        let Some(stats) = self.per_host.get_mut(i) else {
+            return;
+        };
+        match ev {
+            PingEvent::Resolved { .. } => self.resolution_errors[i] = None,
+            PingEvent::AddressChanged { .. } => {}
+            PingEvent::ResolutionFailed { error, .. } => {
+                self.resolution_errors[i] = Some(*error);
+            }
+            PingEvent::Success { rtt, .. } => stats.record_success(*rtt),
+            PingEvent::LateReply { reordered, .. } => {
+                stats.late += 1;
+                stats.reordered += u64::from(*reordered);
+            }
+            PingEvent::Duplicate { .. } => stats.duplicates += 1,
+            PingEvent::Failure { .. } => stats.record_failure(),
+            PingEvent::PathMtu { mtu, .. } => stats.path_mtu = Some(*mtu),
+            PingEvent::HostAdded { .. } => {}
+            PingEvent::HostRemoved { .. } => self.removed[i] = true,
+        }
+    }
+
+    /// True when the host has been removed from the host list.
+    pub fn removed(&self, idx: types::HostIdx) -> bool {
+        self.removed.get(idx.as_usize()).copied().unwrap_or(false)
+    }
+
+    pub fn host(&self, idx: types::HostIdx) -> Option<&HostStats> {
+        self.per_host.get(idx.as_usize())
+    }
+
+    /// True when the host's latest ping failed or it cannot be resolved.
+    pub fn failing(&self, idx: types::HostIdx) -> bool {
+        let i = idx.as_usize();
+        self.per_host.get(i).is_some_and(|stats| stats.streak < 0)
+            || self.resolution_errors.get(i).is_some_and(Option::is_some)
+    }
+
+    /// True when at least one host never got a reply, including hosts that
+    /// could not be resolved. Removed hosts don't count.
+    pub fn any_host_unreachable(&self) -> bool {
+        self.per_host
+            .iter()
+            .zip(&self.removed)
+            .any(|(stats, &removed)| !removed && stats.received == 0)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (host, stats)) in self.hosts.iter().zip(&self.per_host).enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let removed = if self.removed[i] { " (removed)" } else { "" };
+            match host.pinned() {
+                Some(addr) => writeln!(f, "--- {host} ({addr}) ping statistics{removed} ---")?,
+                None => writeln!(f, "--- {host} ping statistics{removed} ---")?,
+            }
+            if let Some(error) = &self.resolution_errors[i] {
+                writeln!(f, "resolution failed: {error}")?;
+                continue;
+            }
+            // Round the loss to one decimal, printing whole numbers without one.
+            let loss = (stats.loss_percent() * 10.0).round() / 10.0;
+            write!(
+                f,
+                "{} packets transmitted, {} received, ",
+                stats.transmitted, stats.received
+            )?;
+            if stats.duplicates > 0 {
+                write!(f, "+{} duplicates, ", stats.duplicates)?;
+            }
+            if stats.late > 0 {
+                write!(f, "+{} late, ", stats.late)?;
+            }
+            if stats.reordered > 0 {
+                write!(f, "{} out of order, ", stats.reordered)?;
+            }
+            writeln!(f, "{loss}% packet loss")?;
+            if let Some((min, avg, max, mdev)) = stats.rtt_ms() {
+                writeln!(
+                    f,
+                    "rtt min/avg/max/mdev = {min:.3}/{avg:.3}/{max:.3}/{mdev:.3} ms"
+                )?;
+            }
+            if let Some(mtu) = stats.path_mtu {
+                writeln!(f, "path mtu {mtu}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::PingFailure;
+
+    fn hosts(names: &[&str]) -> Arc<[types::Hostname]> {
+        names.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn success(i: usize, seq: u16, ms: u64) -> PingEvent {
+        PingEvent::Success {
+            idx: types::HostIdx::new(i),
+            seq,
+            rtt: Duration::from_millis(ms),
+        }
+    }
+
+    fn failure(i: usize, seq: u16) -> PingEvent {
+        PingEvent::Failure {
+            idx: types::HostIdx::new(i),
+            error: PingFailure::Timeout { seq },
+        }
+    }
+
+    #[test]
+    fn aggregates_rtt_and_loss() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        for ev in [success(0, 0, 10), failure(0, 1), success(0, 2, 30)] {
+            stats.record(&ev);
+        }
+        let host = stats.host(types::HostIdx::new(0)).unwrap();
+        assert_eq!((host.transmitted, host.received), (3, 2));
+        assert!((host.loss_percent() - 100.0 / 3.0).abs() < 1e-9);
+        let (min, avg, max, mdev) = host.rtt_ms().unwrap();
+        assert_eq!((min, avg, max), (10.0, 20.0, 30.0));
+        assert!((mdev - 10.0).abs() < 1e-9);
+        assert_eq!(host.jitter_ms(), Some(20.0));
+    }
+
+    #[test]
+    fn streak_counts_consecutive_outcomes() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        let streak = |stats: &Stats| stats.host(types::HostIdx::new(0)).unwrap().streak;
+        stats.record(&success(0, 0, 1));
+        stats.record(&success(0, 1, 1));
+        assert_eq!(streak(&stats), 2);
+        stats.record(&failure(0, 2));
+        stats.record(&failure(0, 3));
+        stats.record(&failure(0, 4));
+        assert_eq!(streak(&stats), -3);
+        stats.record(&success(0, 5, 1));
+        assert_eq!(streak(&stats), 1);
+    }
+
+    #[test]
+    fn later_resolution_clears_the_error() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        stats.record(&PingEvent::ResolutionFailed {
+            idx: types::HostIdx::new(0),
+            error: types::ResolveError::NoAddresses,
+        });
+        stats.record(&PingEvent::Resolved {
+            idx: types::HostIdx::new(0),
+            addr: "10.0.0.1".parse().unwrap(),
+        });
+        stats.record(&success(0, 0, 1));
+        assert!(!stats.to_string().contains("resolution failed"));
+    }
+
+    #[test]
+    fn hosts_without_replies_are_unreachable() {
+        let mut stats = Stats::new(hosts(&["a", "b"]));
+        stats.record(&success(0, 0, 1));
+        assert!(stats.any_host_unreachable());
+        stats.record(&failure(1, 0));
+        assert!(stats.any_host_unreachable());
+        stats.record(&success(1, 1, 1));
+        assert!(!stats.any_host_unreachable());
+    }
+
+    #[test]
+    fn hosts_come_and_go() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        let added = |i, name: &str| PingEvent::HostAdded {
+            idx: types::HostIdx::new(i),
+            host: name.parse().unwrap(),
+        };
+        // Only the next free index takes a host.
+        stats.record(&added(2, "c"));
+        stats.record(&added(1, "b"));
+        stats.record(&success(1, 0, 10));
+        assert!(stats.any_host_unreachable());
+        stats.record(&PingEvent::HostRemoved {
+            idx: types::HostIdx::new(0),
+        });
+        assert!(stats.removed(types::HostIdx::new(0)));
+        // A removed host no longer makes the run fail.
+        assert!(!stats.any_host_unreachable());
+        assert!(!stats.removed(types::HostIdx::new(1)));
+        assert!(stats.host(types::HostIdx::new(2)).is_none());
+        assert_eq!(stats.host(types::HostIdx::new(1)).unwrap().received, 1);
+        let summary = stats.to_string();
+        assert!(summary.starts_with("--- a ping statistics (removed) ---\n"));
+        assert!(summary.contains("--- b ping statistics ---\n1 packets transmitted"));
+    }
+
+    #[test]
+    fn summary_looks_like_ping() {
+        let mut stats = Stats::new(hosts(&["a", "b"]));
+        for ev in [success(0, 0, 10), failure(0, 1), success(0, 2, 30)] {
+            stats.record(&ev);
+        }
+        stats.record(&PingEvent::ResolutionFailed {
+            idx: types::HostIdx::new(1),
+            error: types::ResolveError::NoAddresses,
+        });
+        assert_eq!(
+            stats.to_string(),
+            "--- a ping statistics ---\n\
+             3 packets transmitted, 2 received, 33.3% packet loss\n\
+             rtt min/avg/max/mdev = 10.000/20.000/30.000/10.000 ms\n\
+             \n\
+             --- b ping statistics ---\n\
+             resolution failed: no addresses found\n"
+        );
+    }
+
+    #[test]
+    fn summary_reports_the_path_mtu() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        stats.record(&PingEvent::PathMtu {
+            idx: types::HostIdx::new(0),
+            mtu: 1500,
+        });
+        stats.record(&success(0, 0, 10));
+        assert!(stats.to_string().ends_with("ms\npath mtu 1500\n"));
+    }
+
+    #[test]
+    fn summary_counts_duplicate_late_and_reordered_replies() {
+        let mut stats = Stats::new(hosts(&["a"]));
+        for ev in [
+            success(0, 0, 10),
+            PingEvent::Duplicate {
+                idx: types::HostIdx::new(0),
+                seq: 0,
+                rtt: Duration::from_millis(11),
+            },
+            failure(0, 1),
+            success(0, 2, 10),
+            PingEvent::LateReply {
+                idx: types::HostIdx::new(0),
+                seq: 1,
+                rtt: Duration::from_millis(900),
+                reordered: true,
+            },
+        ] {
+            stats.record(&ev);
+        }
+        let host = stats.host(types::HostIdx::new(0)).unwrap();
+        assert_eq!((host.duplicates, host.late, host.reordered), (1, 1, 1));
+        assert_eq!((host.transmitted, host.received), (3, 2));
+        assert_eq!(host.rtt_ms().unwrap().2, 10.0);
+        assert!(stats.to_string().contains(
+            "3 packets transmitted, 2 received, +1 duplicates, +1 late, 1 out of order, 33.3% packet loss"
+        ));
+    }
+}
@@ -0,0 +1,325 @@
+//! Path tracing for the mtr-like path view.
+//!
+//! `Path` is the functional core: it folds `HopEvent`s into the address and
+//! statistics of every hop towards one host. `run_tracer` is the shell: every
+//! interval it sends one probe per TTL, all at once, and reports each outcome
+//! on its own channel, which only the printer listens to.
+//!
+//! Hops are probed with UDP datagrams, like traceroute(8). Routers answer an
+//! expired ICMP echo request with a message the shared ICMP clients can't
+//! match to the request, whereas the kernel hands the errors caused by a UDP
+//! socket back to that socket (see `probe`).
+
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    client,
+    event::PingFailure,
+    probe,
+    stats::HostStats,
+    types::{self, SocketOptions},
+};
+
+/// Hops probed before the host answers.
+pub const MAX_TTL: u8 = 30;
+
+/// Destination port of hop probes for hosts that don't name one, as used by
+/// traceroute(8); nothing is expected to listen there.
+const TRACE_PORT: u16 = 33434;
+
+/// Outcome of one probe sent with a limited TTL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HopEvent {
+    pub ttl: u8,
+    pub outcome: HopOutcome,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HopOutcome {
+    /// Router `from` reported that the probe's TTL ran out.
+    Router {
+        from: IpAddr,
+        rtt: Duration,
+    },
+    /// The host itself answered.
+    Destination {
+        rtt: Duration,
+    },
+    Lost,
+}
+
+/// One hop of a path: the latest address that answered for it and how
+/// its probes fared.
+#[derive(Clone, Debug, Default)]
+pub struct Hop {
+    pub addr: Option<IpAddr>,
+    pub stats: HostStats,
+}
+
+/// Hops towards `target`, in TTL order.
+#[derive(Clone, Debug)]
+pub struct Path {
+    target: IpAddr,
+    hops: Vec<Hop>,
+    /// Lowest TTL at which the host answered; no hop lies beyond it.
+    reached: Option<u8>,
+}
+
+impl Path {
+    pub fn new(target: IpAddr) -> Self {
+        Self {
+            target,
+            hops: Vec::new(),
+            reached: None,
+        }
+    }
+
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Hops up to the host once it answered, else up to the furthest one
+    /// probed.
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    /// Account for one probe. Outcomes beyond the host are ignored.
+    pub fn record(&mut self, ev: &HopEvent) {
+        let Some(i) = usize::from(ev.ttl).checked_sub(1) else {
+            return;
+        };
+        if self.reached.is_some_and(|reached| ev.ttl > reached) {
+            return;
+        }
+        if self.hops.len() <= i {
+            self.hops.resize_with(i + 1, Hop::default);
+        }
+        let hop = &mut self.hops[i];
+        match ev.outcome {
+            HopOutcome::Router { from, rtt } => {
+                hop.addr = Some(from);
+                hop.stats.record_success(rtt);
+            }
+            HopOutcome::Destination { rtt } => {
+                hop.addr = Some(self.target);
+                hop.stats.record_success(rtt);
+                self.reached = Some(ev.ttl);
+                self.hops.truncate(i + 1);
+            }
+            HopOutcome::Lost => hop.stats.record_failure(),
+        }
+    }
+}
+
+/// How to trace one host: the settings of its worker.
+#[derive(Clone)]
+pub struct TraceConfig {
+    pub probe: types::Probe,
+    pub clients: client::PingClients,
+    pub id: surge_ping::PingIdentifier,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub payload_len: usize,
+    pub options: SocketOptions,
+}
+
+/// How to trace every host, indexed like the host list; `run` appends the
+/// hosts that join while running.
+pub type Tracers = Arc<Mutex<Vec<TraceConfig>>>;
+
+/// The probe hops towards a host probed with `probe` are traced with: UDP
+/// hosts are traced on their own port so that their echo service answers
+/// the last hop, every other host on `TRACE_PORT`.
+pub fn hop_probe(probe: types::Probe) -> types::Probe {
+    match probe {
+        types::Probe::Udp(port) => types::Probe::Udp(port),
+        _ => types::Probe::Udp(TRACE_PORT),
+    }
+}
+
+/// Probe every hop towards `addr` once per interval, until the receiver is
+/// gone. Once the host answers at some TTL, the probes beyond it still in
+/// flight are dropped and later rounds stop there.
+pub async fn run_tracer(cfg: TraceConfig, addr: IpAddr, tx: mpsc::Sender<HopEvent>) {
+    let probe = hop_probe(cfg.probe);
+    // One prober per TTL, the first one for TTL 1.
+    let mut probers = Vec::with_capacity(MAX_TTL.into());
+    for ttl in 1..=MAX_TTL {
+        let options = SocketOptions {
+            ttl: Some(ttl),
+            ..cfg.options
+        };
+        probers.push(
+            probe::Prober::new(
+                probe,
+                addr,
+                &cfg.clients,
+                cfg.id,
+                cfg.timeout,
+                cfg.payload_len,
+                options,
+            )
+            .await,
+        );
+    }
+    let mut ticker = tokio::time::interval(cfg.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut seq: u16 = 0;
+    loop {
+        ticker.tick().await;
+        let mut round = tokio::task::JoinSet::new();
+        let mut pending = Vec::with_capacity(probers.len());
+        for (ttl, prober) in (1..).zip(&probers) {
+            let round_trip = prober.send(seq, prober.timeout());
+            pending.push(round.spawn(async move {
+                let start = Instant::now();
+                let outcome = outcome(round_trip.await, start.elapsed());
+                HopEvent { ttl, outcome }
+            }));
+            seq = seq.wrapping_add(1);
+        }
+        while let Some(joined) = round.join_next().await {
+            let Ok(ev) = joined else { continue };
+            if usize::from(ev.ttl) > probers.len() {
+                continue;
+            }
+            if let HopOutcome::Destination { .. } = ev.outcome {
+                probers.truncate(ev.ttl.into());
+                pending.drain(probers.len()..).for_each(|task| task.abort());
+            }
+            if tx.send(ev).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// What a hop probe's result says about the hop. A refused datagram was
+/// refused by the host itself.
+fn outcome(result: Result<Duration, PingFailure>, elapsed: Duration) -> HopOutcome {
+    match result {
+        Ok(rtt) => HopOutcome::Destination { rtt },
+        Err(PingFailure::Io(std::io::ErrorKind::ConnectionRefused)) => {
+            HopOutcome::Destination { rtt: elapsed }
+        }
+        Err(PingFailure::TtlExceeded { from }) => HopOutcome::Router { from, rtt: elapsed },
+        Err(_) => HopOutcome::Lost,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn hop(ttl: u8, outcome: HopOutcome) -> HopEvent {
+        HopEvent { ttl, outcome }
+    }
+
+    fn router(last_octet: u8) -> HopOutcome {
+        HopOutcome::Router {
+            from: Ipv4Addr::new(10, 0, 0, last_octet).into(),
+            rtt: Duration::from_millis(last_octet.into()),
+        }
+    }
+
+    fn destination() -> HopOutcome {
+        HopOutcome::Destination {
+            rtt: Duration::from_millis(9),
+        }
+    }
+
+    #[test]
+    fn hops_fill_in_as_probes_return() {
+        let mut path = Path::new("192.0.2.1".parse().unwrap());
+        for ev in [
+            hop(2, router(2)),
+            hop(1, router(1)),
+            hop(3, HopOutcome::Lost),
+        ] {
+            path.record(&ev);
+        }
+        let addrs: Vec<_> = path.hops().iter().map(|hop| hop.addr).collect();
+        assert_eq!(
+            addrs,
+            [
+                Some("10.0.0.1".parse().unwrap()),
+                Some("10.0.0.2".parse().unwrap()),
+                None
+            ]
+        );
+        assert_eq!(path.hops()[2].stats.lost(), 1);
+    }
+
+    #[test]
+    fn the_host_ends_the_path() {
+        let mut path = Path::new("192.0.2.1".parse().unwrap());
+        // Probes beyond the host reach it too, in any order.
+        for ev in [
+            hop(1, router(1)),
+            hop(4, destination()),
+            hop(3, destination()),
+            hop(5, destination()),
+            hop(2, router(2)),
+        ] {
+            path.record(&ev);
+        }
+        assert_eq!(path.hops().len(), 3);
+        assert_eq!(path.hops()[2].addr, Some(path.target()));
+        assert_eq!(path.hops()[2].stats.received, 1);
+    }
+
+    #[test]
+    fn ignores_ttl_zero() {
+        let mut path = Path::new("192.0.2.1".parse().unwrap());
+        path.record(&hop(0, router(1)));
+        assert!(path.hops().is_empty());
+    }
+
+    #[test]
+    fn hops_are_traced_on_the_port_of_udp_hosts() {
+        assert_eq!(hop_probe(types::Probe::Udp(7)), types::Probe::Udp(7));
+        for probe in [types::Probe::Icmp, types::Probe::Tcp(22)] {
+            assert_eq!(hop_probe(probe), types::Probe::Udp(TRACE_PORT));
+        }
+    }
+
+    #[tokio::test]
+    async fn localhost_is_one_hop_away() {
+        let (tx, mut rx) = mpsc::channel(64);
+        let cfg = TraceConfig {
+            probe: types::Probe::Icmp,
+            clients: client::PingClients::new([]).unwrap(),
+            id: surge_ping::PingIdentifier(1),
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_millis(500),
+            payload_len: probe::DEFAULT_PAYLOAD_LEN,
+            options: SocketOptions::default(),
+        };
+        let tracer = tokio::spawn(run_tracer(cfg, Ipv4Addr::LOCALHOST.into(), tx));
+        let mut path = Path::new(Ipv4Addr::LOCALHOST.into());
+        // Probes with a higher TTL may answer first.
+        loop {
+            let ev = rx.recv().await.unwrap();
+            path.record(&ev);
+            if ev.ttl == 1 {
+                break;
+            }
+        }
+        assert_eq!(path.hops().len(), 1);
+        assert_eq!(path.hops()[0].addr, Some(Ipv4Addr::LOCALHOST.into()));
+        // Later rounds only probe up to the host.
+        for _ in 0..3 {
+            assert_eq!(rx.recv().await.unwrap().ttl, 1);
+        }
+        tracer.abort();
+    }
+}
@@ -1,32 +1,290 @@
 //! Domain-level newtypes shared across the crate.
 
-use std::{io, net::IpAddr, sync::Arc};
+use std::{io, net::IpAddr, ops::Range, sync::Arc, time::Duration};
 
-/// A hostname or IP-address string, validated at the CLI boundary.
-#[derive(Clone, Debug)]
-pub struct Hostname(Arc<str>);
+/// A host to ping as typed by the user, validated at the CLI boundary.
+///
+/// Besides a bare hostname or IP address, the string may name the probe used
+/// for this host: `icmp://host`, `icmp-dgram://host`, `tcp://host:port` or
+/// `udp://host:port`. IPv6 literals are bracketed when followed by a port,
+/// e.g. `tcp://[::1]:22`.
+///
+/// In the config file a host may also be a table with per-host settings, see
+/// the `Deserialize` impl.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hostname {
+    spec: Arc<str>,
+    /// Byte range of the bare host within `spec`.
+    host: Range<usize>,
+    probe: Probe,
+    /// Only resolve to addresses of this family.
+    family: Option<Family>,
+    /// Address this host is pinned to, by `--all-addresses` or a config
+    /// `address`; never resolved.
+    pinned: Option<IpAddr>,
+    /// Shown instead of `spec` in the live display.
+    label: Option<Arc<str>>,
+    /// Section of the live display this host is listed under.
+    group: Option<Arc<str>>,
+    /// Overrides the global interval for this host.
+    interval: Option<Duration>,
+    /// Overrides the global timeout for this host.
+    timeout: Option<Duration>,
+    /// Bytes of payload in ICMP echo requests and UDP datagrams.
+    payload_size: Option<u16>,
+    /// Overrides the global TTL (IPv6 hop limit) for this host.
+    ttl: Option<u8>,
+    /// Overrides the global TOS (IPv6 traffic class) for this host.
+    tos: Option<u8>,
+    /// Overrides the global don't-fragment setting for this host.
+    dont_fragment: Option<bool>,
+}
+
+/// IP address family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    pub fn matches(self, addr: IpAddr) -> bool {
+        match self {
+            Self::Ipv4 => addr.is_ipv4(),
+            Self::Ipv6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ipv4 => "IPv4",
+            Self::Ipv6 => "IPv6",
+        })
+    }
+}
+
+/// IP-level options of the probe sockets, set on each one by `apply`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// IPv4 TTL or IPv6 hop limit; the system default when `None`.
+    pub ttl: Option<u8>,
+    /// IPv4 type of service or IPv6 traffic class, DSCP in the upper six bits.
+    pub tos: Option<u8>,
+    /// Set don't-fragment and never fragment locally either.
+    pub dont_fragment: bool,
+}
+
+impl SocketOptions {
+    /// Set the options on `socket`, an IPv6 socket when `v6`.
+    pub fn apply(&self, socket: socket2::SockRef<'_>, v6: bool) -> io::Result<()> {
+        if let Some(ttl) = self.ttl {
+            if v6 {
+                socket.set_unicast_hops_v6(ttl.into())?;
+            } else {
+                socket.set_ttl_v4(ttl.into())?;
+            }
+        }
+        if let Some(tos) = self.tos {
+            if v6 {
+                set_tclass_v6(&socket, tos)?;
+            } else {
+                socket.set_tos_v4(tos.into())?;
+            }
+        }
+        if self.dont_fragment {
+            set_dont_fragment(&socket, v6)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn set_tclass_v6(socket: &socket2::SockRef<'_>, tclass: u8) -> io::Result<()> {
+    socket.set_tclass_v6(tclass.into())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn set_tclass_v6(_: &socket2::SockRef<'_>, _: u8) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Ask for path MTU discovery with don't-fragment set on every packet, so
+/// oversized datagrams fail with `EMSGSIZE` instead of being fragmented.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_dont_fragment(socket: &socket2::SockRef<'_>, v6: bool) -> io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+
+    let (level, name, value) = if v6 {
+        (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_DO,
+        )
+    } else {
+        (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        )
+    };
+    setsockopt(socket.as_raw_fd(), level, name, value)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_dont_fragment(_: &socket2::SockRef<'_>, _: bool) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn setsockopt(
+    fd: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: `value` outlives the call and its size is passed along.
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            (&raw const value).cast(),
+            std::mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// How a host is probed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// ICMP echo over a raw socket, which needs CAP_NET_RAW.
+    Icmp,
+    /// ICMP echo over an unprivileged datagram socket, where the kernel allows
+    /// them (see `net.ipv4.ping_group_range` on Linux).
+    IcmpDgram,
+    /// TCP connect to the given port; the handshake time is the RTT.
+    Tcp(u16),
+    /// UDP datagram to the given port, answered by an echo service.
+    Udp(u16),
+}
 
 impl Hostname {
+    /// The string exactly as the user typed it, probe scheme included.
     pub fn as_str(&self) -> &str {
-        self.0.as_ref()
+        self.spec.as_ref()
+    }
+
+    /// The bare hostname or IP address, without probe scheme or port.
+    pub fn host(&self) -> &str {
+        &self.spec[self.host.clone()]
+    }
+
+    pub fn probe(&self) -> Probe {
+        self.probe
+    }
+
+    pub fn family(&self) -> Option<Family> {
+        self.family
+    }
+
+    /// Only resolve to addresses of `family`.
+    pub fn with_family(self, family: Family) -> Self {
+        Self {
+            family: Some(family),
+            ..self
+        }
+    }
+
+    /// Name shown in the live display: the label if any, else the spec.
+    pub fn display_name(&self) -> &str {
+        self.label.as_deref().unwrap_or(self.as_str())
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn payload_size(&self) -> Option<u16> {
+        self.payload_size
+    }
+
+    /// This host's socket options, falling back to `defaults` for those it
+    /// leaves unset.
+    pub fn socket_options(&self, defaults: SocketOptions) -> SocketOptions {
+        SocketOptions {
+            ttl: self.ttl.or(defaults.ttl),
+            tos: self.tos.or(defaults.tos),
+            dont_fragment: self.dont_fragment.unwrap_or(defaults.dont_fragment),
+        }
+    }
+
+    /// The address this host is pinned to, if any.
+    pub fn pinned(&self) -> Option<IpAddr> {
+        self.pinned
+    }
+
+    /// The same host pinned to `addr`: it keeps its name and probe but always
+    /// resolves to `addr`.
+    pub fn pinned_to(&self, addr: IpAddr) -> Self {
+        Self {
+            pinned: Some(addr),
+            ..self.clone()
+        }
+    }
+
+    /// Resolves the bare hostname or IP address to its first `IpAddr` of the
+    /// requested family.
+    pub async fn resolve(&self) -> Result<IpAddr, ResolveError> {
+        Ok(self.resolve_all().await?[0])
     }
 
-    /// Resolves this hostname or IP-address string to its first `IpAddr`.
+    /// Resolves the bare hostname or IP address to every distinct `IpAddr` of
+    /// the requested family, in resolver order. Never returns an empty list.
     ///
     /// Tries a direct parse first (handles bare IP literals without a DNS
     /// round-trip), then falls back to `tokio::net::lookup_host`.
-    pub async fn resolve(&self) -> Result<IpAddr, ResolveError> {
-        let host = self.0.as_ref();
-        if let Ok(ip) = host.parse::<IpAddr>() {
-            return Ok(ip);
+    pub async fn resolve_all(&self) -> Result<Vec<IpAddr>, ResolveError> {
+        if let Some(addr) = self.pinned {
+            return Ok(vec![addr]);
+        }
+        let host = self.host();
+        let found: Vec<IpAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => tokio::net::lookup_host(format!("{host}:0"))
+                .await
+                .map_err(|e| ResolveError::DnsLookup(e.kind()))?
+                .map(|sa| sa.ip())
+                .collect(),
+        };
+        let mut addrs = Vec::with_capacity(found.len());
+        for addr in found {
+            if self.family.is_none_or(|family| family.matches(addr)) && !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
         }
-        let mut addrs = tokio::net::lookup_host(format!("{host}:0"))
-            .await
-            .map_err(|e| ResolveError::DnsLookup(e.kind()))?;
-        addrs
-            .next()
-            .map(|sa| sa.ip())
-            .ok_or(ResolveError::NoAddresses)
+        if addrs.is_empty() {
+            return Err(match self.family {
+                Some(family) => ResolveError::NoAddressesInFamily(family),
+                None => ResolveError::NoAddresses,
+            });
+        }
+        Ok(addrs)
     }
 }
 
@@ -35,6 +293,7 @@ impl Hostname {
 pub enum ResolveError {
     DnsLookup(io::ErrorKind),
     NoAddresses,
+    NoAddressesInFamily(Family),
 }
 
 impl std::fmt::Display for ResolveError {
@@ -42,32 +301,268 @@ impl std::fmt::Display for ResolveError {
         match self {
             Self::DnsLookup(kind) => write!(f, "dns lookup failed: {}", io::Error::from(*kind)),
             Self::NoAddresses => f.write_str("no addresses found"),
+            Self::NoAddressesInFamily(family) => write!(f, "no {family} addresses found"),
         }
     }
 }
 
 impl std::error::Error for ResolveError {}
 
+/// Malformed host string, e.g. an unknown probe scheme or a missing port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostnameError(String);
+
+impl std::fmt::Display for HostnameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HostnameError {}
+
 impl std::fmt::Display for Hostname {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.spec.fmt(f)
     }
 }
 
+/// Config-file form of a host: either the same string as on the command
+/// line, or a table
+///
+/// ```toml
+/// { name = "db.example.com", address = "10.0.0.5", label = "db",
+///   group = "backend", interval = 5000, timeout = 1000,
+///   payload_size = 56, probe = "tcp:5432", family = "ipv4",
+///   ttl = 64, dscp = 46, dont_fragment = true }
+/// ```
+///
+/// where only `name` (or its alias `host`) is required. `address` skips DNS,
+/// `interval` and `timeout` are milliseconds, and `probe` is `icmp`,
+/// `icmp-dgram`, `tcp:PORT` or `udp:PORT`. `size` is an alias of
+/// `payload_size`; `dscp` is the upper six bits of `tos`, so only one of the
+/// two may be given.
 impl<'de> serde::Deserialize<'de> for Hostname {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let value = <String as serde::Deserialize>::deserialize(deserializer)?;
-        Ok(Self(Arc::from(value)))
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Table {
+            #[serde(alias = "host")]
+            name: String,
+            address: Option<IpAddr>,
+            label: Option<String>,
+            group: Option<String>,
+            interval: Option<u64>,
+            timeout: Option<u64>,
+            #[serde(alias = "size")]
+            payload_size: Option<u16>,
+            probe: Option<String>,
+            family: Option<Family>,
+            ttl: Option<u8>,
+            tos: Option<u8>,
+            dscp: Option<u8>,
+            dont_fragment: Option<bool>,
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Spec(String),
+            Table(Table),
+        }
+
+        let table = match <Repr as serde::Deserialize>::deserialize(deserializer)? {
+            Repr::Spec(spec) => return spec.parse().map_err(serde::de::Error::custom),
+            Repr::Table(table) => table,
+        };
+        let spec = match &table.probe {
+            Some(probe) => spec_with_probe(&table.name, probe),
+            None => Ok(table.name),
+        };
+        let mut host: Self = spec
+            .and_then(|spec| spec.parse())
+            .map_err(serde::de::Error::custom)?;
+        let millis = |key: &str, ms: Option<u64>| match ms {
+            Some(0) => Err(serde::de::Error::custom(format!(
+                "`{key}` must be at least 1 ms"
+            ))),
+            ms => Ok(ms.map(Duration::from_millis)),
+        };
+        host.interval = millis("interval", table.interval)?;
+        host.timeout = millis("timeout", table.timeout)?;
+        host.family = table.family;
+        host.pinned = table.address;
+        host.label = table.label.map(Arc::from);
+        host.group = table.group.map(Arc::from);
+        host.payload_size = table.payload_size;
+        host.ttl = match table.ttl {
+            Some(0) => return Err(serde::de::Error::custom("`ttl` must be at least 1")),
+            ttl => ttl,
+        };
+        host.tos = match (table.tos, table.dscp) {
+            (Some(_), Some(_)) => {
+                return Err(serde::de::Error::custom("`tos` conflicts with `dscp`"));
+            }
+            (_, Some(dscp)) if dscp > 63 => {
+                return Err(serde::de::Error::custom("`dscp` must be at most 63"));
+            }
+            (tos, dscp) => tos.or(dscp.map(|dscp| dscp << 2)),
+        };
+        host.dont_fragment = table.dont_fragment;
+        Ok(host)
+    }
+}
+
+/// Turn a config `name` and `probe` (`icmp`, `icmp-dgram`, `tcp:PORT` or
+/// `udp:PORT`) into the equivalent command-line host string.
+fn spec_with_probe(name: &str, probe: &str) -> Result<String, HostnameError> {
+    if name.contains("://") {
+        return Err(HostnameError(format!(
+            "`{name}`: `probe` conflicts with the probe in `name`"
+        )));
     }
+    Ok(match probe.split_once(':') {
+        Some((scheme, port)) if name.contains(':') => format!("{scheme}://[{name}]:{port}"),
+        Some((scheme, port)) => format!("{scheme}://{name}:{port}"),
+        None => format!("{probe}://{name}"),
+    })
 }
 
 impl std::str::FromStr for Hostname {
-    type Err = std::convert::Infallible;
+    type Err = HostnameError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Hostname(Arc::from(s)))
+        let error = |msg: &str| Err(HostnameError(format!("`{s}`: {msg}")));
+        let (scheme, rest) = match s.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme), rest),
+            None => (None, s),
+        };
+        let start = s.len() - rest.len();
+        let (host, probe) = match scheme {
+            None | Some("icmp") => (rest, Probe::Icmp),
+            Some("icmp-dgram") => (rest, Probe::IcmpDgram),
+            Some(scheme @ ("tcp" | "udp")) => {
+                let Some((host, port)) = rest.rsplit_once(':') else {
+                    return error(&format!("a port is required, e.g. {scheme}://host:7"));
+                };
+                let port = match port.parse::<u16>() {
+                    Ok(port) if port > 0 => port,
+                    _ => return error(&format!("invalid port `{port}`")),
+                };
+                let probe = if scheme == "tcp" {
+                    Probe::Tcp(port)
+                } else {
+                    Probe::Udp(port)
+                };
+                (host, probe)
+            }
+            Some(scheme) => {
+                return error(&format!(
+                    "unknown probe `{scheme}`, expected icmp, icmp-dgram, tcp or udp"
+                ));
+            }
+        };
+        // Brackets only delimit an IPv6 literal from the port; drop them.
+        let (start, host) = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            Some(inner) => (start + 1, inner),
+            None => (start, host),
+        };
+        if host.is_empty() {
+            return error("missing host");
+        }
+        Ok(Hostname {
+            spec: Arc::from(s),
+            host: start..start + host.len(),
+            probe,
+            family: None,
+            pinned: None,
+            label: None,
+            group: None,
+            interval: None,
+            timeout: None,
+            payload_size: None,
+            ttl: None,
+            tos: None,
+            dont_fragment: None,
+        })
+    }
+}
+
+/// How events are presented.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// Live TUI when stdout is a terminal, plain log lines otherwise
+    #[default]
+    Auto,
+    /// Live per-host spinners
+    Tui,
+    /// One timestamped, human-readable line per event
+    Plain,
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header line
+    Csv,
+}
+
+impl OutputMode {
+    /// Replace `Auto` with the concrete mode suited to the terminal.
+    pub fn resolve(self, stdout_is_terminal: bool) -> Self {
+        match self {
+            Self::Auto if stdout_is_terminal => Self::Tui,
+            Self::Auto => Self::Plain,
+            mode => mode,
+        }
+    }
+}
+
+/// What the tallest sparkline block stands for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SparklineScale {
+    /// Each host's slowest recent RTT
+    #[default]
+    Host,
+    /// The slowest recent RTT across all hosts
+    Global,
+}
+
+/// Order of hosts in the live display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// As given on the command line or in the config file
+    #[default]
+    None,
+    /// By displayed name
+    Name,
+    /// Highest packet loss first
+    Loss,
+    /// Highest average RTT first
+    Latency,
+}
+
+impl SortOrder {
+    /// The order after this one, cycling back to `None`.
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Name,
+            Self::Name => Self::Loss,
+            Self::Loss => Self::Latency,
+            Self::Latency => Self::None,
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Name => "name",
+            Self::Loss => "loss",
+            Self::Latency => "latency",
+        })
     }
 }
 
@@ -93,6 +588,74 @@ impl HostIdx {
 mod tests {
     use super::*;
 
+    #[test]
+    fn auto_output_mode_depends_on_the_terminal() {
+        assert_eq!(OutputMode::Auto.resolve(true), OutputMode::Tui);
+        assert_eq!(OutputMode::Auto.resolve(false), OutputMode::Plain);
+        assert_eq!(OutputMode::Csv.resolve(true), OutputMode::Csv);
+    }
+
+    #[test]
+    fn parses_probe_schemes() {
+        let cases = [
+            ("example.com", "example.com", Probe::Icmp),
+            ("::1", "::1", Probe::Icmp),
+            ("icmp://example.com", "example.com", Probe::Icmp),
+            ("icmp-dgram://10.0.0.1", "10.0.0.1", Probe::IcmpDgram),
+            ("tcp://db:5432", "db", Probe::Tcp(5432)),
+            ("udp://[::1]:7", "::1", Probe::Udp(7)),
+        ];
+        for (input, host, probe) in cases {
+            let parsed = input.parse::<Hostname>().unwrap();
+            assert_eq!(parsed.as_str(), input);
+            assert_eq!(parsed.host(), host, "{input}");
+            assert_eq!(parsed.probe(), probe, "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_probes() {
+        for input in [
+            "tcp://db",
+            "tcp://db:0",
+            "udp://db:http",
+            "http://example.com",
+            "icmp://",
+            "tcp://:80",
+        ] {
+            assert!(
+                input.parse::<Hostname>().is_err(),
+                "{input} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn family_filters_resolved_addresses() {
+        let host = "127.0.0.1".parse::<Hostname>().unwrap();
+        let v4: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert_eq!(
+            host.clone().with_family(Family::Ipv4).resolve().await,
+            Ok(v4)
+        );
+        assert_eq!(
+            host.with_family(Family::Ipv6).resolve().await,
+            Err(ResolveError::NoAddressesInFamily(Family::Ipv6))
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_hosts_resolve_to_their_address() {
+        let host = "localhost".parse::<Hostname>().unwrap();
+        let addr: IpAddr = "::1".parse().unwrap();
+        let pinned = host.pinned_to(addr);
+
+        assert_eq!(pinned.as_str(), "localhost");
+        assert_eq!(pinned.pinned(), Some(addr));
+        assert_eq!(pinned.resolve_all().await, Ok(vec![addr]));
+    }
+
     #[test]
     fn cloned_hostnames_share_backing_storage() {
         let host = "example.com".parse::<Hostname>().unwrap();
@@ -103,4 +666,20 @@ mod tests {
             clone.as_str().as_ptr()
         ));
     }
+
+    #[test]
+    fn socket_options_are_set_on_the_socket() {
+        let socket = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let options = SocketOptions {
+            ttl: Some(7),
+            tos: Some(46 << 2),
+            dont_fragment: cfg!(target_os = "linux"),
+        };
+        options
+            .apply(socket2::SockRef::from(&socket), false)
+            .unwrap();
+        let socket = socket2::SockRef::from(&socket);
+        assert_eq!(socket.ttl_v4().unwrap(), 7);
+        assert_eq!(socket.tos_v4().unwrap(), 46 << 2);
+    }
 }
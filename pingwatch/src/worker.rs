@@ -1,9 +1,13 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
 use tokio::sync::mpsc;
 
-use crate::{client, event, types};
+use crate::{client, event, probe, types};
 
-/// Per-worker configuration: host identity, timing, and shared ICMP clients.
+/// Per-worker configuration: host identity (including its probe kind), timing,
+/// and shared ICMP clients.
 pub struct WorkerConfig {
     pub host: types::Hostname,
     pub idx: types::HostIdx,
@@ -11,35 +15,74 @@ pub struct WorkerConfig {
     pub clients: client::PingClients,
     pub interval: Duration,
     pub timeout: Duration,
+    /// Bytes of payload per probe, for the probe kinds that carry one.
+    pub payload_len: usize,
+    /// TTL, TOS and don't-fragment of the probe sockets.
+    pub options: types::SocketOptions,
+    /// Discover the path MTU, whenever the host is resolved, before pinging.
+    pub mtu_discover: bool,
+    /// Number of pings after which the worker stops; `None` pings forever.
+    pub count: Option<u64>,
+    /// Instant after which the worker stops, shared by all workers.
+    pub deadline: Option<tokio::time::Instant>,
+    /// How long a resolved address is trusted before resolving again.
+    pub resolve_interval: Duration,
+    /// Consecutive failures after which the host is resolved again early.
+    pub resolve_after_failures: u32,
 }
 
-/// Resolves a host and pings in a loop -- emitting a typed `PingEvent` for
-/// every outcome. Contains zero display logic.
+/// How many timeouts a timed-out probe keeps waiting for a late reply.
+const LATE_REPLY_TIMEOUTS: u32 = 4;
+
+/// Recent probes remembered to tell what a stray reply answers.
+const SEQ_WINDOW: usize = 1024;
+
+/// Stray replies buffered before further ones go uncounted.
+const STRAY_CAPACITY: usize = 64;
+
+/// Upper bound for the delay between two failed resolutions.
+const MAX_RESOLVE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Largest payload of an ICMP echo or UDP datagram over IPv4 and IPv6.
+const MAX_PAYLOAD_V4: usize = 65_507;
+const MAX_PAYLOAD_V6: usize = 65_527;
+
+/// Resolves a host and probes it in a loop -- emitting a typed `PingEvent` for
+/// every outcome, whatever the probe kind. Contains zero display logic.
 ///
 /// `tx` is moved in so it drops automatically when the task exits, contributing
 /// to the "all senders gone -> printer exits" signal without explicit coordination.
+///
+/// A probe that times out is reported as failed but keeps waiting a few more
+/// timeouts; a reply in that time is reported as a `LateReply` for the same
+/// sequence number. Stray replies the prober sees after that, or after a
+/// probe was answered, are reported as late or `Duplicate` by what became of
+/// their probe.
+///
+/// The worker returns after `cfg.count` pings or at `cfg.deadline`, whichever
+/// comes first, failed resolutions counting as pings; a ping still in flight
+/// at the deadline is dropped unreported, as are late replies still awaited.
 pub async fn run_worker(cfg: WorkerConfig, tx: mpsc::Sender<event::PingEvent>) {
-    let addr = match cfg.host.resolve().await {
-        Ok(addr) => {
-            let _ = tx
-                .send(event::PingEvent::Resolved { idx: cfg.idx, addr })
-                .await;
-            addr
-        }
-        Err(e) => {
-            let _ = tx
-                .send(event::PingEvent::ResolutionFailed {
-                    idx: cfg.idx,
-                    error: e,
-                })
-                .await;
-            return;
+    match cfg.deadline {
+        Some(deadline) => {
+            let _ = tokio::time::timeout_at(deadline, resolve_and_ping(cfg, tx)).await;
         }
-    };
+        None => resolve_and_ping(cfg, tx).await,
+    }
+}
 
-    let client = cfg.clients.for_addr(addr);
-    let mut pinger = client.pinger(addr, cfg.id).await;
-    pinger.timeout(cfg.timeout);
+async fn resolve_and_ping(cfg: WorkerConfig, tx: mpsc::Sender<event::PingEvent>) {
+    let mut sent: u64 = 0;
+    let Some(mut addr) = resolve_with_backoff(&cfg, &mut sent, &tx).await else {
+        return;
+    };
+    let (strays_tx, mut strays) = mpsc::channel(STRAY_CAPACITY);
+    let mut prober = new_prober(&cfg, addr, &strays_tx).await;
+    let mut seq: u16 = 0;
+    if !discover_mtu(&cfg, &mut prober, addr, &mut seq, &tx).await {
+        return;
+    }
+    let mut resolve_at = tokio::time::Instant::now() + cfg.resolve_interval;
 
     // Use a fixed-interval ticker instead of post-ping sleep so that RTT and
     // processing time don't accumulate as drift. Delay behavior skips missed
@@ -47,34 +90,343 @@ pub async fn run_worker(cfg: WorkerConfig, tx: mpsc::Sender<event::PingEvent>) {
     let mut ticker = tokio::time::interval(cfg.interval);
     ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-    let mut seq: u16 = 0;
-    loop {
-        ticker.tick().await; // first tick fires immediately; subsequent ticks are interval-aligned
-        match pinger.ping(surge_ping::PingSequence(seq), &[0u8; 8]).await {
-            Ok((_, rtt)) => {
-                // Break when the printer has exited -- no point continuing.
-                if tx
-                    .send(event::PingEvent::Success { idx: cfg.idx, rtt })
-                    .await
-                    .is_err()
-                {
-                    return;
+    // Probes in flight, by sequence number, including timed-out ones still
+    // waiting for a late reply.
+    let mut in_flight = tokio::task::JoinSet::new();
+    let late_wait = cfg.timeout * LATE_REPLY_TIMEOUTS;
+    let mut probes = SentProbes::default();
+
+    let mut failures: u32 = 0;
+    while cfg.count.is_none_or(|count| sent < count) {
+        // The first tick fires immediately; subsequent ticks are
+        // interval-aligned. Late replies are reported while waiting.
+        loop {
+            tokio::select! {
+                biased;
+                _ = ticker.tick() => break,
+                Some(Ok((late_seq, result))) = in_flight.join_next() => {
+                    if !send_late(&cfg, &mut probes, late_seq, result, &tx).await {
+                        return;
+                    }
+                }
+                Some(stray) = strays.recv() => {
+                    if !send_stray(&cfg, &mut probes, stray, &tx).await {
+                        return;
+                    }
                 }
             }
-            Err(e) => {
-                if tx
-                    .send(event::PingEvent::Failure {
-                        idx: cfg.idx,
-                        error: e.into(),
-                    })
-                    .await
-                    .is_err()
-                {
+        }
+
+        // Follow the host to a new address when its record has expired or it
+        // stopped answering. A failed re-resolution keeps the old address.
+        let stale = failures > 0 && failures.is_multiple_of(cfg.resolve_after_failures);
+        if stale || tokio::time::Instant::now() >= resolve_at {
+            resolve_at = tokio::time::Instant::now() + cfg.resolve_interval;
+            if let Ok(new) = cfg.host.resolve().await
+                && new != addr
+            {
+                let ev = event::PingEvent::AddressChanged {
+                    idx: cfg.idx,
+                    from: addr,
+                    to: new,
+                };
+                if tx.send(ev).await.is_err() {
+                    return;
+                }
+                addr = new;
+                prober = new_prober(&cfg, addr, &strays_tx).await;
+                if !discover_mtu(&cfg, &mut prober, addr, &mut seq, &tx).await {
                     return;
                 }
             }
         }
+
+        let reply = prober.send(seq, late_wait);
+        probes.sent(seq, Instant::now());
+        in_flight.spawn(async move { (seq, reply.await) });
+        let timeout_at = tokio::time::Instant::now() + cfg.timeout;
+        let result = loop {
+            tokio::select! {
+                biased;
+                Some(Ok((done, result))) = in_flight.join_next() => {
+                    if done == seq {
+                        break result;
+                    }
+                    if !send_late(&cfg, &mut probes, done, result, &tx).await {
+                        return;
+                    }
+                }
+                Some(stray) = strays.recv() => {
+                    if !send_stray(&cfg, &mut probes, stray, &tx).await {
+                        return;
+                    }
+                }
+                _ = tokio::time::sleep_until(timeout_at) => {
+                    break Err(event::PingFailure::Timeout { seq });
+                }
+            }
+        };
+        let ev = match result {
+            Ok(rtt) => {
+                failures = 0;
+                probes.answered(seq);
+                event::PingEvent::Success {
+                    idx: cfg.idx,
+                    seq,
+                    rtt,
+                }
+            }
+            Err(error) => {
+                if !matches!(error, event::PingFailure::Timeout { .. }) {
+                    probes.given_up(seq);
+                }
+                failures += 1;
+                event::PingEvent::Failure {
+                    idx: cfg.idx,
+                    error,
+                }
+            }
+        };
+        // Break when the printer has exited -- no point continuing.
+        if tx.send(ev).await.is_err() {
+            return;
+        }
         seq = seq.wrapping_add(1);
+        sent += 1;
+    }
+}
+
+/// Report the reply to a probe that already timed out; a probe that never
+/// got one is already reported. Returns `false` once the printer has exited.
+async fn send_late(
+    cfg: &WorkerConfig,
+    probes: &mut SentProbes,
+    seq: u16,
+    result: Result<Duration, event::PingFailure>,
+    tx: &mpsc::Sender<event::PingEvent>,
+) -> bool {
+    let Ok(rtt) = result else {
+        probes.given_up(seq);
+        return true;
+    };
+    tx.send(probes.late(cfg.idx, seq, rtt)).await.is_ok()
+}
+
+/// Report a stray reply, unless its probe is too old to tell what it
+/// answers. Returns `false` once the printer has exited.
+async fn send_stray(
+    cfg: &WorkerConfig,
+    probes: &mut SentProbes,
+    stray: probe::Stray,
+    tx: &mpsc::Sender<event::PingEvent>,
+) -> bool {
+    match probes.stray(cfg.idx, stray) {
+        Some(ev) => tx.send(ev).await.is_ok(),
+        None => true,
+    }
+}
+
+async fn new_prober(
+    cfg: &WorkerConfig,
+    addr: std::net::IpAddr,
+    strays: &mpsc::Sender<probe::Stray>,
+) -> probe::Prober {
+    let mut prober = probe::Prober::new(
+        cfg.host.probe(),
+        addr,
+        &cfg.clients,
+        cfg.id,
+        cfg.timeout,
+        cfg.payload_len,
+        cfg.options,
+    )
+    .await;
+    prober.report_strays(strays.clone());
+    prober
+}
+
+/// What became of the recent probes of one host, by sequence number, so
+/// that replies can be told apart like ping(8) does.
+#[derive(Debug, Default)]
+struct SentProbes {
+    probes: HashMap<u16, SentProbe>,
+    /// The sequence numbers in `probes`, oldest first.
+    order: VecDeque<u16>,
+    /// Newest probe answered so far, in time or late.
+    newest_answered: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SentProbe {
+    at: Instant,
+    answered: bool,
+    /// The round trip ended without a reply.
+    given_up: bool,
+}
+
+impl SentProbes {
+    /// Remember that probe `seq` went out `at`, forgetting the oldest probe
+    /// once there are more than `SEQ_WINDOW`.
+    fn sent(&mut self, seq: u16, at: Instant) {
+        let probe = SentProbe {
+            at,
+            answered: false,
+            given_up: false,
+        };
+        if self.probes.insert(seq, probe).is_none() {
+            self.order.push_back(seq);
+        }
+        while self.order.len() > SEQ_WINDOW {
+            if let Some(oldest) = self.order.pop_front() {
+                self.probes.remove(&oldest);
+            }
+        }
+    }
+
+    /// Probe `seq` got its reply in time.
+    fn answered(&mut self, seq: u16) {
+        self.answer(seq);
+    }
+
+    /// Probe `seq` got its first reply after timing out.
+    fn late(&mut self, idx: types::HostIdx, seq: u16, rtt: Duration) -> event::PingEvent {
+        let reordered = self.answer(seq);
+        event::PingEvent::LateReply {
+            idx,
+            seq,
+            rtt,
+            reordered,
+        }
+    }
+
+    /// The round trip of probe `seq` ended without a reply.
+    fn given_up(&mut self, seq: u16) {
+        if let Some(probe) = self.probes.get_mut(&seq) {
+            probe.given_up = true;
+        }
+    }
+
+    /// The event for a reply no round trip waited for: the first reply to a
+    /// probe given up on is late, any other a duplicate. `None` for probes
+    /// too old to tell.
+    fn stray(&mut self, idx: types::HostIdx, stray: probe::Stray) -> Option<event::PingEvent> {
+        let probe = *self.probes.get(&stray.seq)?;
+        let rtt = stray.at.saturating_duration_since(probe.at);
+        Some(if probe.given_up && !probe.answered {
+            self.late(idx, stray.seq, rtt)
+        } else {
+            event::PingEvent::Duplicate {
+                idx,
+                seq: stray.seq,
+                rtt,
+            }
+        })
+    }
+
+    /// Mark probe `seq` answered; true when a newer probe was answered
+    /// before it.
+    fn answer(&mut self, seq: u16) -> bool {
+        if let Some(probe) = self.probes.get_mut(&seq) {
+            probe.answered = true;
+        }
+        let reordered = self
+            .newest_answered
+            .is_some_and(|newest| before(seq, newest));
+        if !reordered {
+            self.newest_answered = Some(seq);
+        }
+        reordered
+    }
+}
+
+/// Whether sequence number `a` was sent before `b`, allowing for wrap-around.
+fn before(a: u16, b: u16) -> bool {
+    let distance = b.wrapping_sub(a);
+    distance != 0 && distance < 0x8000
+}
+
+/// With `--mtu-discover`, binary-search the largest payload that reaches
+/// `addr` with don't-fragment set and emit the path MTU it implies. Only
+/// runs once the configured payload gets through; TCP probes have no
+/// payload to grow. Returns `false` once the printer has exited.
+async fn discover_mtu(
+    cfg: &WorkerConfig,
+    prober: &mut probe::Prober,
+    addr: std::net::IpAddr,
+    seq: &mut u16,
+    tx: &mpsc::Sender<event::PingEvent>,
+) -> bool {
+    if !cfg.mtu_discover || matches!(cfg.host.probe(), types::Probe::Tcp(_)) {
+        return true;
+    }
+    // IP header plus the ICMP or UDP header, both eight bytes.
+    let (overhead, max) = match addr {
+        std::net::IpAddr::V4(_) => (20 + 8, MAX_PAYLOAD_V4),
+        std::net::IpAddr::V6(_) => (40 + 8, MAX_PAYLOAD_V6),
+    };
+    let mut try_len = async |len: usize| {
+        prober.set_payload_len(len);
+        let result = prober.probe(*seq).await;
+        *seq = seq.wrapping_add(1);
+        result
+    };
+    // Invariant: `lo` bytes get through, more than `hi` don't.
+    let mut lo = cfg.payload_len;
+    let mut hi = max;
+    if try_len(lo).await.is_err() {
+        prober.set_payload_len(cfg.payload_len);
+        return true;
+    }
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        match try_len(mid).await {
+            Ok(_) => lo = mid,
+            Err(event::PingFailure::FragmentationNeeded { mtu: Some(mtu) })
+                if (mtu as usize) > overhead =>
+            {
+                hi = (mid - 1).min(mtu as usize - overhead).max(lo);
+            }
+            Err(_) => hi = mid - 1,
+        }
+    }
+    prober.set_payload_len(cfg.payload_len);
+    let ev = event::PingEvent::PathMtu {
+        idx: cfg.idx,
+        mtu: (lo + overhead) as u32,
+    };
+    tx.send(ev).await.is_ok()
+}
+
+/// Resolve the host, retrying with exponential backoff (starting at the ping
+/// interval) until it succeeds. Each failed attempt is counted in `sent`, so
+/// a host that never resolves still stops after `cfg.count` attempts. Returns
+/// `None` then, or once the printer has exited.
+async fn resolve_with_backoff(
+    cfg: &WorkerConfig,
+    sent: &mut u64,
+    tx: &mpsc::Sender<event::PingEvent>,
+) -> Option<std::net::IpAddr> {
+    let mut backoff = cfg.interval;
+    loop {
+        let ev = match cfg.host.resolve().await {
+            Ok(addr) => {
+                tx.send(event::PingEvent::Resolved { idx: cfg.idx, addr })
+                    .await
+                    .ok()?;
+                return Some(addr);
+            }
+            Err(error) => event::PingEvent::ResolutionFailed {
+                idx: cfg.idx,
+                error,
+            },
+        };
+        tx.send(ev).await.ok()?;
+        *sent += 1;
+        if cfg.count.is_some_and(|count| *sent >= count) {
+            return None;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RESOLVE_BACKOFF);
     }
 }
 
@@ -83,35 +435,76 @@ mod tests {
     use super::*;
 
     fn worker_cfg(host: &str) -> WorkerConfig {
+        let host = host.parse::<types::Hostname>().unwrap();
         WorkerConfig {
-            host: host.parse::<types::Hostname>().unwrap(),
+            clients: client::PingClients::new([(host.probe(), types::SocketOptions::default())])
+                .unwrap(),
+            host,
             idx: types::HostIdx::new(0),
             id: surge_ping::PingIdentifier(42),
-            clients: client::PingClients::new().unwrap(),
             interval: Duration::from_millis(100),
             timeout: Duration::from_millis(200),
+            payload_len: probe::DEFAULT_PAYLOAD_LEN,
+            options: types::SocketOptions::default(),
+            mtu_discover: false,
+            count: None,
+            deadline: None,
+            resolve_interval: Duration::from_secs(300),
+            resolve_after_failures: 3,
         }
     }
 
-    // Resolution failure: worker emits exactly one ResolutionFailed event, then exits
-    // (the sender drop closes the channel, confirming the task has returned).
+    // Resolution failure: worker keeps retrying, emitting one ResolutionFailed
+    // event per attempt, and exits once the receiver is gone.
     #[tokio::test]
-    async fn invalid_host_emits_resolution_failed_and_exits() {
+    async fn invalid_host_retries_resolution() {
         let (tx, mut rx) = mpsc::channel(8);
-        tokio::spawn(run_worker(
+        let worker = tokio::spawn(run_worker(
             worker_cfg("this.host.does.not.exist.invalid"),
             tx,
         ));
 
-        let msg = rx.recv().await.expect("expected at least one event");
-        assert!(
-            matches!(msg, event::PingEvent::ResolutionFailed { .. }),
-            "expected ResolutionFailed, got {msg:?}"
-        );
-        // Channel must drain: worker returned, all senders dropped.
+        for _ in 0..2 {
+            let msg = rx.recv().await.expect("expected a retry");
+            assert!(
+                matches!(msg, event::PingEvent::ResolutionFailed { .. }),
+                "expected ResolutionFailed, got {msg:?}"
+            );
+        }
+        drop(rx);
+        tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .expect("worker should exit once the receiver is dropped")
+            .unwrap();
+    }
+
+    // Count: failed resolutions use up the count like failed pings do.
+    #[tokio::test]
+    async fn failed_resolutions_count_towards_count() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let cfg = WorkerConfig {
+            count: Some(2),
+            interval: Duration::from_millis(10),
+            ..worker_cfg("this.host.does.not.exist.invalid")
+        };
+        tokio::spawn(run_worker(cfg, tx));
+
+        let mut events = Vec::new();
+        while let Some(ev) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("worker should exit after its count")
+        {
+            events.push(ev);
+        }
         assert!(
-            rx.recv().await.is_none(),
-            "worker should have exited after resolution failure"
+            matches!(
+                events[..],
+                [
+                    event::PingEvent::ResolutionFailed { .. },
+                    event::PingEvent::ResolutionFailed { .. }
+                ]
+            ),
+            "expected two failed resolutions, got {events:?}"
         );
     }
 
@@ -137,6 +530,48 @@ mod tests {
         );
     }
 
+    // Count: exactly `count` ping results follow the Resolved event, then the
+    // worker exits and the channel closes.
+    #[tokio::test]
+    async fn worker_stops_after_count() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let cfg = WorkerConfig {
+            count: Some(3),
+            ..worker_cfg("127.0.0.1")
+        };
+        tokio::spawn(run_worker(cfg, tx));
+
+        let mut events = Vec::new();
+        while let Some(ev) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("worker should exit after its count")
+        {
+            events.push(ev);
+        }
+        assert_eq!(
+            events.len(),
+            4,
+            "expected Resolved + 3 results, got {events:?}"
+        );
+    }
+
+    // Deadline: the worker exits at the deadline even without a count.
+    #[tokio::test]
+    async fn worker_stops_at_deadline() {
+        let (tx, mut rx) = mpsc::channel(64);
+        let cfg = WorkerConfig {
+            deadline: Some(tokio::time::Instant::now() + Duration::from_millis(300)),
+            ..worker_cfg("127.0.0.1")
+        };
+        let worker = tokio::spawn(run_worker(cfg, tx));
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .expect("worker should exit at the deadline")
+            .unwrap();
+    }
+
     // Backpressure / cancellation: when the receiver is dropped the worker must
     // detect the closed channel and exit rather than spin indefinitely.
     #[tokio::test]
@@ -152,4 +587,163 @@ mod tests {
         .await
         .expect("worker should exit after detecting closed channel");
     }
+
+    // A reply after the timeout is reported as late for the probe that
+    // already failed, never as a success.
+    #[tokio::test]
+    async fn late_replies_are_reported_after_the_timeout() {
+        let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            for delay in [300, 0, 0] {
+                let (n, from) = echo.recv_from(&mut buf).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                echo.send_to(&buf[..n], from).await.unwrap();
+            }
+        });
+        let (tx, mut rx) = mpsc::channel(16);
+        let cfg = WorkerConfig {
+            count: Some(3),
+            ..worker_cfg(&format!("udp://127.0.0.1:{port}"))
+        };
+        tokio::spawn(run_worker(cfg, tx));
+
+        let mut events = Vec::new();
+        while let Some(ev) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("worker should exit after its count")
+        {
+            events.push(ev);
+        }
+        let timed_out = events.iter().position(|ev| {
+            matches!(
+                ev,
+                event::PingEvent::Failure {
+                    error: event::PingFailure::Timeout { seq: 0 },
+                    ..
+                }
+            )
+        });
+        let late = events
+            .iter()
+            .position(|ev| matches!(ev, event::PingEvent::LateReply { seq: 0, .. }));
+        assert!(
+            matches!((timed_out, late), (Some(t), Some(l)) if t < l),
+            "expected a timeout then a late reply for seq 0, got {events:?}"
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|ev| matches!(ev, event::PingEvent::Success { seq: 0, .. })),
+            "{events:?}"
+        );
+    }
+
+    // Each datagram is echoed twice; the second echo is a duplicate of a
+    // probe already answered and doesn't count as another success.
+    #[tokio::test]
+    async fn duplicate_replies_are_reported() {
+        let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            loop {
+                let (n, from) = echo.recv_from(&mut buf).await.unwrap();
+                for _ in 0..2 {
+                    echo.send_to(&buf[..n], from).await.unwrap();
+                }
+            }
+        });
+        let (tx, mut rx) = mpsc::channel(16);
+        let cfg = WorkerConfig {
+            count: Some(3),
+            ..worker_cfg(&format!("udp://127.0.0.1:{port}"))
+        };
+        tokio::spawn(run_worker(cfg, tx));
+
+        let mut events = Vec::new();
+        while let Some(ev) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("worker should exit after its count")
+        {
+            events.push(ev);
+        }
+        let count = |seq: u16, duplicate: bool| {
+            events
+                .iter()
+                .filter(|ev| match ev {
+                    event::PingEvent::Success { seq: s, .. } => !duplicate && *s == seq,
+                    event::PingEvent::Duplicate { seq: s, .. } => duplicate && *s == seq,
+                    _ => false,
+                })
+                .count()
+        };
+        assert_eq!((count(0, false), count(0, true)), (1, 1), "{events:?}");
+    }
+
+    #[test]
+    fn tells_stray_replies_apart_by_sequence_number() {
+        let idx = types::HostIdx::new(0);
+        let start = Instant::now();
+        let stray = |seq, ms| probe::Stray {
+            seq,
+            at: start + Duration::from_millis(ms),
+        };
+        let mut probes = SentProbes::default();
+        for seq in 0..3 {
+            probes.sent(seq, start + Duration::from_millis(u64::from(seq) * 100));
+        }
+        probes.answered(0);
+        assert!(matches!(
+            probes.stray(idx, stray(0, 5)),
+            Some(event::PingEvent::Duplicate { seq: 0, rtt, .. }) if rtt == Duration::from_millis(5)
+        ));
+        // Probe 1 timed out and gave up before probe 2 was answered.
+        probes.given_up(1);
+        probes.answered(2);
+        assert!(matches!(
+            probes.stray(idx, stray(1, 900)),
+            Some(event::PingEvent::LateReply { seq: 1, reordered: true, rtt, .. })
+                if rtt == Duration::from_millis(800)
+        ));
+        assert!(matches!(
+            probes.stray(idx, stray(1, 901)),
+            Some(event::PingEvent::Duplicate { seq: 1, .. })
+        ));
+        assert!(probes.stray(idx, stray(7, 0)).is_none());
+    }
+
+    #[test]
+    fn late_replies_in_order_and_after_wrap_around() {
+        let idx = types::HostIdx::new(0);
+        let mut probes = SentProbes::default();
+        probes.sent(u16::MAX, Instant::now());
+        probes.sent(0, Instant::now());
+        probes.answered(u16::MAX);
+        assert!(matches!(
+            probes.late(idx, 0, Duration::ZERO),
+            event::PingEvent::LateReply {
+                reordered: false,
+                ..
+            }
+        ));
+        assert!(matches!(
+            probes.late(idx, u16::MAX - 1, Duration::ZERO),
+            event::PingEvent::LateReply {
+                reordered: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn forgets_probes_outside_the_window() {
+        let mut probes = SentProbes::default();
+        for seq in 0..=(SEQ_WINDOW as u16) {
+            probes.sent(seq, Instant::now());
+        }
+        assert_eq!(probes.probes.len(), SEQ_WINDOW);
+        assert!(!probes.probes.contains_key(&0));
+    }
 }
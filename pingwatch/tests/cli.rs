@@ -1,8 +1,10 @@
 mod support;
 
-use pingwatch::cli::Args;
+use pingwatch::cli::{Args, Command};
 use pingwatch::spinner_style::SpinnerStyle;
+use pingwatch::types::{Family, OutputMode, Probe, SocketOptions, SortOrder, SparklineScale};
 use rstest::rstest;
+use std::time::Duration;
 use support::IntegrationFixture;
 
 /// Call `Args::parse_from("pingwatch", argv)` with `XDG_CONFIG_HOME` pointing
@@ -17,13 +19,13 @@ fn parse_no_config(argv: &[&str]) -> anyhow::Result<Args> {
 // ---------------------------------------------------------------------------
 
 // 0 hosts from CLI with no config → rejected.
-// 1–10 from CLI → accepted.
-// 11 from CLI → rejected by clap's num_args upper bound.
+// Any positive number from CLI → accepted; the display scrolls.
 #[rstest]
 #[case(0, false)]
 #[case(1, true)]
 #[case(10, true)]
-#[case(11, false)]
+#[case(11, true)]
+#[case(200, true)]
 fn host_count_validation(#[case] count: usize, #[case] valid: bool) {
     let hosts: Vec<String> = (1..=count).map(|i| format!("host{i}")).collect();
     let argv: Vec<&str> = std::iter::once("pingwatch")
@@ -36,6 +38,39 @@ fn host_count_validation(#[case] count: usize, #[case] valid: bool) {
     );
 }
 
+// `-` reads hosts from stdin, alone or next to other hosts; hosts from the
+// config file are watched for changes instead.
+#[test]
+fn hosts_may_come_from_stdin() {
+    let args = parse_no_config(&["pingwatch", "-"]).unwrap();
+    assert!(args.hosts_from_stdin);
+    assert!(args.hosts.is_empty());
+    let args = parse_no_config(&["pingwatch", "a", "-"]).unwrap();
+    assert!(args.hosts_from_stdin);
+    assert_eq!(args.hosts.len(), 1);
+    assert!(args.watch.is_none());
+    assert!(
+        !parse_no_config(&["pingwatch", "a"])
+            .unwrap()
+            .hosts_from_stdin
+    );
+}
+
+#[test]
+fn only_hosts_from_the_config_file_are_watched() {
+    let fixture = IntegrationFixture::with_config("hosts = [\"a\"]\n");
+    let watch = fixture.parse(["pingwatch"]).unwrap().watch.unwrap();
+    assert_eq!(
+        watch.path(),
+        fixture.config_home().join("pingwatch/config.toml")
+    );
+    assert_eq!(watch.hosts().unwrap()[0].as_str(), "a");
+    fixture.write_config("hosts = [\"a\", \"b\"]\n");
+    assert_eq!(watch.hosts().unwrap().len(), 2);
+    assert!(fixture.parse(["pingwatch", "c"]).unwrap().watch.is_none());
+    assert!(fixture.parse(["pingwatch", "-"]).unwrap().watch.is_none());
+}
+
 // ---------------------------------------------------------------------------
 // Timing flags — clap-level parsing (no config)
 // ---------------------------------------------------------------------------
@@ -191,3 +226,557 @@ fn invalid_config_spinner_style_rejected() {
     let fixture = IntegrationFixture::with_config("spinner_style = \"not-a-style\"\n");
     assert!(fixture.parse(["pingwatch", "host"]).is_err());
 }
+
+// ---------------------------------------------------------------------------
+// Output mode — clap-level parsing and config precedence
+// ---------------------------------------------------------------------------
+
+// Explicit modes are kept as-is; `auto` is left for `run` to resolve against
+// stdout, here as if it weren't a terminal.
+#[rstest]
+#[case(&["pingwatch", "host"], OutputMode::Plain)]
+#[case(&["pingwatch", "--output", "tui", "host"], OutputMode::Tui)]
+#[case(&["pingwatch", "--output", "jsonl", "host"], OutputMode::Jsonl)]
+#[case(&["pingwatch", "-o", "csv", "host"], OutputMode::Csv)]
+fn output_mode_params(#[case] argv: &[&str], #[case] expected: OutputMode) {
+    let args = parse_no_config(argv).unwrap();
+    assert_eq!(args.output.resolve(false), expected);
+}
+
+#[test]
+fn config_output_mode_used_when_flag_absent() {
+    let fixture = IntegrationFixture::with_config("output = \"jsonl\"\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.output, OutputMode::Jsonl);
+}
+
+#[test]
+fn cli_output_mode_overrides_config() {
+    let fixture = IntegrationFixture::with_config("output = \"jsonl\"\n");
+    let args = fixture
+        .parse(["pingwatch", "--output", "csv", "host"])
+        .unwrap();
+    assert_eq!(args.output, OutputMode::Csv);
+}
+
+#[rstest]
+#[case(&["pingwatch", "--output", "xml", "host"])]
+fn invalid_output_mode_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Count and deadline — optional limits, CLI over config
+// ---------------------------------------------------------------------------
+
+#[rstest]
+#[case(&["pingwatch", "host"], None, None)]
+#[case(&["pingwatch", "-c", "5", "host"], Some(5), None)]
+#[case(&["pingwatch", "--deadline", "3000", "host"], None, Some(3000))]
+#[case(&["pingwatch", "--count", "1", "-w", "10", "host"], Some(1), Some(10))]
+fn count_and_deadline_params(
+    #[case] argv: &[&str],
+    #[case] count: Option<u64>,
+    #[case] deadline_ms: Option<u64>,
+) {
+    let args = parse_no_config(argv).unwrap();
+    assert_eq!(args.count, count);
+    assert_eq!(args.deadline, deadline_ms.map(Duration::from_millis));
+}
+
+#[rstest]
+#[case(&["pingwatch", "-c", "0", "host"])]
+#[case(&["pingwatch", "-w", "0", "host"])]
+#[case(&["pingwatch", "-c", "-1", "host"])]
+fn invalid_count_and_deadline_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+#[test]
+fn config_count_and_deadline_used_when_flags_absent() {
+    let fixture = IntegrationFixture::with_config("count = 3\ndeadline = 500\n");
+    let args = fixture.parse(["pingwatch", "-c", "4", "host"]).unwrap();
+    assert_eq!(args.count, Some(4));
+    assert_eq!(args.deadline, Some(Duration::from_millis(500)));
+}
+
+#[rstest]
+#[case("count = 0\n")]
+#[case("deadline = 0\n")]
+fn invalid_config_count_and_deadline_rejected(#[case] config: &str) {
+    let fixture = IntegrationFixture::with_config(config);
+    assert!(fixture.parse(["pingwatch", "host"]).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Sparkline — optional width, scale from CLI or config
+// ---------------------------------------------------------------------------
+
+#[rstest]
+#[case(&["pingwatch", "host"], None, SparklineScale::Host)]
+#[case(&["pingwatch", "--sparkline", "20", "host"], Some(20), SparklineScale::Host)]
+#[case(
+    &["pingwatch", "--sparkline", "5", "--sparkline-scale", "global", "host"],
+    Some(5),
+    SparklineScale::Global
+)]
+fn sparkline_params(
+    #[case] argv: &[&str],
+    #[case] width: Option<usize>,
+    #[case] scale: SparklineScale,
+) {
+    let args = parse_no_config(argv).unwrap();
+    assert_eq!(args.sparkline, width);
+    assert_eq!(args.sparkline_scale, scale);
+}
+
+#[rstest]
+#[case(&["pingwatch", "--sparkline", "0", "host"])]
+#[case(&["pingwatch", "--sparkline-scale", "local", "host"])]
+fn invalid_sparkline_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+#[test]
+fn display_layout_defaults_and_overrides() {
+    let args = parse_no_config(&["pingwatch", "host"]).unwrap();
+    assert_eq!(args.sort, SortOrder::None);
+    assert!(!args.compact);
+    assert!(!args.path);
+    assert!(parse_no_config(&["pingwatch", "--sort", "random", "host"]).is_err());
+    assert!(
+        parse_no_config(&["pingwatch", "--path", "host"])
+            .unwrap()
+            .path
+    );
+
+    let fixture = IntegrationFixture::with_config("sort = \"loss\"\ncompact = true\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.sort, SortOrder::Loss);
+    assert!(args.compact);
+    let args = fixture
+        .parse(["pingwatch", "--sort", "latency", "host"])
+        .unwrap();
+    assert_eq!(args.sort, SortOrder::Latency);
+}
+
+#[test]
+fn config_sparkline_used_when_flags_absent() {
+    let fixture = IntegrationFixture::with_config("sparkline = 30\nsparkline_scale = \"global\"\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.sparkline, Some(30));
+    assert_eq!(args.sparkline_scale, SparklineScale::Global);
+}
+
+#[test]
+fn invalid_config_sparkline_rejected() {
+    let fixture = IntegrationFixture::with_config("sparkline = 0\n");
+    assert!(fixture.parse(["pingwatch", "host"]).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Probe kinds — part of each host string
+// ---------------------------------------------------------------------------
+
+#[test]
+fn hosts_may_pick_their_probe() {
+    let args = parse_no_config(&["pingwatch", "db", "tcp://db:5432", "udp://ntp:123"]).unwrap();
+    let probes: Vec<_> = args.hosts.iter().map(|h| h.probe()).collect();
+    assert_eq!(probes, [Probe::Icmp, Probe::Tcp(5432), Probe::Udp(123)]);
+}
+
+#[rstest]
+#[case(&["pingwatch", "tcp://db"])]
+#[case(&["pingwatch", "ftp://db:21"])]
+fn malformed_probes_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+#[test]
+fn malformed_config_probe_rejected() {
+    let fixture = IntegrationFixture::with_config("hosts = [\"udp://ntp\"]\n");
+    assert!(fixture.parse(["pingwatch"]).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Alerts — thresholds and actions from CLI or config
+// ---------------------------------------------------------------------------
+
+#[test]
+fn alert_defaults() {
+    let args = parse_no_config(&["pingwatch", "host"]).unwrap();
+    assert_eq!(args.alert_down_after, 3);
+    assert_eq!(args.alert_slow_ms, None);
+    assert_eq!(args.alert_cooldown, Duration::from_secs(30));
+    assert!(!args.alert_bell);
+    assert_eq!(args.alert_exec, None);
+}
+
+#[test]
+fn cli_alert_flags_override_config() {
+    let fixture = IntegrationFixture::with_config(
+        "alert_down_after = 5\nalert_slow_ms = 200\nalert_bell = true\nalert_exec = \"true\"\n",
+    );
+    let args = fixture
+        .parse([
+            "pingwatch",
+            "--alert-down-after",
+            "2",
+            "--alert-notify",
+            "notify-send pingwatch",
+            "host",
+        ])
+        .unwrap();
+    assert_eq!(args.alert_down_after, 2);
+    assert_eq!(args.alert_slow_ms, Some(Duration::from_millis(200)));
+    assert!(args.alert_bell);
+    assert_eq!(args.alert_notify.as_deref(), Some("notify-send pingwatch"));
+    assert_eq!(args.alert_exec.as_deref(), Some("true"));
+}
+
+#[rstest]
+#[case(&["pingwatch", "--no-alert-bell", "host"], false)]
+#[case(&["pingwatch", "--no-alert-bell", "--alert-bell", "host"], true)]
+#[case(&["pingwatch", "--alert-bell", "--no-alert-bell", "host"], false)]
+fn alert_bell_can_be_turned_off_from_the_cli(#[case] argv: &[&str], #[case] bell: bool) {
+    let fixture = IntegrationFixture::with_config("alert_bell = true\n");
+    assert_eq!(
+        fixture.parse(argv.iter().copied()).unwrap().alert_bell,
+        bell
+    );
+}
+
+#[rstest]
+#[case(&["pingwatch", "--alert-down-after", "0", "host"])]
+#[case(&["pingwatch", "--alert-slow-ms", "0", "host"])]
+#[case(&["pingwatch", "--alert-cooldown", "soon", "host"])]
+fn invalid_alert_flags_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Re-resolution — TTL and failure threshold
+// ---------------------------------------------------------------------------
+
+#[rstest]
+#[case(&["pingwatch", "host"], 300_000, 3)]
+#[case(&["pingwatch", "--resolve-interval", "60000", "host"], 60_000, 3)]
+#[case(&["pingwatch", "--resolve-after-failures", "1", "host"], 300_000, 1)]
+fn resolve_params(#[case] argv: &[&str], #[case] interval_ms: u64, #[case] after: u32) {
+    let args = parse_no_config(argv).unwrap();
+    assert_eq!(args.resolve_interval, Duration::from_millis(interval_ms));
+    assert_eq!(args.resolve_after_failures, after);
+}
+
+#[test]
+fn config_resolve_settings_used_when_flags_absent() {
+    let fixture =
+        IntegrationFixture::with_config("resolve_interval = 5000\nresolve_after_failures = 10\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.resolve_interval, Duration::from_secs(5));
+    assert_eq!(args.resolve_after_failures, 10);
+}
+
+#[rstest]
+#[case(&["pingwatch", "--resolve-interval", "0", "host"])]
+#[case(&["pingwatch", "--resolve-after-failures", "0", "host"])]
+fn invalid_resolve_settings_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Address families — -4/-6, per-host config and --all-addresses
+// ---------------------------------------------------------------------------
+
+#[rstest]
+#[case(&["pingwatch", "a", "b"], None)]
+#[case(&["pingwatch", "-4", "a", "b"], Some(Family::Ipv4))]
+#[case(&["pingwatch", "--ipv6", "a", "b"], Some(Family::Ipv6))]
+fn family_flags_apply_to_every_host(#[case] argv: &[&str], #[case] family: Option<Family>) {
+    let args = parse_no_config(argv).unwrap();
+    assert!(args.hosts.iter().all(|h| h.family() == family));
+}
+
+#[test]
+fn family_flags_conflict() {
+    assert!(parse_no_config(&["pingwatch", "-4", "-6", "host"]).is_err());
+}
+
+#[test]
+fn config_hosts_may_pick_their_family() {
+    let fixture = IntegrationFixture::with_config(
+        "hosts = [\"a\", { host = \"b\", family = \"ipv6\" }]\nall_addresses = true\n",
+    );
+    let args = fixture.parse(["pingwatch"]).unwrap();
+    let families: Vec<_> = args.hosts.iter().map(|h| h.family()).collect();
+    assert_eq!(families, [None, Some(Family::Ipv6)]);
+    assert!(args.all_addresses);
+
+    // The command line wins over the per-host setting.
+    let args = fixture.parse(["pingwatch", "-4"]).unwrap();
+    assert!(args.hosts.iter().all(|h| h.family() == Some(Family::Ipv4)));
+}
+
+#[rstest]
+#[case("hosts = [{ host = \"b\", family = \"ipx\" }]\n")]
+#[case("hosts = [{ host = \"b\", port = 22 }]\n")]
+fn malformed_config_host_tables_rejected(#[case] config: &str) {
+    let fixture = IntegrationFixture::with_config(config);
+    assert!(fixture.parse(["pingwatch"]).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Host tables and profiles — richer config entries
+// ---------------------------------------------------------------------------
+
+#[test]
+fn config_host_tables_carry_per_host_settings() {
+    let fixture = IntegrationFixture::with_config(
+        r#"
+hosts = [
+    { name = "db.example.com", address = "10.0.0.5", label = "db", group = "backend",
+      interval = 5000, timeout = 1000, payload_size = 56, probe = "tcp:5432" },
+    { name = "::1", probe = "udp:7" },
+]
+"#,
+    );
+    let args = fixture.parse(["pingwatch"]).unwrap();
+    // Ungrouped hosts are listed before groups.
+    assert_eq!(args.hosts[0].as_str(), "udp://[::1]:7");
+    let db = &args.hosts[1];
+    assert_eq!(db.as_str(), "tcp://db.example.com:5432");
+    assert_eq!(db.probe(), Probe::Tcp(5432));
+    assert_eq!(db.pinned(), Some("10.0.0.5".parse().unwrap()));
+    assert_eq!(db.display_name(), "db");
+    assert_eq!(db.group(), Some("backend"));
+    assert_eq!(db.interval(), Some(Duration::from_secs(5)));
+    assert_eq!(db.timeout(), Some(Duration::from_secs(1)));
+    assert_eq!(db.payload_size(), Some(56));
+    // Global settings are untouched by per-host ones.
+    assert_eq!(args.interval, Duration::from_secs(1));
+}
+
+#[rstest]
+#[case("hosts = [{ name = \"db\", interval = 0 }]\n")]
+#[case("hosts = [{ name = \"tcp://db:1\", probe = \"icmp\" }]\n")]
+#[case("hosts = [{ name = \"db\", probe = \"tcp\" }]\n")]
+#[case("hosts = [{ name = \"db\", address = \"db.example.com\" }]\n")]
+#[case("hosts = [{ label = \"db\" }]\n")]
+fn malformed_config_host_settings_rejected(#[case] config: &str) {
+    let fixture = IntegrationFixture::with_config(config);
+    assert!(fixture.parse(["pingwatch"]).is_err());
+}
+
+#[test]
+fn config_hosts_are_sorted_into_groups() {
+    let fixture = IntegrationFixture::with_config(
+        r#"
+hosts = [
+    { name = "a", group = "x" },
+    "b",
+    { name = "c", group = "y" },
+    { name = "d", group = "x" },
+    "e",
+]
+"#,
+    );
+    let args = fixture.parse(["pingwatch"]).unwrap();
+    let names: Vec<_> = args.hosts.iter().map(|h| h.as_str()).collect();
+    assert_eq!(names, ["b", "e", "a", "d", "c"]);
+}
+
+const PROFILES: &str = r#"
+hosts = ["home"]
+interval = 500
+
+[profiles.work]
+hosts = ["vpn", "intranet"]
+timeout = 3000
+
+[profiles.empty]
+"#;
+
+#[test]
+fn profile_keys_replace_top_level_ones() {
+    let fixture = IntegrationFixture::with_config(PROFILES);
+    let args = fixture.parse(["pingwatch", "--profile", "work"]).unwrap();
+    let names: Vec<_> = args.hosts.iter().map(|h| h.as_str()).collect();
+    assert_eq!(names, ["vpn", "intranet"]);
+    assert_eq!(args.interval, Duration::from_millis(500));
+    assert_eq!(args.timeout, Duration::from_secs(3));
+    assert_eq!(args.profile.as_deref(), Some("work"));
+
+    let args = fixture.parse(["pingwatch", "--profile", "empty"]).unwrap();
+    assert_eq!(args.hosts[0].as_str(), "home");
+
+    let args = fixture.parse(["pingwatch"]).unwrap();
+    assert_eq!(args.hosts[0].as_str(), "home");
+    assert_eq!(args.timeout, Duration::from_secs(2));
+}
+
+#[test]
+fn cli_overrides_profile() {
+    let fixture = IntegrationFixture::with_config(PROFILES);
+    let args = fixture
+        .parse(["pingwatch", "--profile", "work", "-t", "100", "other"])
+        .unwrap();
+    assert_eq!(args.hosts[0].as_str(), "other");
+    assert_eq!(args.timeout, Duration::from_millis(100));
+}
+
+#[test]
+fn unknown_profile_rejected() {
+    let fixture = IntegrationFixture::with_config(PROFILES);
+    let Err(err) = fixture.parse(["pingwatch", "--profile", "play"]) else {
+        panic!("unknown profile accepted");
+    };
+    assert!(err.to_string().contains("empty, work"), "{err}");
+}
+
+// ---------------------------------------------------------------------------
+// Recording and replay — --record FILE and the `replay` subcommand
+// ---------------------------------------------------------------------------
+
+#[test]
+fn record_takes_a_file() {
+    let args = parse_no_config(&["pingwatch", "--record", "session.rec", "host"]).unwrap();
+    assert_eq!(args.record.as_deref(), Some("session.rec".as_ref()));
+    assert!(args.command.is_none());
+}
+
+// A replay takes its hosts from the recording, so none are required.
+#[rstest]
+#[case(&["pingwatch", "replay", "session.rec"], 1.0, false)]
+#[case(&["pingwatch", "replay", "--speed", "4", "session.rec"], 4.0, false)]
+#[case(&["pingwatch", "--sort", "loss", "replay", "--summary", "session.rec"], 1.0, true)]
+fn replay_params(#[case] argv: &[&str], #[case] speed: f64, #[case] summary: bool) {
+    let args = parse_no_config(argv).unwrap();
+    let Some(Command::Replay(replay)) = args.command else {
+        panic!("not a replay");
+    };
+    assert_eq!(replay.file.to_str(), Some("session.rec"));
+    assert_eq!(replay.speed, speed);
+    assert_eq!(replay.summary, summary);
+}
+
+#[rstest]
+#[case(&["pingwatch", "replay"])]
+#[case(&["pingwatch", "replay", "--speed", "0", "session.rec"])]
+#[case(&["pingwatch", "replay", "--speed", "-2", "session.rec"])]
+#[case(&["pingwatch", "replay", "--speed", "inf", "session.rec"])]
+fn invalid_replay_args_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Metrics exporter — --metrics-listen from CLI or config
+// ---------------------------------------------------------------------------
+
+#[test]
+fn metrics_listen_from_cli_or_config() {
+    let args = parse_no_config(&["pingwatch", "host"]).unwrap();
+    assert_eq!(args.metrics_listen, None);
+    assert!(parse_no_config(&["pingwatch", "--metrics-listen", "localhost", "host"]).is_err());
+
+    let fixture = IntegrationFixture::with_config("metrics_listen = \"127.0.0.1:9898\"\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.metrics_listen, Some("127.0.0.1:9898".parse().unwrap()));
+    let args = fixture
+        .parse(["pingwatch", "--metrics-listen", "[::1]:9000", "host"])
+        .unwrap();
+    assert_eq!(args.metrics_listen, Some("[::1]:9000".parse().unwrap()));
+}
+
+// ---------------------------------------------------------------------------
+// Probe packets — size, TTL, TOS/DSCP, don't-fragment and MTU discovery
+// ---------------------------------------------------------------------------
+
+#[rstest]
+#[case(&["pingwatch", "host"], SocketOptions::default())]
+#[case(&["pingwatch", "--ttl", "5", "--tos", "16", "host"], SocketOptions { ttl: Some(5), tos: Some(16), dont_fragment: false })]
+#[case(&["pingwatch", "--dscp", "46", "--dont-fragment", "host"], SocketOptions { ttl: None, tos: Some(184), dont_fragment: true })]
+fn socket_options_from_cli(#[case] argv: &[&str], #[case] options: SocketOptions) {
+    assert_eq!(parse_no_config(argv).unwrap().socket_options(), options);
+}
+
+#[rstest]
+#[case(&["pingwatch", "--ttl", "0", "host"])]
+#[case(&["pingwatch", "--ttl", "256", "host"])]
+#[case(&["pingwatch", "--dscp", "64", "host"])]
+#[case(&["pingwatch", "--tos", "16", "--dscp", "4", "host"])]
+#[case(&["pingwatch", "--size", "65536", "host"])]
+fn invalid_packet_args_rejected(#[case] argv: &[&str]) {
+    assert!(parse_no_config(argv).is_err());
+}
+
+#[test]
+fn packet_settings_from_config() {
+    let fixture =
+        IntegrationFixture::with_config("size = 1400\nttl = 32\ndscp = 10\nmtu_discover = true\n");
+    let args = fixture.parse(["pingwatch", "host"]).unwrap();
+    assert_eq!(args.size, Some(1400));
+    assert!(args.mtu_discover);
+    assert_eq!(
+        args.socket_options(),
+        SocketOptions {
+            ttl: Some(32),
+            tos: Some(40),
+            dont_fragment: false
+        }
+    );
+
+    // Either of --tos and --dscp replaces both config keys.
+    let args = fixture.parse(["pingwatch", "--tos", "8", "host"]).unwrap();
+    assert_eq!(args.socket_options().tos, Some(8));
+}
+
+#[rstest]
+#[case("ttl = 0\n")]
+#[case("dscp = 64\n")]
+#[case("tos = 8\ndscp = 2\n")]
+fn invalid_packet_config_rejected(#[case] config: &str) {
+    let fixture = IntegrationFixture::with_config(config);
+    assert!(fixture.parse(["pingwatch", "host"]).is_err());
+}
+
+#[test]
+fn config_hosts_override_packet_settings() {
+    let fixture = IntegrationFixture::with_config(
+        r#"
+ttl = 64
+dont_fragment = true
+hosts = [
+    { name = "a", size = 100, dscp = 46, dont_fragment = false },
+    { name = "b", ttl = 3, tos = 4 },
+]
+"#,
+    );
+    let args = fixture.parse(["pingwatch"]).unwrap();
+    let defaults = args.socket_options();
+    assert_eq!(args.hosts[0].payload_size(), Some(100));
+    assert_eq!(
+        args.hosts[0].socket_options(defaults),
+        SocketOptions {
+            ttl: Some(64),
+            tos: Some(184),
+            dont_fragment: false
+        }
+    );
+    assert_eq!(
+        args.hosts[1].socket_options(defaults),
+        SocketOptions {
+            ttl: Some(3),
+            tos: Some(4),
+            dont_fragment: true
+        }
+    );
+}
+
+#[rstest]
+#[case("hosts = [{ name = \"db\", ttl = 0 }]\n")]
+#[case("hosts = [{ name = \"db\", dscp = 64 }]\n")]
+#[case("hosts = [{ name = \"db\", tos = 4, dscp = 1 }]\n")]
+fn malformed_host_packet_settings_rejected(#[case] config: &str) {
+    let fixture = IntegrationFixture::with_config(config);
+    assert!(fixture.parse(["pingwatch"]).is_err());
+}
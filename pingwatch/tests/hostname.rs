@@ -40,3 +40,15 @@ fn string_roundtrip(#[case] input: &str) {
     assert_eq!(h.to_string(), input);
     assert_eq!(h.as_str(), input);
 }
+
+// Probe schemes and ports are stripped before resolution.
+#[rstest]
+#[case("tcp://127.0.0.1:22", "127.0.0.1")]
+#[case("udp://[::1]:7", "::1")]
+#[case("icmp-dgram://127.0.0.1", "127.0.0.1")]
+#[tokio::test]
+async fn probe_prefix_is_not_resolved(#[case] input: &str, #[case] expected: &str) {
+    let h: Hostname = input.parse().unwrap();
+    assert_eq!(h.resolve().await.unwrap().to_string(), expected);
+    assert_eq!(h.as_str(), input);
+}
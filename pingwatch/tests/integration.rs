@@ -2,20 +2,27 @@ mod support;
 
 use std::time::Duration;
 
+use pingwatch::types::HostIdx;
 use support::{FixtureConfig, IntegrationFixture};
 
 const NXDOMAIN_HOST: &str = "this.host.does.not.exist.invalid";
 const NXDOMAIN_HOST_2: &str = "this.host.also.does.not.exist.invalid";
 
+// Unresolvable hosts are retried until their count is used up.
 #[tokio::test(flavor = "current_thread")]
 async fn run_exits_when_cli_host_fails_resolution() {
     let fixture = IntegrationFixture::new();
-    fixture.run(["pingwatch", NXDOMAIN_HOST]).await.unwrap();
+    let stats = fixture
+        .run(["pingwatch", "-c", "1", NXDOMAIN_HOST])
+        .await
+        .unwrap();
+    assert!(stats.any_host_unreachable());
 }
 
 #[tokio::test(flavor = "current_thread")]
 async fn run_exits_when_config_supplies_host() {
-    let fixture = IntegrationFixture::with_config(&format!("hosts = [\"{NXDOMAIN_HOST}\"]\n"));
+    let fixture =
+        IntegrationFixture::with_config(&format!("hosts = [\"{NXDOMAIN_HOST}\"]\ncount = 1\n"));
     fixture.run(["pingwatch"]).await.unwrap();
 }
 
@@ -23,7 +30,7 @@ async fn run_exits_when_config_supplies_host() {
 async fn run_exits_when_multiple_hosts_fail_resolution() {
     let fixture = IntegrationFixture::new();
     fixture
-        .run(["pingwatch", NXDOMAIN_HOST, NXDOMAIN_HOST_2])
+        .run(["pingwatch", "-c", "1", NXDOMAIN_HOST, NXDOMAIN_HOST_2])
         .await
         .unwrap();
 }
@@ -32,7 +39,7 @@ async fn run_exits_when_multiple_hosts_fail_resolution() {
 async fn fixture_configures_timing_and_config_home_for_runtime() {
     let fixture = IntegrationFixture::from_config(FixtureConfig {
         config_toml: Some(&format!(
-            "hosts = [\"{NXDOMAIN_HOST}\"]\ninterval = 25\ntimeout = 50\n"
+            "hosts = [\"{NXDOMAIN_HOST}\"]\ninterval = 25\ntimeout = 50\ncount = 1\n"
         )),
     });
 
@@ -46,3 +53,268 @@ async fn fixture_configures_timing_and_config_home_for_runtime() {
 
     pingwatch::run(args).await.unwrap();
 }
+
+#[tokio::test(flavor = "current_thread")]
+async fn run_exits_after_count_pings_per_host() {
+    let fixture = IntegrationFixture::new();
+    let stats = fixture
+        .run([
+            "pingwatch",
+            "-c",
+            "2",
+            "-i",
+            "10",
+            "-t",
+            "100",
+            "127.0.0.1",
+            "::1",
+        ])
+        .await
+        .unwrap();
+    for i in 0..2 {
+        let host = stats.host(HostIdx::new(i)).unwrap();
+        assert_eq!(host.transmitted, 2);
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn all_addresses_pins_one_host_per_address() {
+    let fixture = IntegrationFixture::new();
+    let stats = fixture
+        .run([
+            "pingwatch",
+            "--all-addresses",
+            "-4",
+            "-c",
+            "1",
+            "-t",
+            "100",
+            "localhost",
+        ])
+        .await
+        .unwrap();
+    assert_eq!(stats.host(HostIdx::new(0)).unwrap().transmitted, 1);
+    assert!(
+        stats
+            .to_string()
+            .contains("--- localhost (127.0.0.1) ping statistics ---")
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn run_exits_at_deadline() {
+    let fixture = IntegrationFixture::new();
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        fixture.run(["pingwatch", "-w", "200", "-i", "10", "127.0.0.1"]),
+    )
+    .await
+    .expect("run should return at the deadline")
+    .unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn alert_hook_runs_when_a_host_goes_down() {
+    let fixture = IntegrationFixture::new();
+    let out = fixture.config_home().join("alert.txt");
+    let hook = format!(
+        "echo \"$PINGWATCH_HOST $PINGWATCH_STATE\" > '{}'",
+        out.display()
+    );
+    // Nothing listens on port 1, so every probe is refused.
+    fixture
+        .run([
+            "pingwatch",
+            "-c",
+            "2",
+            "-i",
+            "10",
+            "--alert-down-after",
+            "2",
+            "--alert-exec",
+            &hook,
+            "tcp://127.0.0.1:1",
+        ])
+        .await
+        .unwrap();
+
+    // The hook may have created the file without having written to it yet.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let read = || std::fs::read_to_string(&out).unwrap_or_default();
+    while !read().ends_with('\n') && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(read().trim(), "tcp://127.0.0.1:1 down");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn alert_notify_gets_the_host_name_as_an_argument_not_a_shell_word() {
+    let fixture = IntegrationFixture::new();
+    let out = fixture.config_home().join("notified.txt");
+    let pwned = fixture.config_home().join("pwned");
+    let script = fixture.config_home().join("notify.sh");
+    std::fs::write(
+        &script,
+        format!("printf '%s\\n' \"$1\" > '{}'\n", out.display()),
+    )
+    .unwrap();
+    let name = format!("x;touch {}", pwned.display());
+    fixture.write_config(&format!(
+        "hosts = [{{ name = \"{name}\", address = \"127.0.0.1\", probe = \"tcp:1\" }}]\n"
+    ));
+    let notify = format!("sh {}", script.display());
+    fixture
+        .run([
+            "pingwatch",
+            "-c",
+            "2",
+            "-i",
+            "10",
+            "--alert-down-after",
+            "2",
+            "--alert-notify",
+            &notify,
+        ])
+        .await
+        .unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let read = || std::fs::read_to_string(&out).unwrap_or_default();
+    while !read().ends_with('\n') && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(read().ends_with(" is down\n"), "{}", read());
+    assert!(read().contains(&name), "{}", read());
+    assert!(!pwned.exists());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn replay_summarises_a_recorded_session() {
+    let fixture = IntegrationFixture::new();
+    let recording = fixture.config_home().join("session.rec");
+    let recording = recording.to_str().unwrap();
+    // Nothing listens on port 1, so every probe is refused.
+    let recorded = fixture
+        .run([
+            "pingwatch",
+            "-c",
+            "2",
+            "-i",
+            "10",
+            "--record",
+            recording,
+            "127.0.0.1",
+            "tcp://127.0.0.1:1",
+        ])
+        .await
+        .unwrap();
+
+    let replayed = fixture
+        .run(["pingwatch", "replay", "--summary", recording])
+        .await
+        .unwrap();
+    // RTTs are recorded to the microsecond, so compare counts only.
+    for i in 0..2 {
+        let (recorded, replayed) = (
+            recorded.host(HostIdx::new(i)).unwrap(),
+            replayed.host(HostIdx::new(i)).unwrap(),
+        );
+        assert_eq!(replayed.transmitted, recorded.transmitted);
+        assert_eq!(replayed.received, recorded.received);
+    }
+    assert_eq!(replayed.host(HostIdx::new(1)).unwrap().transmitted, 2);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn metrics_are_served_while_running() {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let fixture = IntegrationFixture::new();
+    // Borrow a free port from the OS for the exporter.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let run = fixture.run([
+        "pingwatch",
+        "-c",
+        "20",
+        "-i",
+        "50",
+        "--metrics-listen",
+        &addr,
+        "tcp://127.0.0.1:1",
+    ]);
+    let scrape = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+    let (stats, response) = tokio::join!(run, scrape);
+    stats.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(
+        response.contains("pingwatch_up{host=\"tcp://127.0.0.1:1\"} 0\n"),
+        "{response}"
+    );
+    assert!(response.contains("pingwatch_lost_total{host=\"tcp://127.0.0.1:1\"} "));
+    assert!(response.ends_with("# EOF\n"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn mtu_discovery_finds_the_loopback_limit() {
+    let fixture = IntegrationFixture::new();
+    let echo = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let host = format!("udp://{}", echo.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; 65_536];
+        while let Ok((n, from)) = echo.recv_from(&mut buf) {
+            let _ = echo.send_to(&buf[..n], from);
+        }
+    });
+
+    let stats = fixture
+        .run(["pingwatch", "-c", "1", "--mtu-discover", &host])
+        .await
+        .unwrap();
+    let host = stats.host(HostIdx::new(0)).unwrap();
+    // Loopback carries the largest IPv4 datagram, 65535 bytes.
+    assert_eq!(host.path_mtu, Some(65_535));
+    assert_eq!(host.transmitted, 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn config_edits_add_and_remove_hosts_while_running() {
+    let fixture = IntegrationFixture::with_config(
+        "hosts = [\"tcp://127.0.0.1:1\"]\ninterval = 20\ndeadline = 2000\n",
+    );
+    let run = fixture.run(["pingwatch"]);
+    let edit = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        fixture.write_config("hosts = [\"tcp://127.0.0.1:2\"]\ninterval = 20\n");
+    };
+    let (stats, ()) = tokio::join!(run, edit);
+    let stats = stats.unwrap();
+
+    assert!(stats.removed(HostIdx::new(0)));
+    let added = stats.host(HostIdx::new(1)).unwrap();
+    assert!(added.transmitted > 0);
+    assert!(!stats.removed(HostIdx::new(1)));
+    let summary = stats.to_string();
+    assert!(
+        summary.contains("--- tcp://127.0.0.1:1 ping statistics (removed) ---"),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("--- tcp://127.0.0.1:2 ping statistics ---"),
+        "{summary}"
+    );
+}
@@ -72,7 +72,7 @@ impl IntegrationFixture {
     }
 
     #[allow(dead_code)]
-    pub async fn run<I, T>(&self, argv: I) -> anyhow::Result<()>
+    pub async fn run<I, T>(&self, argv: I) -> anyhow::Result<pingwatch::stats::Stats>
     where
         I: IntoIterator<Item = T>,
         T: Into<OsString> + Clone,